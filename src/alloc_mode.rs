@@ -1,5 +1,6 @@
 //Wrapper around wgpu::BufferUsages
 bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     #[repr(transparent)]
     pub struct AllocMode: u32 {
         const MAP_READ = 1 << 0;
@@ -35,3 +36,28 @@ impl From<AllocMode> for wgpu::BufferUsages {
         flags
     }
 }
+
+impl From<wgpu::BufferUsages> for AllocMode {
+    fn from(value: wgpu::BufferUsages) -> Self {
+        let mut flags = Self::empty();
+        if value.contains(wgpu::BufferUsages::MAP_READ) {
+            flags |= Self::MAP_READ;
+        }
+        if value.contains(wgpu::BufferUsages::MAP_WRITE) {
+            flags |= Self::MAP_WRITE;
+        }
+        if value.contains(wgpu::BufferUsages::COPY_SRC) {
+            flags |= Self::COPY_SRC;
+        }
+        if value.contains(wgpu::BufferUsages::COPY_DST) {
+            flags |= Self::COPY_DST;
+        }
+        if value.contains(wgpu::BufferUsages::UNIFORM) {
+            flags |= Self::UNIFORM;
+        }
+        if value.contains(wgpu::BufferUsages::STORAGE) {
+            flags |= Self::STORAGE;
+        }
+        flags
+    }
+}
@@ -0,0 +1,339 @@
+use crate::{
+    AllocMode, CPUPrim, Device, DeviceAllocator, DeviceError, DeviceId, DevicePrimitive, Storage,
+    Tensor, TensorError, WebGPU, CPU,
+};
+use std::alloc::Layout;
+use std::sync::Arc;
+
+///A device selected at runtime, e.g from a configuration flag.
+///Dispatches every [`Device`] method to the device it wraps, so `Tensor<AnyDevice>` can be
+///passed around without knowing where it lives.
+///Operations are implemented on the statically typed tensors, see [`Tensor::into_cpu`] and
+///[`Tensor::into_webgpu`] to recover them.
+#[derive(Debug, Clone)]
+pub enum AnyDevice {
+    CPU(CPU),
+    WebGPU(Arc<WebGPU>),
+}
+
+///Memory allocated by an [`AnyDevice`], tagged with the kind of device it belongs to.
+#[derive(Debug)]
+pub enum AnyPrim {
+    CPU(CPUPrim),
+    WebGPU(wgpu::Buffer),
+}
+
+impl AnyDevice {
+    ///Creates the device called `name`, either `cpu` or `webgpu` (alias `gpu`), ignoring case.
+    pub async fn from_name(name: &str) -> Result<Self, DeviceError> {
+        match name.to_ascii_lowercase().as_str() {
+            "cpu" => Ok(Self::CPU(CPU)),
+            "webgpu" | "gpu" => Ok(Self::WebGPU(Arc::new(WebGPU::new().await?))),
+            _ => Err(DeviceError::ResourceError(anyhow::anyhow!(
+                "Unknown device: {}",
+                name
+            ))),
+        }
+    }
+
+    ///The kind of the wrapped device.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CPU(_) => "CPU",
+            Self::WebGPU(_) => "WebGPU",
+        }
+    }
+}
+
+impl From<CPU> for AnyDevice {
+    fn from(device: CPU) -> Self {
+        Self::CPU(device)
+    }
+}
+
+impl From<WebGPU> for AnyDevice {
+    fn from(device: WebGPU) -> Self {
+        Self::WebGPU(Arc::new(device))
+    }
+}
+
+impl From<Arc<WebGPU>> for AnyDevice {
+    fn from(device: Arc<WebGPU>) -> Self {
+        Self::WebGPU(device)
+    }
+}
+
+impl AnyPrim {
+    ///The kind of device the memory belongs to.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CPU(_) => "CPU",
+            Self::WebGPU(_) => "WebGPU",
+        }
+    }
+}
+
+impl DevicePrimitive for AnyPrim {
+    fn len(&self) -> usize {
+        match self {
+            Self::CPU(prim) => prim.len(),
+            Self::WebGPU(buffer) => buffer.len(),
+        }
+    }
+
+    fn as_wgpu_buffer(&self) -> Option<&wgpu::Buffer> {
+        match self {
+            Self::CPU(_) => None,
+            Self::WebGPU(buffer) => Some(buffer),
+        }
+    }
+}
+
+impl AnyDevice {
+    fn foreign(&self, prim: &AnyPrim) -> DeviceError {
+        DeviceError::ForeignPrimitive {
+            expected: self.name(),
+            actual: prim.name(),
+        }
+    }
+}
+
+///Primitives are only ever paired with the device that allocated them,
+///anything else is a bug in the caller.
+impl DeviceAllocator for AnyDevice {
+    type Prim = AnyPrim;
+
+    unsafe fn alloc(&self, layout: Layout, mode: AllocMode) -> Self::Prim {
+        match self {
+            Self::CPU(cpu) => AnyPrim::CPU(unsafe { cpu.alloc(layout, mode) }),
+            Self::WebGPU(gpu) => AnyPrim::WebGPU(unsafe { gpu.handle().alloc(layout, mode) }),
+        }
+    }
+
+    unsafe fn alloc_init(&self, layout: Layout, init: &[u8], mode: AllocMode) -> Self::Prim {
+        match self {
+            Self::CPU(cpu) => AnyPrim::CPU(unsafe { cpu.alloc_init(layout, init, mode) }),
+            Self::WebGPU(gpu) => {
+                AnyPrim::WebGPU(unsafe { gpu.handle().alloc_init(layout, init, mode) })
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, item: Self::Prim, layout: Layout) {
+        match (self, item) {
+            (Self::CPU(cpu), AnyPrim::CPU(prim)) => unsafe { cpu.dealloc(prim, layout) },
+            (Self::WebGPU(gpu), AnyPrim::WebGPU(buffer)) => unsafe {
+                gpu.handle().dealloc(buffer, layout)
+            },
+            (device, item) => panic!("{}", device.foreign(&item)),
+        }
+    }
+}
+
+impl Device for AnyDevice {
+    type Prim = AnyPrim;
+    type Allocator = AnyDevice;
+
+    fn id(&self) -> DeviceId {
+        match self {
+            Self::CPU(cpu) => cpu.id(),
+            Self::WebGPU(gpu) => gpu.id(),
+        }
+    }
+
+    fn copy_from_host(&self, src: &[u8], dst: &mut Self::Prim) -> Result<(), DeviceError> {
+        match (self, dst) {
+            (Self::CPU(cpu), AnyPrim::CPU(prim)) => cpu.copy_from_host(src, prim),
+            (Self::WebGPU(gpu), AnyPrim::WebGPU(buffer)) => gpu.copy_from_host(src, buffer),
+            (device, dst) => Err(device.foreign(dst)),
+        }
+    }
+
+    fn copy_to_host(&self, src: &Self::Prim, dst: &mut [u8]) -> Result<(), DeviceError> {
+        match (self, src) {
+            (Self::CPU(cpu), AnyPrim::CPU(prim)) => cpu.copy_to_host(prim, dst),
+            (Self::WebGPU(gpu), AnyPrim::WebGPU(buffer)) => gpu.copy_to_host(buffer, dst),
+            (device, src) => Err(device.foreign(src)),
+        }
+    }
+
+    async fn copy_to_host_async(
+        &self,
+        src: &Self::Prim,
+        dst: &mut [u8],
+    ) -> Result<(), DeviceError> {
+        match (self, src) {
+            (Self::CPU(cpu), AnyPrim::CPU(prim)) => cpu.copy_to_host_async(prim, dst).await,
+            (Self::WebGPU(gpu), AnyPrim::WebGPU(buffer)) => {
+                gpu.copy_to_host_async(buffer, dst).await
+            }
+            (device, src) => Err(device.foreign(src)),
+        }
+    }
+
+    ///Defers to the wrapped device, so its specialized transfers are kept.
+    fn copy_to<Ext: Device>(
+        &self,
+        src: &Self::Prim,
+        dst: &mut Ext::Prim,
+        ext: &Ext,
+    ) -> Result<(), DeviceError> {
+        match (self, src) {
+            (Self::CPU(cpu), AnyPrim::CPU(prim)) => cpu.copy_to(prim, dst, ext),
+            (Self::WebGPU(gpu), AnyPrim::WebGPU(buffer)) => gpu.copy_to(buffer, dst, ext),
+            (device, src) => Err(device.foreign(src)),
+        }
+    }
+
+    fn allocate(&self, layout: Layout, mode: AllocMode) -> Result<Self::Prim, DeviceError> {
+        match self {
+            Self::CPU(cpu) => Ok(AnyPrim::CPU(cpu.allocate(layout, mode)?)),
+            Self::WebGPU(gpu) => Ok(AnyPrim::WebGPU(gpu.allocate(layout, mode)?)),
+        }
+    }
+
+    fn deallocate(&self, item: Self::Prim, layout: Layout) -> Result<(), DeviceError> {
+        match (self, item) {
+            (Self::CPU(cpu), AnyPrim::CPU(prim)) => cpu.deallocate(prim, layout),
+            (Self::WebGPU(gpu), AnyPrim::WebGPU(buffer)) => gpu.deallocate(buffer, layout),
+            (device, item) => Err(device.foreign(&item)),
+        }
+    }
+}
+
+///Moves the memory of `storage` to `ext` when it is not shared, wrapping the primitive with `f`.
+///Shared storage is copied instead, as other tensors still reference it.
+fn rewrap<D: Device, Ext: Device>(
+    storage: Arc<Storage<D>>,
+    ext: &Arc<Ext>,
+    f: impl FnOnce(D::Prim) -> Ext::Prim,
+) -> Result<Arc<Storage<Ext>>, TensorError> {
+    match Arc::try_unwrap(storage) {
+        Ok(storage) => {
+            let (dt, layout) = (storage.dt(), *storage.layout());
+            Ok(Arc::new(Storage::from_prim(
+                f(storage.into_prim()),
+                dt,
+                layout,
+                ext.clone(),
+            )))
+        }
+        Err(storage) => Ok(Arc::new(storage.to(ext)?)),
+    }
+}
+
+impl<D: Device> Tensor<D> {
+    ///Copies the tensor to `device`, whichever devices are involved.
+    ///The result does not require grad.
+    pub fn to_any(self, device: &AnyDevice) -> Result<Tensor<AnyDevice>, anyhow::Error> {
+        self.to(&Arc::new(device.clone()))
+    }
+}
+
+impl Tensor<CPU> {
+    ///Erases the device type, without copying unless the storage is shared.
+    ///The result does not require grad.
+    pub fn into_any(self) -> Result<Tensor<AnyDevice>, TensorError> {
+        let device = Arc::new(AnyDevice::CPU(CPU));
+        self.map_storage(|storage| rewrap(storage, &device, AnyPrim::CPU))
+    }
+}
+
+impl Tensor<WebGPU> {
+    ///Erases the device type, without copying unless the storage is shared.
+    ///The result does not require grad.
+    pub fn into_any(self) -> Result<Tensor<AnyDevice>, TensorError> {
+        let device = Arc::new(AnyDevice::WebGPU(self.storage().device().clone()));
+        self.map_storage(|storage| rewrap(storage, &device, AnyPrim::WebGPU))
+    }
+}
+
+impl Tensor<AnyDevice> {
+    fn mismatch(&self, expected: &str) -> TensorError {
+        TensorError::DeviceMismatch {
+            expected: expected.to_string(),
+            actual: self.device_id().to_string(),
+        }
+    }
+
+    ///Recovers the statically typed tensor, without copying unless the storage is shared.
+    ///Fails if the tensor is not on the CPU, see [`Tensor::to_any`] to move it there first.
+    pub fn into_cpu(self) -> Result<Tensor<CPU>, TensorError> {
+        if !matches!(self.device(), AnyDevice::CPU(_)) {
+            return Err(self.mismatch("CPU"));
+        }
+        self.map_storage(|storage| {
+            rewrap(storage, &Arc::new(CPU), |prim| match prim {
+                AnyPrim::CPU(prim) => prim,
+                AnyPrim::WebGPU(_) => unreachable!("CPU storage holding a WebGPU buffer"),
+            })
+        })
+    }
+
+    ///Recovers the statically typed tensor, without copying unless the storage is shared.
+    ///Fails if the tensor is not on a WebGPU device.
+    pub fn into_webgpu(self) -> Result<Tensor<WebGPU>, TensorError> {
+        let AnyDevice::WebGPU(device) = self.device().clone() else {
+            return Err(self.mismatch("WebGPU"));
+        };
+        self.map_storage(|storage| {
+            rewrap(storage, &device, |prim| match prim {
+                AnyPrim::WebGPU(buffer) => buffer,
+                AnyPrim::CPU(_) => unreachable!("WebGPU storage holding CPU memory"),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn cpu_roundtrip_is_zero_copy() {
+        let t = Tensor::<CPU>::new(vec![2, 2].into(), vec![1f32, 2., 3., 4.]).unwrap();
+        let ptr = t.as_slice::<f32>().unwrap().as_ptr();
+        let any = t.into_any().unwrap();
+        assert_eq!(any.device().name(), "CPU");
+        let back = any.into_cpu().unwrap();
+        assert_eq!(back.as_slice::<f32>().unwrap().as_ptr(), ptr);
+
+        //Storage shared with another view is copied instead.
+        let copy = back.clone().into_any().unwrap().into_cpu().unwrap();
+        assert_ne!(copy.as_slice::<f32>().unwrap().as_ptr(), ptr);
+        assert_eq!(copy, back);
+
+        assert!(matches!(
+            back.into_any().unwrap().into_webgpu(),
+            Err(TensorError::DeviceMismatch { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn runtime_selected_devices() {
+        let cpu = AnyDevice::from_name("cpu").await.unwrap();
+        let gpu = AnyDevice::from_name("GPU").await.unwrap();
+        assert!(AnyDevice::from_name("tpu").await.is_err());
+
+        let data = vec![1f32, -2., 3., -4., 5., -6.];
+        let t = Tensor::<CPU>::new(vec![2, 3].into(), data.clone()).unwrap();
+        let on_gpu = t.to_any(&gpu).unwrap();
+        assert_eq!(on_gpu.device().name(), "WebGPU");
+        assert!(matches!(
+            on_gpu.clone().into_cpu(),
+            Err(TensorError::DeviceMismatch { .. })
+        ));
+
+        //Statically typed operations still apply once the type is recovered.
+        let doubled = on_gpu.clone().into_webgpu().unwrap();
+        let doubled = doubled.add(&doubled).unwrap().into_any().unwrap();
+        let back = doubled.to_any(&cpu).unwrap().into_cpu().unwrap();
+        assert_eq!(
+            back.to_vec::<f32>().unwrap(),
+            data.iter().map(|x| x * 2.).collect::<Vec<_>>()
+        );
+        let back = on_gpu.to(&Arc::new(CPU)).unwrap();
+        assert_eq!(back.to_vec::<f32>().unwrap(), data);
+    }
+}
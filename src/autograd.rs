@@ -0,0 +1,736 @@
+use crate::{
+    BinaryOp, Device, ReduceOp, Shape, SliceIndex, Tensor, TensorError, UnaryOp, WebGPU, CPU,
+};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+thread_local! {
+    static GRAD_ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+///Whether operations on the current thread are recorded for differentiation.
+pub fn is_grad_enabled() -> bool {
+    GRAD_ENABLED.with(Cell::get)
+}
+
+///Runs `f` without recording operations for differentiation, e.g for inference or
+///for updating parameters. Results of operations inside `f` do not require grad.
+///Like [`is_grad_enabled`], this only affects the current thread.
+pub fn no_grad<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            GRAD_ENABLED.with(|enabled| enabled.set(self.0));
+        }
+    }
+    let _restore = Restore(GRAD_ENABLED.with(|enabled| enabled.replace(false)));
+    f()
+}
+
+///A differentiable operation, recorded with the tensors it was applied to.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum GradOp {
+    Unary(UnaryOp),
+    ///Recorded on the operands broadcast to the shape of the output.
+    Binary(BinaryOp),
+    Reduce {
+        op: ReduceOp,
+        dims: Vec<usize>,
+        keepdim: bool,
+    },
+    ///Recorded on the operands broadcast to `[..batch, m, k]` and `[..batch, k, n]`.
+    Matmul,
+    Cast,
+    ///Copies without changing the shape, e.g [`Tensor::contiguous`].
+    Identity,
+    ///Changes the shape but not the order of elements.
+    Reshape,
+    Permute(Vec<usize>),
+    Expand,
+    Slice(Vec<SliceIndex>),
+    Softmax,
+    LogSoftmax,
+    ///Inputs are `x`, then the weight and bias if present.
+    LayerNorm {
+        eps: f32,
+        weight: bool,
+        bias: bool,
+    },
+    ///Inputs are `x`, then the weight if present.
+    RmsNorm {
+        eps: f32,
+        weight: bool,
+    },
+}
+
+impl GradOp {
+    pub(crate) fn reduce(op: ReduceOp, dims: &[usize], keepdim: bool) -> Self {
+        GradOp::Reduce {
+            op,
+            dims: dims.to_vec(),
+            keepdim,
+        }
+    }
+}
+
+///The operation which produced a tensor.
+#[derive(Debug)]
+struct Backward<D: Device> {
+    op: GradOp,
+    inputs: Vec<Tensor<D>>,
+    ///Detached, so that the node does not own itself.
+    output: Tensor<D>,
+}
+
+///Autograd state shared by a tensor that requires grad and its clones.
+///Leaves have no [`Backward`], and accumulate their gradient.
+#[derive(Debug)]
+pub struct GradNode<D: Device> {
+    backward: Option<Backward<D>>,
+    grad: Mutex<Option<Tensor<D>>>,
+}
+
+///Device specific operations used to backpropagate.
+pub(crate) trait Backprop: Sized {
+    ///Gradients with respect to each of the `inputs` of `op`, given those of its `output`.
+    ///`None` if an input does not contribute to the gradient.
+    fn input_grads(
+        op: &GradOp,
+        inputs: &[Self],
+        output: &Self,
+        grad: &Self,
+    ) -> Result<Vec<Option<Self>>, TensorError>;
+
+    fn add_grad(&self, grad: &Self) -> Result<Self, TensorError>;
+
+    ///Gradients accumulated into a leaf are contiguous.
+    fn leaf_grad(&self) -> Result<Self, TensorError>;
+}
+
+///Autograd records the operations applied to tensors that require grad,
+///as a graph which [`Tensor::backward`] traverses in reverse.
+///Only float tensors can require grad, gradients have the DType of their tensor.
+impl<D: Device> Tensor<D> {
+    pub fn requires_grad(&self) -> bool {
+        self.autograd().is_some()
+    }
+
+    ///Marks the tensor as a leaf whose gradient is accumulated by [`Tensor::backward`].
+    ///Disabling it detaches the tensor from the graph.
+    pub fn set_requires_grad(&mut self, requires_grad: bool) -> Result<(), TensorError> {
+        if !requires_grad {
+            self.set_autograd(None);
+        } else if !self.dt().is_float() {
+            return Err(TensorError::UnsupportedDType(self.dt()));
+        } else if !self.requires_grad() {
+            self.set_autograd(Some(Arc::new(GradNode {
+                backward: None,
+                grad: Mutex::new(None),
+            })));
+        }
+        Ok(())
+    }
+
+    ///Whether the tensor was created by the user rather than by a recorded operation.
+    pub fn is_leaf(&self) -> bool {
+        self.autograd().is_none_or(|node| node.backward.is_none())
+    }
+
+    ///The gradient accumulated into a leaf, if any.
+    pub fn grad(&self) -> Option<Tensor<D>> {
+        self.autograd()
+            .and_then(|node| node.grad.lock().unwrap().clone())
+    }
+
+    ///Clears the gradient accumulated into a leaf.
+    pub fn zero_grad(&self) {
+        if let Some(node) = self.autograd() {
+            *node.grad.lock().unwrap() = None;
+        }
+    }
+
+    ///A view sharing storage with `self`, which does not require grad.
+    pub fn detach(&self) -> Tensor<D> {
+        let mut detached = self.clone();
+        detached.set_autograd(None);
+        detached
+    }
+
+    ///Records that `self` is the result of `op` applied to `inputs`,
+    ///if grad is enabled and any of the inputs requires it.
+    pub(crate) fn record(mut self, op: GradOp, inputs: &[&Tensor<D>]) -> Tensor<D> {
+        if !is_grad_enabled() || !self.dt().is_float() || !inputs.iter().any(|t| t.requires_grad())
+        {
+            return self;
+        }
+        let backward = Backward {
+            op,
+            inputs: inputs.iter().map(|&t| t.clone()).collect(),
+            output: self.detach(),
+        };
+        self.set_autograd(Some(Arc::new(GradNode {
+            backward: Some(backward),
+            grad: Mutex::new(None),
+        })));
+        self
+    }
+
+    ///Propagates `grad` from `self` to the leaves, in topological order.
+    fn backpropagate(&self, grad: &Tensor<D>) -> Result<(), TensorError>
+    where
+        Tensor<D>: Backprop,
+    {
+        let root = self.autograd().ok_or(TensorError::NoGradient)?;
+        self.check_device(grad)?;
+        if grad.dt() != self.dt() {
+            return Err(TensorError::DTypeMismatch {
+                expected: self.dt(),
+                actual: grad.dt(),
+            });
+        }
+        if grad.shape() != self.shape() {
+            return Err(TensorError::BroadcastMismatch(
+                grad.shape().clone(),
+                self.shape().clone(),
+            ));
+        }
+        no_grad(|| {
+            let mut grads = HashMap::from([(Arc::as_ptr(root), grad.clone())]);
+            for node in topological_order(root) {
+                let Some(grad) = grads.remove(&Arc::as_ptr(&node)) else {
+                    continue;
+                };
+                let Some(backward) = &node.backward else {
+                    let mut acc = node.grad.lock().unwrap();
+                    *acc = Some(match acc.take() {
+                        Some(acc) => acc.add_grad(&grad)?,
+                        None => grad.leaf_grad()?,
+                    });
+                    continue;
+                };
+                let inputs = backward
+                    .inputs
+                    .iter()
+                    .map(Tensor::detach)
+                    .collect::<Vec<_>>();
+                let input_grads =
+                    Tensor::input_grads(&backward.op, &inputs, &backward.output, &grad)?;
+                for (input, grad) in backward.inputs.iter().zip(input_grads) {
+                    let (Some(input), Some(grad)) = (input.autograd(), grad) else {
+                        continue;
+                    };
+                    let key = Arc::as_ptr(input);
+                    let grad = match grads.remove(&key) {
+                        Some(acc) => acc.add_grad(&grad)?,
+                        None => grad,
+                    };
+                    grads.insert(key, grad);
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+///Nodes reachable from `root`, each before the inputs it was computed from.
+fn topological_order<D: Device>(root: &Arc<GradNode<D>>) -> Vec<Arc<GradNode<D>>> {
+    let mut order = vec![];
+    let mut visited = HashSet::new();
+    //Nodes are pushed twice: to visit their inputs, then once those are ordered.
+    let mut stack = vec![(root.clone(), false)];
+    while let Some((node, inputs_ordered)) = stack.pop() {
+        if inputs_ordered {
+            order.push(node);
+            continue;
+        }
+        if !visited.insert(Arc::as_ptr(&node)) {
+            continue;
+        }
+        stack.push((node.clone(), true));
+        for input in node.backward.iter().flat_map(|b| &b.inputs) {
+            if let Some(input) = input.autograd() {
+                if !visited.contains(&Arc::as_ptr(input)) {
+                    stack.push((input.clone(), false));
+                }
+            }
+        }
+    }
+    order.reverse();
+    order
+}
+
+macro_rules! autograd_methods {
+    ($device:ty) => {
+        impl Backprop for Tensor<$device> {
+            fn input_grads(
+                op: &GradOp,
+                inputs: &[Self],
+                output: &Self,
+                grad: &Self,
+            ) -> Result<Vec<Option<Self>>, TensorError> {
+                let (x, y, g) = (&inputs[0], output, grad);
+                let last = x.shape().rank().saturating_sub(1);
+                let grads = match op {
+                    GradOp::Unary(op) => vec![Self::unary_grad(*op, x, y, g)?],
+                    GradOp::Binary(op) => {
+                        let (da, db) = Self::binary_grad(*op, x, &inputs[1], y, g)?;
+                        vec![Some(da), Some(db)]
+                    }
+                    GradOp::Reduce { op, dims, keepdim } => {
+                        vec![Some(Self::reduce_grad(*op, dims, *keepdim, x, y, g)?)]
+                    }
+                    GradOp::Matmul => {
+                        let rank = x.shape().rank();
+                        let (a, b) = (x, &inputs[1]);
+                        vec![
+                            Some(g.matmul(&b.transpose(rank - 2, rank - 1)?)?),
+                            Some(a.transpose(rank - 2, rank - 1)?.matmul(g)?),
+                        ]
+                    }
+                    GradOp::Cast => vec![Some(g.cast(x.dt())?)],
+                    GradOp::Identity => vec![Some(g.clone())],
+                    GradOp::Reshape => vec![Some(g.reshape(x.shape().clone())?)],
+                    GradOp::Permute(dims) => {
+                        let mut inverse = vec![0; dims.len()];
+                        for (i, &d) in dims.iter().enumerate() {
+                            inverse[d] = i;
+                        }
+                        vec![Some(g.permute(&inverse)?)]
+                    }
+                    GradOp::Expand => vec![Some(g.sum_to(x.shape())?)],
+                    GradOp::Slice(indices) => vec![Some(g.unslice(x.shape().clone(), indices)?)],
+                    GradOp::Softmax => {
+                        let dot = g.mul(y)?.sum(&[last], true)?;
+                        vec![Some(y.mul(&g.sub(&dot)?)?)]
+                    }
+                    GradOp::LogSoftmax => {
+                        let sum = g.sum(&[last], true)?;
+                        vec![Some(g.sub(&y.exp()?.mul(&sum)?)?)]
+                    }
+                    GradOp::LayerNorm { eps, weight, bias } => {
+                        let mean = x.mean(&[last], true)?;
+                        let centered = x.sub(&mean)?;
+                        let var = centered.mul(&centered)?.mean(&[last], true)?;
+                        let rstd = Self::rsqrt(&var, *eps)?;
+                        let xhat = centered.mul(&rstd)?;
+                        let weight = weight.then(|| &inputs[1]);
+                        let mut grads = Self::norm_grad(&xhat, &rstd, weight, g, true)?;
+                        if *bias {
+                            grads.push(Some(g.sum(&(0..last).collect::<Vec<_>>(), false)?));
+                        }
+                        grads
+                    }
+                    GradOp::RmsNorm { eps, weight } => {
+                        let rstd = Self::rsqrt(&x.mul(x)?.mean(&[last], true)?, *eps)?;
+                        let xhat = x.mul(&rstd)?;
+                        let weight = weight.then(|| &inputs[1]);
+                        Self::norm_grad(&xhat, &rstd, weight, g, false)?
+                    }
+                };
+                Ok(grads)
+            }
+
+            fn add_grad(&self, grad: &Self) -> Result<Self, TensorError> {
+                self.add(grad)
+            }
+
+            fn leaf_grad(&self) -> Result<Self, TensorError> {
+                self.contiguous()
+            }
+        }
+
+        impl Tensor<$device> {
+            ///Backpropagates from a tensor of a single element, accumulating the gradient
+            ///of every leaf it depends on, see [`Tensor::grad`].
+            pub fn backward(&self) -> Result<(), TensorError> {
+                if self.numel() != 1 {
+                    return Err(TensorError::NotScalar(self.shape().clone()));
+                }
+                let seed = self.scalar_like(1.)?.expand(self.shape().clone())?;
+                self.backpropagate(&seed)
+            }
+
+            ///Backpropagates `grad`, the gradient of some scalar with respect to `self`.
+            pub fn backward_with(&self, grad: &Tensor<$device>) -> Result<(), TensorError> {
+                self.backpropagate(grad)
+            }
+
+            ///Sums a broadcast gradient back to the `shape` it was broadcast from.
+            fn sum_to(&self, shape: &Shape) -> Result<Tensor<$device>, TensorError> {
+                let rank = self.shape().rank();
+                let lead = rank - shape.rank();
+                let dims = (0..rank)
+                    .filter(|&d| d < lead || (shape[d - lead] == 1 && self.shape()[d] != 1))
+                    .collect::<Vec<_>>();
+                if dims.is_empty() {
+                    return Ok(self.clone());
+                }
+                self.sum(&dims, true)?.reshape(shape.clone())
+            }
+
+            ///Broadcasts the result of a reduction back to the `shape` that was reduced.
+            fn unreduce(
+                &self,
+                shape: &Shape,
+                dims: &[usize],
+                keepdim: bool,
+            ) -> Result<Tensor<$device>, TensorError> {
+                let mut unreduced = self.clone();
+                if !keepdim {
+                    let mut dims = dims.to_vec();
+                    dims.sort_unstable();
+                    for d in dims {
+                        unreduced = unreduced.unsqueeze(d)?;
+                    }
+                }
+                unreduced.expand(shape.clone())
+            }
+
+            ///`1 / sqrt(self + eps)`
+            fn rsqrt(&self, eps: f32) -> Result<Tensor<$device>, TensorError> {
+                self.scalar_like(1.)?
+                    .div(&self.add(&self.scalar_like(eps)?)?.sqrt()?)
+            }
+
+            fn unary_grad(
+                op: UnaryOp,
+                x: &Self,
+                y: &Self,
+                g: &Self,
+            ) -> Result<Option<Tensor<$device>>, TensorError> {
+                let one = x.scalar_like(1.)?;
+                let derivative = match op {
+                    UnaryOp::Exp => y.clone(),
+                    UnaryOp::Log => return Ok(Some(g.div(x)?)),
+                    UnaryOp::Sqrt => return Ok(Some(g.div(&y.add(y)?)?)),
+                    UnaryOp::Abs => x.sign()?,
+                    UnaryOp::Neg => return Ok(Some(g.neg()?)),
+                    UnaryOp::Tanh => one.sub(&y.mul(y)?)?,
+                    UnaryOp::Sigmoid => y.mul(&one.sub(y)?)?,
+                    UnaryOp::Relu => y.sign()?,
+                    UnaryOp::Gelu => {
+                        let k = std::f64::consts::FRAC_2_SQRT_PI * std::f64::consts::FRAC_1_SQRT_2;
+                        let square = x.mul(x)?;
+                        let inner = one.add(&square.mul(&x.scalar_like(0.044715)?)?)?;
+                        let t = x.mul(&inner)?.mul(&x.scalar_like(k)?)?.tanh()?;
+                        let dinner = one
+                            .add(&square.mul(&x.scalar_like(3. * 0.044715)?)?)?
+                            .mul(&x.scalar_like(k)?)?;
+                        let dt = one.sub(&t.mul(&t)?)?.mul(&dinner)?;
+                        one.add(&t)?.add(&x.mul(&dt)?)?.mul(&x.scalar_like(0.5)?)?
+                    }
+                    UnaryOp::Silu => {
+                        let s = x.sigmoid()?;
+                        s.mul(&one.add(&x.mul(&one.sub(&s)?)?)?)?
+                    }
+                    UnaryOp::Sign => return Ok(None),
+                };
+                Ok(Some(g.mul(&derivative)?))
+            }
+
+            fn binary_grad(
+                op: BinaryOp,
+                a: &Self,
+                b: &Self,
+                y: &Self,
+                g: &Self,
+            ) -> Result<(Tensor<$device>, Tensor<$device>), TensorError> {
+                let one = a.scalar_like(1.)?;
+                Ok(match op {
+                    BinaryOp::Add => (g.clone(), g.clone()),
+                    BinaryOp::Sub => (g.clone(), g.neg()?),
+                    BinaryOp::Mul => (g.mul(b)?, g.mul(a)?),
+                    BinaryOp::Div => {
+                        let da = g.div(b)?;
+                        let db = da.mul(y)?.neg()?;
+                        (da, db)
+                    }
+                    BinaryOp::Pow => {
+                        let da = g.mul(b)?.mul(&a.pow(&b.sub(&one)?)?)?;
+                        (da, g.mul(y)?.mul(&a.log()?)?)
+                    }
+                    //Ties split the gradient evenly.
+                    BinaryOp::Maximum | BinaryOp::Minimum => {
+                        let diff = match op {
+                            BinaryOp::Maximum => a.sub(b)?,
+                            _ => b.sub(a)?,
+                        };
+                        let mask = diff.sign()?.add(&one)?.mul(&a.scalar_like(0.5)?)?;
+                        let da = g.mul(&mask)?;
+                        let db = g.sub(&da)?;
+                        (da, db)
+                    }
+                })
+            }
+
+            fn reduce_grad(
+                op: ReduceOp,
+                dims: &[usize],
+                keepdim: bool,
+                x: &Self,
+                y: &Self,
+                g: &Self,
+            ) -> Result<Tensor<$device>, TensorError> {
+                let g = g.unreduce(x.shape(), dims, keepdim)?;
+                let y = y.unreduce(x.shape(), dims, keepdim)?;
+                match op {
+                    ReduceOp::Sum => Ok(g),
+                    ReduceOp::Mean => {
+                        let n = dims.iter().map(|&d| x.shape()[d]).product::<usize>();
+                        g.div(&x.scalar_like(n as f64)?)
+                    }
+                    //Ties split the gradient evenly.
+                    ReduceOp::Max | ReduceOp::Min => {
+                        let one = x.scalar_like(1.)?;
+                        let mask = one.sub(&x.sub(&y)?.sign()?.abs()?)?;
+                        let count = mask.sum(dims, true)?;
+                        g.mul(&mask)?.div(&count)
+                    }
+                    //Each element receives the product of the others in its group.
+                    //Zeros are replaced by one, so that the product and division stay finite:
+                    //without zeros it is `p / x`, with a single zero only that element receives `p`.
+                    ReduceOp::Prod => {
+                        let one = x.scalar_like(1.)?;
+                        let is_zero = one.sub(&x.sign()?.abs()?)?;
+                        let zeros = is_zero.sum(dims, true)?;
+                        let nonzero = x.add(&is_zero)?;
+                        let p = nonzero.reduce(ReduceOp::Prod, dims, true)?;
+                        let no_zero = one.sub(&zeros.sign()?.abs()?)?;
+                        let one_zero = one.sub(&zeros.sub(&one)?.sign()?.abs()?)?;
+                        let others = no_zero.div(&nonzero)?.add(&one_zero.mul(&is_zero)?)?;
+                        g.mul(&p)?.mul(&others)
+                    }
+                    ReduceOp::ArgMax | ReduceOp::ArgMin => {
+                        unreachable!("{:?} has an integer result, which is not recorded", op)
+                    }
+                }
+            }
+
+            ///Gradients of `xhat * weight (+ bias)` with respect to `x` and the weight,
+            ///where `xhat = (x - mean) * rstd`, or `x * rstd` if not `centered`.
+            fn norm_grad(
+                xhat: &Self,
+                rstd: &Self,
+                weight: Option<&Self>,
+                g: &Self,
+                centered: bool,
+            ) -> Result<Vec<Option<Tensor<$device>>>, TensorError> {
+                let last = xhat.shape().rank() - 1;
+                let dxhat = match weight {
+                    Some(weight) => g.mul(weight)?,
+                    None => g.clone(),
+                };
+                let projection = xhat.mul(&dxhat.mul(xhat)?.mean(&[last], true)?)?;
+                let mut dx = dxhat.sub(&projection)?;
+                if centered {
+                    dx = dx.sub(&dxhat.mean(&[last], true)?)?;
+                }
+                let mut grads = vec![Some(rstd.mul(&dx)?)];
+                if weight.is_some() {
+                    let lead = (0..last).collect::<Vec<_>>();
+                    grads.push(Some(g.mul(xhat)?.sum(&lead, false)?));
+                }
+                Ok(grads)
+            }
+        }
+    };
+}
+
+autograd_methods!(CPU);
+autograd_methods!(WebGPU);
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+
+    fn leaf(shape: Vec<usize>, data: Vec<f64>) -> Tensor<CPU> {
+        let mut t = Tensor::<CPU>::new(shape.into(), data).unwrap();
+        t.set_requires_grad(true).unwrap();
+        t
+    }
+
+    ///Compares the gradient of `f(x).sum()` against central differences.
+    fn check_grad(x: &Tensor<CPU>, f: impl Fn(&Tensor<CPU>) -> Tensor<CPU>) {
+        x.zero_grad();
+        f(x).sum_all().unwrap().backward().unwrap();
+        let grad = x.grad().unwrap().to_vec::<f64>().unwrap();
+        let data = x.to_vec::<f64>().unwrap();
+        let eps = 1e-6;
+        for i in 0..data.len() {
+            let at = |delta: f64| {
+                let mut shifted = data.clone();
+                shifted[i] += delta;
+                let t = Tensor::<CPU>::new(x.shape().clone(), shifted).unwrap();
+                no_grad(|| f(&t).sum_all().unwrap().item::<f64>().unwrap())
+            };
+            let expected = (at(eps) - at(-eps)) / (2. * eps);
+            assert!(
+                (grad[i] - expected).abs() <= 1e-5 * expected.abs().max(1.),
+                "d/dx[{}]: {} != {}",
+                i,
+                grad[i],
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn cpu_gradients_match_finite_differences() {
+        let x = leaf(vec![2, 3], vec![0.5, -1.2, 2.0, 0.3, 1.1, -0.7]);
+        let w = Tensor::<CPU>::new(vec![3].into(), vec![0.9f64, -1.5, 0.4]).unwrap();
+        let m = Tensor::<CPU>::new(vec![3, 2].into(), vec![1f64, 2., -1., 0.5, 0.3, -2.]).unwrap();
+        for op in [
+            UnaryOp::Exp,
+            UnaryOp::Abs,
+            UnaryOp::Tanh,
+            UnaryOp::Sigmoid,
+            UnaryOp::Relu,
+            UnaryOp::Gelu,
+            UnaryOp::Silu,
+        ] {
+            check_grad(&x, |x| x.unary(op).unwrap());
+        }
+        check_grad(&x, |x| x.abs().unwrap().sqrt().unwrap().log().unwrap());
+        for op in [
+            BinaryOp::Add,
+            BinaryOp::Sub,
+            BinaryOp::Mul,
+            BinaryOp::Div,
+            BinaryOp::Maximum,
+            BinaryOp::Minimum,
+        ] {
+            check_grad(&x, |x| x.binary(&w, op).unwrap().binary(x, op).unwrap());
+        }
+        check_grad(&x, |x| x.abs().unwrap().pow(&w).unwrap());
+        check_grad(&x, |x| w.abs().unwrap().pow(&x.sigmoid().unwrap()).unwrap());
+        for op in [
+            ReduceOp::Sum,
+            ReduceOp::Mean,
+            ReduceOp::Max,
+            ReduceOp::Min,
+            ReduceOp::Prod,
+        ] {
+            check_grad(&x, |x| x.reduce(op, &[1], false).unwrap().exp().unwrap());
+            check_grad(&x, |x| x.reduce(op, &[0], true).unwrap().mul(x).unwrap());
+        }
+        //Groups with none, one and two zeros along either dimension.
+        let z = leaf(vec![2, 3], vec![0., 2., -1., 0., 0., 3.]);
+        for dim in [0, 1] {
+            check_grad(&z, |z| z.reduce(ReduceOp::Prod, &[dim], false).unwrap());
+        }
+        check_grad(&z, |z| z.prod_all().unwrap());
+        check_grad(&x, |x| x.matmul(&m).unwrap().tanh().unwrap());
+        check_grad(&x, |x| m.matmul(x).unwrap().exp().unwrap());
+        check_grad(&x, |x| {
+            let batched = x.reshape(vec![1, 2, 3].into()).unwrap();
+            batched.matmul(&m).unwrap().sigmoid().unwrap()
+        });
+        check_grad(&x, |x| {
+            let t = x.transpose(0, 1).unwrap().slice(&s![1.., 1]).unwrap();
+            t.unsqueeze(0)
+                .unwrap()
+                .expand(vec![4, 2].into())
+                .unwrap()
+                .exp()
+                .unwrap()
+        });
+        //Rounding through f32 is too coarse for finite differences.
+        x.zero_grad();
+        let y = x.cast(DType::F32).unwrap().cast(DType::F64).unwrap();
+        y.exp().unwrap().sum_all().unwrap().backward().unwrap();
+        let expected = y.detach().exp().unwrap().to_vec::<f64>().unwrap();
+        for (g, e) in x
+            .grad()
+            .unwrap()
+            .to_vec::<f64>()
+            .unwrap()
+            .iter()
+            .zip(expected)
+        {
+            assert!((g - e).abs() <= 1e-6 * e, "{} != {}", g, e);
+        }
+        check_grad(&x, |x| x.softmax().unwrap().mul(&w).unwrap());
+        check_grad(&x, |x| x.log_softmax().unwrap().mul(&w).unwrap());
+        check_grad(&x, |x| {
+            x.layer_norm(None, None, 1e-5).unwrap().mul(&w).unwrap()
+        });
+        check_grad(&x, |x| x.rms_norm(Some(&w), 1e-5).unwrap().mul(&w).unwrap());
+
+        //Weight and bias of the layer norm.
+        let x = x.detach();
+        let mut b = w.clone();
+        b.set_requires_grad(true).unwrap();
+        check_grad(&b, |b| {
+            x.layer_norm(Some(b), Some(b), 1e-5).unwrap().exp().unwrap()
+        });
+        check_grad(&b, |b| x.rms_norm(Some(b), 1e-5).unwrap().exp().unwrap());
+    }
+
+    #[test]
+    fn grads_accumulate_and_no_grad() {
+        let x = leaf(vec![2], vec![1., 2.]);
+        let y = x.mul(&x).unwrap().sum_all().unwrap();
+        assert!(!y.is_leaf());
+        y.backward().unwrap();
+        y.backward().unwrap();
+        assert_eq!(x.grad().unwrap().to_vec::<f64>().unwrap(), vec![4., 8.]);
+        x.zero_grad();
+        assert!(x.grad().is_none());
+
+        let z = no_grad(|| x.exp().unwrap().sum_all().unwrap());
+        assert!(!z.requires_grad());
+        assert!(is_grad_enabled());
+        assert!(matches!(z.backward(), Err(TensorError::NoGradient)));
+        assert!(matches!(
+            x.exp().unwrap().backward(),
+            Err(TensorError::NotScalar(_))
+        ));
+        assert!(x.argmax_all().unwrap().dt() == DType::U32);
+        let mut ints = Tensor::<CPU>::new(vec![1].into(), vec![1u32]).unwrap();
+        assert!(ints.set_requires_grad(true).is_err());
+    }
+
+    #[tokio::test]
+    async fn gpu_gradients_match_cpu() {
+        let device = std::sync::Arc::new(WebGPU::new().await.unwrap());
+        let data = (0..24).map(|i| ((i * 7) % 11) as f32 * 0.3 - 1.4).collect();
+        let mut x = Tensor::<CPU>::new(vec![4, 6].into(), data).unwrap();
+        let m = Tensor::<CPU>::new(
+            vec![6, 3].into(),
+            (0..18).map(|i| i as f32 * 0.1 - 0.8).collect(),
+        )
+        .unwrap();
+        let w = Tensor::<CPU>::new(vec![3].into(), vec![0.5f32, -1., 2.]).unwrap();
+        let mut gx = x.clone().to(&device).unwrap();
+        let (gm, gw) = (
+            m.clone().to(&device).unwrap(),
+            w.clone().to(&device).unwrap(),
+        );
+        x.set_requires_grad(true).unwrap();
+        gx.set_requires_grad(true).unwrap();
+
+        macro_rules! model {
+            ($x:expr, $m:expr, $w:expr) => {{
+                let h = $x.slice(&s![1..]).unwrap().matmul($m).unwrap();
+                let h = h.layer_norm(Some($w), None, 1e-5).unwrap().gelu().unwrap();
+                let h = h.add(&h.max(&[0], true).unwrap()).unwrap();
+                h.log_softmax().unwrap().mean_all().unwrap()
+            }};
+        }
+        model!(x, &m, &w).backward().unwrap();
+        model!(gx, &gm, &gw).backward().unwrap();
+        let expected = x.grad().unwrap().to_vec::<f32>().unwrap();
+        let actual = gx
+            .grad()
+            .unwrap()
+            .to(&Arc::new(CPU))
+            .unwrap()
+            .to_vec::<f32>()
+            .unwrap();
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() <= 1e-5 + 1e-3 * e.abs(), "{} != {}", a, e);
+        }
+    }
+}
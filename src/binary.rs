@@ -0,0 +1,694 @@
+use crate::{
+    as_std, wgsl, Castable, DType, Device, GPUHandle, GradOp, Kernel, KernelArg, Metadata, Shape,
+    StridedIndex, TData, Tensor, TensorError, WebGPU, Workgroups, CPU,
+};
+use half::{bf16, f16};
+use std::ops::{Add, Div, Mul, Sub};
+use std::sync::Arc;
+
+///Elementwise binary operations, broadcasting their operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Maximum,
+    Minimum,
+}
+
+///Arithmetic for every [`DType`].
+///* Integer arithmetic wraps on overflow, and division by zero yields 0.
+///* Integer `pow` with a negative exponent truncates toward zero, like `1 / a.pow(-b)`.
+///* Float `maximum` and `minimum` propagate NaN.
+pub trait Numeric: Castable {
+    fn add(self, rhs: Self) -> Self;
+    fn sub(self, rhs: Self) -> Self;
+    fn mul(self, rhs: Self) -> Self;
+    fn div(self, rhs: Self) -> Self;
+    fn pow(self, rhs: Self) -> Self;
+    fn maximum(self, rhs: Self) -> Self;
+    fn minimum(self, rhs: Self) -> Self;
+}
+
+macro_rules! numeric_int {
+    ($t:ty, $pow:expr) => {
+        impl Numeric for $t {
+            fn add(self, rhs: Self) -> Self {
+                self.wrapping_add(rhs)
+            }
+            fn sub(self, rhs: Self) -> Self {
+                self.wrapping_sub(rhs)
+            }
+            fn mul(self, rhs: Self) -> Self {
+                self.wrapping_mul(rhs)
+            }
+            fn div(self, rhs: Self) -> Self {
+                if rhs == 0 {
+                    0
+                } else {
+                    self.wrapping_div(rhs)
+                }
+            }
+            fn pow(self, rhs: Self) -> Self {
+                $pow(self, rhs)
+            }
+            fn maximum(self, rhs: Self) -> Self {
+                Ord::max(self, rhs)
+            }
+            fn minimum(self, rhs: Self) -> Self {
+                Ord::min(self, rhs)
+            }
+        }
+    };
+}
+
+macro_rules! unsigned_pow {
+    ($t:ty) => {
+        |a: $t, b: $t| a.wrapping_pow(u32::try_from(b).unwrap_or(u32::MAX))
+    };
+}
+
+macro_rules! signed_pow {
+    ($t:ty) => {
+        |a: $t, b: $t| {
+            if b >= 0 {
+                return a.wrapping_pow(u32::try_from(b).unwrap_or(u32::MAX));
+            }
+            match a {
+                1 => 1,
+                -1 if b % 2 == 0 => 1,
+                -1 => -1,
+                _ => 0,
+            }
+        }
+    };
+}
+
+numeric_int!(u8, unsigned_pow!(u8));
+numeric_int!(u16, unsigned_pow!(u16));
+numeric_int!(u32, unsigned_pow!(u32));
+numeric_int!(u64, unsigned_pow!(u64));
+numeric_int!(i8, signed_pow!(i8));
+numeric_int!(i16, signed_pow!(i16));
+numeric_int!(i32, signed_pow!(i32));
+numeric_int!(i64, signed_pow!(i64));
+
+macro_rules! numeric_float {
+    ($t:ty) => {
+        impl Numeric for $t {
+            fn add(self, rhs: Self) -> Self {
+                self + rhs
+            }
+            fn sub(self, rhs: Self) -> Self {
+                self - rhs
+            }
+            fn mul(self, rhs: Self) -> Self {
+                self * rhs
+            }
+            fn div(self, rhs: Self) -> Self {
+                self / rhs
+            }
+            fn pow(self, rhs: Self) -> Self {
+                self.powf(rhs)
+            }
+            fn maximum(self, rhs: Self) -> Self {
+                if self.is_nan() || rhs.is_nan() {
+                    <$t>::NAN
+                } else {
+                    self.max(rhs)
+                }
+            }
+            fn minimum(self, rhs: Self) -> Self {
+                if self.is_nan() || rhs.is_nan() {
+                    <$t>::NAN
+                } else {
+                    self.min(rhs)
+                }
+            }
+        }
+    };
+}
+
+numeric_float!(f32);
+numeric_float!(f64);
+
+///Half precision arithmetic is computed in f32 and rounded back.
+macro_rules! numeric_half {
+    ($t:ty) => {
+        impl Numeric for $t {
+            fn add(self, rhs: Self) -> Self {
+                <$t>::from_f32(Numeric::add(self.to_f32(), rhs.to_f32()))
+            }
+            fn sub(self, rhs: Self) -> Self {
+                <$t>::from_f32(Numeric::sub(self.to_f32(), rhs.to_f32()))
+            }
+            fn mul(self, rhs: Self) -> Self {
+                <$t>::from_f32(Numeric::mul(self.to_f32(), rhs.to_f32()))
+            }
+            fn div(self, rhs: Self) -> Self {
+                <$t>::from_f32(Numeric::div(self.to_f32(), rhs.to_f32()))
+            }
+            fn pow(self, rhs: Self) -> Self {
+                <$t>::from_f32(Numeric::pow(self.to_f32(), rhs.to_f32()))
+            }
+            fn maximum(self, rhs: Self) -> Self {
+                <$t>::from_f32(Numeric::maximum(self.to_f32(), rhs.to_f32()))
+            }
+            fn minimum(self, rhs: Self) -> Self {
+                <$t>::from_f32(Numeric::minimum(self.to_f32(), rhs.to_f32()))
+            }
+        }
+    };
+}
+
+numeric_half!(f16);
+numeric_half!(bf16);
+
+impl BinaryOp {
+    fn func<T: Numeric>(self) -> fn(T, T) -> T {
+        match self {
+            BinaryOp::Add => T::add,
+            BinaryOp::Sub => T::sub,
+            BinaryOp::Mul => T::mul,
+            BinaryOp::Div => T::div,
+            BinaryOp::Pow => T::pow,
+            BinaryOp::Maximum => T::maximum,
+            BinaryOp::Minimum => T::minimum,
+        }
+    }
+}
+
+impl<D: Device> Tensor<D> {
+    ///Checks the devices and DTypes of `self` and `rhs` agree, and expands both to their broadcast shape.
+    fn broadcast_with(&self, rhs: &Tensor<D>) -> Result<(Tensor<D>, Tensor<D>), TensorError> {
+        self.check_device(rhs)?;
+        if self.dt() != rhs.dt() {
+            return Err(TensorError::DTypeMismatch {
+                expected: self.dt(),
+                actual: rhs.dt(),
+            });
+        }
+        let shape = self.shape().broadcast(rhs.shape()).ok_or_else(|| {
+            TensorError::BroadcastMismatch(self.shape().clone(), rhs.shape().clone())
+        })?;
+        Ok((self.expand(shape.clone())?, rhs.expand(shape)?))
+    }
+}
+
+impl Tensor<CPU> {
+    ///Applies `op` elementwise, broadcasting `self` and `rhs` to a common shape.
+    pub fn binary(&self, rhs: &Tensor<CPU>, op: BinaryOp) -> Result<Tensor<CPU>, TensorError> {
+        let (lhs, rhs) = self.broadcast_with(rhs)?;
+
+        fn binary_t<T: Numeric>(
+            lhs: &Tensor<CPU>,
+            rhs: &Tensor<CPU>,
+            op: BinaryOp,
+        ) -> Result<Tensor<CPU>, TensorError> {
+            let f = op.func::<T>();
+            let a = lhs.storage_slice::<T>()?;
+            let b = rhs.storage_slice::<T>()?;
+            let result = StridedIndex::new(lhs.shape(), lhs.strides())
+                .zip(StridedIndex::new(rhs.shape(), rhs.strides()))
+                .map(|(i, j)| f(a[lhs.offset() + i], b[rhs.offset() + j]))
+                .collect();
+            Tensor::new(lhs.shape().clone(), result)
+        }
+        let dst = as_std!(binary_t(self.dt())(&lhs, &rhs, op))?;
+        Ok(dst.record(GradOp::Binary(op), &[&lhs, &rhs]))
+    }
+
+    ///Creates a tensor of rank 0 holding `value`, cast to `dt`.
+    pub fn scalar<T: Castable>(value: T, dt: DType) -> Result<Tensor<CPU>, TensorError> {
+        let scalar = Tensor::new(Shape::from(vec![]), vec![value])?;
+        if dt == T::dtype() {
+            Ok(scalar)
+        } else {
+            scalar.cast(dt)
+        }
+    }
+
+    pub(crate) fn scalar_like<T: Castable>(&self, value: T) -> Result<Tensor<CPU>, TensorError> {
+        Tensor::<CPU>::scalar(value, self.dt())
+    }
+}
+
+///Binary ops on the GPU support F32, I32 and U32, other DTypes return [`TensorError::UnsupportedDType`].
+///Results match [`Tensor::<CPU>::binary`], with the exception of float `div` and `pow`,
+///which are within 2.5 ULP and a relative error of 1e-5 respectively.
+impl Tensor<WebGPU> {
+    ///Applies `op` elementwise, broadcasting `self` and `rhs` to a common shape.
+    pub fn binary(
+        &self,
+        rhs: &Tensor<WebGPU>,
+        op: BinaryOp,
+    ) -> Result<Tensor<WebGPU>, TensorError> {
+        let (lhs, rhs) = self.broadcast_with(rhs)?;
+        let dt = lhs.dt();
+        let source = BinaryKernel::source(op, dt)?;
+        let numel = lhs.numel();
+        let dst = Tensor::uninit(self.storage().device().clone(), dt, lhs.shape().clone())?;
+        if numel > 0 {
+            let handle = self.device().handle();
+            let meta = Metadata::new()
+                .push(numel)
+                .push_layout(&lhs)
+                .push_layout(&rhs)
+                .upload(handle)?;
+            Kernel::new("binary", source, dt).dispatch(
+                handle,
+                &[
+                    KernelArg::tensor(&lhs),
+                    KernelArg::tensor(&rhs),
+                    KernelArg::output(&dst),
+                    KernelArg::uniform(&meta),
+                ],
+                Workgroups::linear(numel),
+            );
+        }
+        Ok(dst.record(GradOp::Binary(op), &[&lhs, &rhs]))
+    }
+
+    ///Creates a tensor of rank 0 on `device` holding `value`, cast to `dt`.
+    pub fn scalar<T: Castable>(
+        device: &Arc<WebGPU>,
+        value: T,
+        dt: DType,
+    ) -> Result<Tensor<WebGPU>, TensorError> {
+        fn bytes<T: TData>(scalar: &Tensor<CPU>) -> Result<Vec<u8>, TensorError> {
+            Ok(bytemuck::cast_slice(scalar.as_slice::<T>()?).to_vec())
+        }
+        let host = Tensor::<CPU>::scalar(value, dt)?;
+        let bytes = as_std!(bytes(dt)(&host))?;
+        Tensor::<WebGPU>::from_bytes(device.clone(), dt, host.shape().clone(), &bytes)
+    }
+
+    pub(crate) fn scalar_like<T: Castable>(&self, value: T) -> Result<Tensor<WebGPU>, TensorError> {
+        Tensor::<WebGPU>::scalar(self.storage().device(), value, self.dt())
+    }
+}
+
+macro_rules! binary_methods {
+    ($device:ty) => {
+        impl Tensor<$device> {
+            pub fn add(&self, rhs: &Tensor<$device>) -> Result<Tensor<$device>, TensorError> {
+                self.binary(rhs, BinaryOp::Add)
+            }
+
+            pub fn sub(&self, rhs: &Tensor<$device>) -> Result<Tensor<$device>, TensorError> {
+                self.binary(rhs, BinaryOp::Sub)
+            }
+
+            pub fn mul(&self, rhs: &Tensor<$device>) -> Result<Tensor<$device>, TensorError> {
+                self.binary(rhs, BinaryOp::Mul)
+            }
+
+            pub fn div(&self, rhs: &Tensor<$device>) -> Result<Tensor<$device>, TensorError> {
+                self.binary(rhs, BinaryOp::Div)
+            }
+
+            pub fn pow(&self, rhs: &Tensor<$device>) -> Result<Tensor<$device>, TensorError> {
+                self.binary(rhs, BinaryOp::Pow)
+            }
+
+            pub fn maximum(&self, rhs: &Tensor<$device>) -> Result<Tensor<$device>, TensorError> {
+                self.binary(rhs, BinaryOp::Maximum)
+            }
+
+            pub fn minimum(&self, rhs: &Tensor<$device>) -> Result<Tensor<$device>, TensorError> {
+                self.binary(rhs, BinaryOp::Minimum)
+            }
+        }
+    };
+}
+
+binary_methods!(CPU);
+binary_methods!(WebGPU);
+
+///Operator overloads panic if the operands cannot be broadcast or have different DTypes.
+///Use the methods on [`Tensor`] to handle these errors.
+macro_rules! binary_trait {
+    ($device:ty, $trait:ident, $method:ident) => {
+        impl $trait<&Tensor<$device>> for &Tensor<$device> {
+            type Output = Tensor<$device>;
+
+            fn $method(self, rhs: &Tensor<$device>) -> Self::Output {
+                Tensor::<$device>::$method(self, rhs).unwrap()
+            }
+        }
+
+        ///The scalar is cast to the DType of the tensor.
+        impl<T: Castable> $trait<T> for &Tensor<$device> {
+            type Output = Tensor<$device>;
+
+            fn $method(self, rhs: T) -> Self::Output {
+                let rhs = self.scalar_like(rhs).unwrap();
+                Tensor::<$device>::$method(self, &rhs).unwrap()
+            }
+        }
+    };
+}
+
+binary_trait!(CPU, Add, add);
+binary_trait!(CPU, Sub, sub);
+binary_trait!(CPU, Mul, mul);
+binary_trait!(CPU, Div, div);
+binary_trait!(WebGPU, Add, add);
+binary_trait!(WebGPU, Sub, sub);
+binary_trait!(WebGPU, Mul, mul);
+binary_trait!(WebGPU, Div, div);
+
+macro_rules! scalar_lhs {
+    ($device:ty; $($t:ty),*) => {
+        $(
+            impl Add<&Tensor<$device>> for $t {
+                type Output = Tensor<$device>;
+
+                fn add(self, rhs: &Tensor<$device>) -> Self::Output {
+                    let lhs = rhs.scalar_like(self).unwrap();
+                    Tensor::<$device>::add(&lhs, rhs).unwrap()
+                }
+            }
+
+            impl Sub<&Tensor<$device>> for $t {
+                type Output = Tensor<$device>;
+
+                fn sub(self, rhs: &Tensor<$device>) -> Self::Output {
+                    let lhs = rhs.scalar_like(self).unwrap();
+                    Tensor::<$device>::sub(&lhs, rhs).unwrap()
+                }
+            }
+
+            impl Mul<&Tensor<$device>> for $t {
+                type Output = Tensor<$device>;
+
+                fn mul(self, rhs: &Tensor<$device>) -> Self::Output {
+                    let lhs = rhs.scalar_like(self).unwrap();
+                    Tensor::<$device>::mul(&lhs, rhs).unwrap()
+                }
+            }
+
+            impl Div<&Tensor<$device>> for $t {
+                type Output = Tensor<$device>;
+
+                fn div(self, rhs: &Tensor<$device>) -> Self::Output {
+                    let lhs = rhs.scalar_like(self).unwrap();
+                    Tensor::<$device>::div(&lhs, rhs).unwrap()
+                }
+            }
+        )*
+    };
+}
+
+scalar_lhs!(CPU; u8, u16, u32, u64, i8, i16, i32, i64, f16, bf16, f32, f64);
+scalar_lhs!(WebGPU; u8, u16, u32, u64, i8, i16, i32, i64, f16, bf16, f32, f64);
+
+///Combines two strided inputs elementwise into a packed output.
+///Metadata layout: `[numel, lhs layout.., rhs layout..]`, both layouts having the same rank.
+struct BinaryKernel;
+
+impl BinaryKernel {
+    ///WGSL helpers needed by [`BinaryKernel::expression`], for `dt`.
+    fn helpers(dt: DType) -> &'static str {
+        match dt {
+            DType::F32 => {
+                r#"
+const NAN = 0x7fc00000u;
+
+fn power(a: f32, b: f32) -> f32 {
+    if (b == 0.0 || a == 1.0) {
+        return 1.0;
+    }
+    if (a != a || b != b) {
+        return bitcast<f32>(NAN);
+    }
+    if (a < 0.0) {
+        if (floor(b) != b) {
+            return bitcast<f32>(NAN);
+        }
+        let r = pow(-a, b);
+        let odd = abs(b) < 16777216.0 && fract(b * 0.5) != 0.0;
+        return select(r, -r, odd);
+    }
+    return pow(a, b);
+}
+
+fn maximum(a: f32, b: f32) -> f32 {
+    return select(max(a, b), bitcast<f32>(NAN), a != a || b != b);
+}
+
+fn minimum(a: f32, b: f32) -> f32 {
+    return select(min(a, b), bitcast<f32>(NAN), a != a || b != b);
+}
+"#
+            }
+            DType::I32 => {
+                r#"
+fn divide(a: i32, b: i32) -> i32 {
+    //Avoids the undefined i32::MIN / -1, which wraps to i32::MIN.
+    let d = select(b, 1, b == 0 || (b == -1 && a == -2147483647 - 1));
+    return select(a / d, 0, b == 0);
+}
+
+fn power(a: i32, b: i32) -> i32 {
+    if (b < 0) {
+        if (a == 1) {
+            return 1;
+        }
+        if (a == -1) {
+            return select(-1, 1, b % 2 == 0);
+        }
+        return 0;
+    }
+    var base = a;
+    var e = u32(b);
+    var r = 1;
+    while (e != 0u) {
+        if ((e & 1u) == 1u) {
+            r *= base;
+        }
+        base *= base;
+        e >>= 1u;
+    }
+    return r;
+}
+"#
+            }
+            _ => {
+                r#"
+fn divide(a: u32, b: u32) -> u32 {
+    return select(a / select(b, 1u, b == 0u), 0u, b == 0u);
+}
+
+fn power(a: u32, b: u32) -> u32 {
+    var base = a;
+    var e = b;
+    var r = 1u;
+    while (e != 0u) {
+        if ((e & 1u) == 1u) {
+            r *= base;
+        }
+        base *= base;
+        e >>= 1u;
+    }
+    return r;
+}
+"#
+            }
+        }
+    }
+
+    ///WGSL expression computing `op` of `a` and `b`.
+    fn expression(op: BinaryOp, dt: DType) -> &'static str {
+        match (op, dt) {
+            (BinaryOp::Add, _) => "a + b",
+            (BinaryOp::Sub, _) => "a - b",
+            (BinaryOp::Mul, _) => "a * b",
+            (BinaryOp::Div, DType::F32) => "a / b",
+            (BinaryOp::Div, _) => "divide(a, b)",
+            (BinaryOp::Pow, _) => "power(a, b)",
+            (BinaryOp::Maximum, DType::F32) => "maximum(a, b)",
+            (BinaryOp::Maximum, _) => "max(a, b)",
+            (BinaryOp::Minimum, DType::F32) => "minimum(a, b)",
+            (BinaryOp::Minimum, _) => "min(a, b)",
+        }
+    }
+
+    fn source(op: BinaryOp, dt: DType) -> Result<String, TensorError> {
+        let ty = wgsl::scalar_type(dt).ok_or(TensorError::UnsupportedDType(dt))?;
+        Ok(format!(
+            r#"
+@group(0) @binding(0) var<storage, read> lhs: array<{ty}>;
+@group(0) @binding(1) var<storage, read> rhs: array<{ty}>;
+@group(0) @binding(2) var<storage, read_write> dst: array<{ty}>;
+{meta}{global_index}{strided_offset}{helpers}
+fn op(a: {ty}, b: {ty}) -> {ty} {{
+    return {expr};
+}}
+
+@compute @workgroup_size({wg})
+fn main(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {{
+    let i = global_index(gid, groups);
+    if (i >= param(0u)) {{
+        return;
+    }}
+    let rhs_base = 3u + 2u * param(1u);
+    dst[i] = op(lhs[strided_offset(1u, i)], rhs[strided_offset(rhs_base, i)]);
+}}
+"#,
+            meta = Metadata::declaration(3),
+            global_index = wgsl::GLOBAL_INDEX,
+            strided_offset = wgsl::STRIDED_OFFSET,
+            helpers = Self::helpers(dt),
+            expr = Self::expression(op, dt),
+            wg = GPUHandle::WORKGROUP_SIZE,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn broadcasting_ops() {
+        let a = Tensor::<CPU>::new(vec![2, 3].into(), vec![1f32, 2., 3., 4., 5., 6.]).unwrap();
+        let b = Tensor::<CPU>::new(vec![3].into(), vec![10f32, 20., 30.]).unwrap();
+        let col = Tensor::<CPU>::new(vec![2, 1].into(), vec![1f32, 2.]).unwrap();
+
+        assert_eq!(
+            (&a + &b).to_vec::<f32>().unwrap(),
+            vec![11., 22., 33., 14., 25., 36.]
+        );
+        assert_eq!(
+            (&a * &col).to_vec::<f32>().unwrap(),
+            vec![1., 2., 3., 8., 10., 12.]
+        );
+        assert_eq!(
+            (&a - 1.0).to_vec::<f32>().unwrap(),
+            vec![0., 1., 2., 3., 4., 5.]
+        );
+        assert_eq!(
+            (12f32 / &a).to_vec::<f32>().unwrap(),
+            vec![12., 6., 4., 3., 2.4, 2.]
+        );
+        assert_eq!(
+            a.transpose(0, 1)
+                .unwrap()
+                .minimum(&col.transpose(0, 1).unwrap())
+                .unwrap()
+                .to_vec::<f32>()
+                .unwrap(),
+            vec![1., 2., 1., 2., 1., 2.]
+        );
+        assert!(a.add(&col.transpose(0, 1).unwrap()).is_err());
+    }
+
+    #[test]
+    fn integer_semantics() {
+        let a = Tensor::<CPU>::new(vec![4].into(), vec![250u8, 7, 2, 3]).unwrap();
+        let b = Tensor::<CPU>::new(vec![4].into(), vec![10u8, 0, 3, 2]).unwrap();
+        assert_eq!(a.add(&b).unwrap().to_vec::<u8>().unwrap(), vec![4, 7, 5, 5]);
+        assert_eq!(
+            a.div(&b).unwrap().to_vec::<u8>().unwrap(),
+            vec![25, 0, 0, 1]
+        );
+        assert_eq!(a.pow(&b).unwrap().to_vec::<u8>().unwrap()[2..], [8, 9]);
+        assert_eq!(
+            a.minimum(&b).unwrap().to_vec::<u8>().unwrap(),
+            vec![10, 0, 2, 2]
+        );
+
+        let f = Tensor::<CPU>::new(vec![2].into(), vec![1f32, 2.]).unwrap();
+        assert!(matches!(a.add(&f), Err(TensorError::DTypeMismatch { .. })));
+    }
+
+    #[tokio::test]
+    async fn gpu_binary_matches_cpu() {
+        let device = Arc::new(WebGPU::new().await.unwrap());
+        let a =
+            Tensor::<CPU>::new(vec![2, 3].into(), vec![1f32, -2., 3., 0.5, f32::NAN, 6.]).unwrap();
+        let b = Tensor::<CPU>::new(vec![3, 1, 1].into(), vec![2f32, -3., 0.25]).unwrap();
+        let gpu_a = a.clone().to(&device).unwrap();
+
+        let ops = [
+            BinaryOp::Add,
+            BinaryOp::Sub,
+            BinaryOp::Mul,
+            BinaryOp::Div,
+            BinaryOp::Pow,
+            BinaryOp::Maximum,
+            BinaryOp::Minimum,
+        ];
+        let rhs = Tensor::<WebGPU>::from_bytes(
+            gpu_a.storage().device().clone(),
+            DType::F32,
+            b.shape().clone(),
+            bytemuck::cast_slice(b.as_slice::<f32>().unwrap()),
+        )
+        .unwrap();
+        for op in ops {
+            let expected = a.binary(&b, op).unwrap().to_vec::<f32>().unwrap();
+            let result = gpu_a.binary(&rhs, op).unwrap().to(&Arc::new(CPU)).unwrap();
+            assert_eq!(result.shape(), &Shape::from(vec![3, 2, 3]));
+            for (r, e) in result.to_vec::<f32>().unwrap().iter().zip(&expected) {
+                assert!(
+                    (r.is_nan() && e.is_nan()) || (r - e).abs() <= 1e-5 * e.abs(),
+                    "{:?}: {} != {}",
+                    op,
+                    r,
+                    e
+                );
+            }
+        }
+        let shifted = (&gpu_a - 1.0).to(&Arc::new(CPU)).unwrap();
+        assert_eq!(shifted.to_vec::<f32>().unwrap()[..4], [0., -3., 2., -0.5]);
+
+        //Two layouts of rank 31 don't fit in the kernel metadata.
+        let deep = Tensor::<CPU>::new(vec![1; 31].into(), vec![1f32])
+            .unwrap()
+            .to(&device)
+            .unwrap();
+        assert!(matches!(
+            deep.add(&deep),
+            Err(TensorError::MetadataOverflow(129))
+        ));
+    }
+
+    #[tokio::test]
+    async fn gpu_integer_semantics() {
+        let a = Tensor::<CPU>::new(vec![5].into(), vec![i32::MIN, 7, -2, 3, i32::MAX]).unwrap();
+        let b = Tensor::<CPU>::new(vec![5].into(), vec![-1i32, 0, 3, -2, 2]).unwrap();
+        let gpu_a = a
+            .clone()
+            .to(&Arc::new(WebGPU::new().await.unwrap()))
+            .unwrap();
+        let gpu_b = Tensor::<WebGPU>::from_bytes(
+            gpu_a.storage().device().clone(),
+            DType::I32,
+            b.shape().clone(),
+            bytemuck::cast_slice(b.as_slice::<i32>().unwrap()),
+        )
+        .unwrap();
+        for op in [BinaryOp::Add, BinaryOp::Mul, BinaryOp::Div, BinaryOp::Pow] {
+            assert_eq!(
+                gpu_a
+                    .binary(&gpu_b, op)
+                    .unwrap()
+                    .to(&Arc::new(CPU))
+                    .unwrap(),
+                a.binary(&b, op).unwrap(),
+                "{:?}",
+                op
+            );
+        }
+        let halves = gpu_a.cast(DType::F16).unwrap();
+        assert!(matches!(
+            halves.add(&halves),
+            Err(TensorError::UnsupportedDType(DType::F16))
+        ));
+    }
+}
@@ -0,0 +1,287 @@
+use crate::{
+    as_std, wgsl, Castable, DType, GPUHandle, GradOp, Kernel, KernelArg, Metadata, Shape, TData,
+    Tensor, TensorError, WebGPU, Workgroups, CPU,
+};
+use num_traits::AsPrimitive;
+
+///Casting follows Rust's `as` semantics, see [`Castable`]:
+///* float -> int: truncates toward zero, saturating at the bounds of the target. NaN maps to 0.
+///* int -> int: wraps, keeping the low bits.
+///* any -> float: rounds to the nearest representable value.
+impl Tensor<CPU> {
+    pub fn cast(&self, dt: DType) -> Result<Tensor<CPU>, TensorError> {
+        fn cast_from<S: Castable>(
+            tensor: &Tensor<CPU>,
+            dt: DType,
+        ) -> Result<Tensor<CPU>, TensorError> {
+            let owned;
+            let src = if tensor.is_contiguous() {
+                tensor.as_slice::<S>()?
+            } else {
+                owned = tensor.to_vec::<S>()?;
+                &owned
+            };
+            as_std!(cast_slice<S>(dt)(src, tensor.shape().clone()))
+        }
+        fn cast_slice<S: AsPrimitive<T>, T: TData>(
+            src: &[S],
+            shape: Shape,
+        ) -> Result<Tensor<CPU>, TensorError> {
+            Tensor::new(shape, src.iter().map(|s| s.as_()).collect())
+        }
+        let dst = as_std!(cast_from(self.dt())(self, dt))?;
+        Ok(dst.record(GradOp::Cast, &[self]))
+    }
+}
+
+///Casting on the GPU matches [`Tensor::<CPU>::cast`] for every DType of 32 bits or less.
+///64 bit types have no WGSL representation, and return [`TensorError::UnsupportedDType`].
+///Float -> half conversions round to nearest, ties may differ from the CPU by 1 ULP.
+impl Tensor<WebGPU> {
+    pub fn cast(&self, dt: DType) -> Result<Tensor<WebGPU>, TensorError> {
+        if !self.is_packed() {
+            return self.contiguous()?.cast(dt);
+        }
+        let src_dt = self.dt();
+        let source = CastKernel::source(src_dt, dt)?;
+        let numel = self.numel();
+        let dst = Tensor::uninit(self.storage().device().clone(), dt, self.shape().clone())?;
+        if numel > 0 {
+            let handle = self.device().handle();
+            let meta = Metadata::new().push(numel).upload(handle)?;
+            let words = (numel * dt.size_of()).div_ceil(4);
+            Kernel::new("cast", source, src_dt).dispatch(
+                handle,
+                &[
+                    KernelArg::tensor(self),
+                    KernelArg::output(&dst),
+                    KernelArg::uniform(&meta),
+                ],
+                Workgroups::linear(words),
+            );
+        }
+        Ok(dst.record(GradOp::Cast, &[self]))
+    }
+}
+
+///Generates and runs a WGSL shader converting between two DTypes.
+///Elements are read from and written to packed u32 words, so that 8 and 16 bit
+///types can be handled without shader extensions.
+struct CastKernel;
+
+impl CastKernel {
+    fn bits(dt: DType) -> Result<u32, TensorError> {
+        match dt {
+            DType::U8 | DType::I8 => Ok(8),
+            DType::U16 | DType::I16 | DType::F16 | DType::BF16 => Ok(16),
+            DType::U32 | DType::I32 | DType::F32 => Ok(32),
+            DType::U64 | DType::I64 | DType::F64 | DType::Q4_0 | DType::Q8_0 => {
+                Err(TensorError::UnsupportedDType(dt))
+            }
+        }
+    }
+
+    fn mask(bits: u32) -> String {
+        format!("0x{:X}u", u32::MAX >> (32 - bits))
+    }
+
+    ///Type used to hold an element of `dt` inside the shader.
+    fn domain(dt: DType) -> &'static str {
+        match dt {
+            DType::U8 | DType::U16 | DType::U32 | DType::U64 => "u32",
+            DType::I8 | DType::I16 | DType::I32 | DType::I64 => "i32",
+            DType::F16 | DType::BF16 | DType::F32 | DType::F64 => "f32",
+            DType::Q4_0 | DType::Q8_0 => "f32",
+        }
+    }
+
+    ///WGSL for `fn load(i: u32) -> domain`, reading element `i` of the source.
+    fn load(dt: DType) -> Result<String, TensorError> {
+        let bits = Self::bits(dt)?;
+        let per_word = 32 / bits;
+        let unpack = match dt {
+            DType::U8 | DType::U16 | DType::U32 => "raw".to_string(),
+            DType::I8 | DType::I16 | DType::I32 => {
+                format!("bitcast<i32>(raw << {0}u) >> {0}u", 32 - bits)
+            }
+            DType::F16 => "unpack2x16float(raw).x".to_string(),
+            DType::BF16 => "bitcast<f32>(raw << 16u)".to_string(),
+            DType::F32 => "bitcast<f32>(raw)".to_string(),
+            _ => unreachable!(),
+        };
+        Ok(format!(
+            r#"
+fn load(i: u32) -> {domain} {{
+    let word = src[i / {per_word}u];
+    let raw = (word >> ((i % {per_word}u) * {bits}u)) & {mask};
+    return {unpack};
+}}"#,
+            domain = Self::domain(dt),
+            mask = Self::mask(bits),
+        ))
+    }
+
+    ///WGSL for `fn convert(v: src_domain) -> u32`, producing the bits of the destination element.
+    fn convert(src: DType, dst: DType) -> Result<String, TensorError> {
+        let bits = Self::bits(dst)?;
+        let src_domain = Self::domain(src);
+        let body = match (src_domain, Self::domain(dst)) {
+            (_, "f32") => {
+                let x = if src_domain == "f32" { "v" } else { "f32(v)" };
+                match dst {
+                    DType::F32 => format!("return bitcast<u32>({x});"),
+                    //`pack2x16float` is indeterminate outside the finite f16 range,
+                    //NaN and overflow are handled as `f16::from_f32` rounds them.
+                    DType::F16 => format!(
+                        r#"let x = {x};
+    let sign = (bitcast<u32>(x) >> 16u) & 0x8000u;
+    if (x != x) {{
+        return sign | 0x7E00u;
+    }}
+    if (abs(x) >= 65520.0) {{
+        return sign | 0x7C00u;
+    }}
+    return pack2x16float(vec2<f32>(clamp(x, -65504.0, 65504.0), 0.0)) & 0xFFFFu;"#
+                    ),
+                    DType::BF16 => format!(
+                        r#"let x = {x};
+    let b = bitcast<u32>(x);
+    if (x != x) {{
+        return (b >> 16u) | 0x40u;
+    }}
+    return (b + 0x7FFFu + ((b >> 16u) & 1u)) >> 16u;"#
+                    ),
+                    _ => unreachable!(),
+                }
+            }
+            ("f32", "u32") => format!(
+                r#"let x = select(v, 0.0, v != v);
+    return select(u32(max(x, 0.0)), {mask}, x >= {limit:.1});"#,
+                mask = Self::mask(bits),
+                limit = 2f64.powi(bits as i32),
+            ),
+            ("f32", "i32") => format!(
+                r#"let x = select(v, 0.0, v != v);
+    let i = select(i32(max(x, {lo:.1})), {hi}, x >= {limit:.1});
+    return bitcast<u32>(i) & {mask};"#,
+                lo = -(2f64.powi(bits as i32 - 1)),
+                hi = (1i64 << (bits - 1)) - 1,
+                limit = 2f64.powi(bits as i32 - 1),
+                mask = Self::mask(bits),
+            ),
+            _ => format!("return bitcast<u32>(v) & {};", Self::mask(bits)),
+        };
+        Ok(format!(
+            r#"
+fn convert(v: {src_domain}) -> u32 {{
+    {body}
+}}"#
+        ))
+    }
+
+    fn source(src: DType, dst: DType) -> Result<String, TensorError> {
+        let bits = Self::bits(dst)?;
+        Ok(format!(
+            r#"
+@group(0) @binding(0) var<storage, read> src: array<u32>;
+@group(0) @binding(1) var<storage, read_write> dst: array<u32>;
+{meta}{global_index}{load}
+{convert}
+
+@compute @workgroup_size({wg})
+fn main(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {{
+    let numel = param(0u);
+    let word = global_index(gid, groups);
+    let words = (numel + {per_word}u - 1u) / {per_word}u;
+    if (word >= words) {{
+        return;
+    }}
+    var result = 0u;
+    for (var k = 0u; k < {per_word}u; k++) {{
+        let i = word * {per_word}u + k;
+        if (i < numel) {{
+            result |= convert(load(i)) << (k * {bits}u);
+        }}
+    }}
+    dst[word] = result;
+}}
+"#,
+            meta = Metadata::declaration(2),
+            global_index = wgsl::GLOBAL_INDEX,
+            load = Self::load(src)?,
+            convert = Self::convert(src, dst)?,
+            wg = GPUHandle::WORKGROUP_SIZE,
+            per_word = 32 / bits,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn cpu_cast_semantics() {
+        let src =
+            Tensor::<CPU>::new(vec![5].into(), vec![-1.7f32, 2.5, 300.0, f32::NAN, -1e10]).unwrap();
+        let as_u8 = src.cast(DType::U8).unwrap();
+        assert_eq!(as_u8.as_slice::<u8>().unwrap(), &[0, 2, 255, 0, 0]);
+        let as_i32 = src.cast(DType::I32).unwrap();
+        assert_eq!(
+            as_i32.as_slice::<i32>().unwrap(),
+            &[-1, 2, 300, 0, i32::MIN]
+        );
+
+        let ints = Tensor::<CPU>::new(vec![2].into(), vec![300i32, -1]).unwrap();
+        let wrapped = ints.cast(DType::U8).unwrap();
+        assert_eq!(wrapped.as_slice::<u8>().unwrap(), &[44, 255]);
+        let halves = ints.cast(DType::F16).unwrap();
+        assert_eq!(
+            halves.cast(DType::F64).unwrap().to_vec::<f64>().unwrap(),
+            vec![300., -1.]
+        );
+    }
+
+    #[tokio::test]
+    async fn gpu_cast_matches_cpu() {
+        let device = Arc::new(WebGPU::new().await.unwrap());
+        let data = vec![
+            -1.7f32,
+            2.5,
+            300.0,
+            f32::NAN,
+            -1e10,
+            65504.0,
+            1e-3,
+            65519.0,
+            -65520.0,
+        ];
+        let src = Tensor::<CPU>::new(vec![9].into(), data.clone()).unwrap();
+        let gpu = Tensor::<CPU>::new(vec![9].into(), data)
+            .unwrap()
+            .to(&device)
+            .unwrap();
+        let dts = [
+            DType::U8,
+            DType::I8,
+            DType::U16,
+            DType::I16,
+            DType::U32,
+            DType::I32,
+            DType::F16,
+            DType::BF16,
+        ];
+        for dt in dts {
+            let expected_back = src.cast(dt).unwrap().cast(DType::F32).unwrap();
+            let back = gpu.cast(dt).unwrap().cast(DType::F32).unwrap();
+            let returned = back.to(&Arc::new(CPU)).unwrap();
+            assert_eq!(
+                format!("{}", returned),
+                format!("{}", expected_back),
+                "Mismatch casting to {:?}",
+                dt
+            );
+        }
+    }
+}
@@ -1,16 +1,44 @@
-use crate::{AllocMode, Device, DeviceAllocator, DeviceError, DevicePrimitive};
+use crate::{AllocMode, Device, DeviceAllocator, DeviceError, DeviceId, DevicePrimitive};
+use memmap2::Mmap;
+use std::sync::Arc;
 
 ///The CPU primitive for storing data.
 ///Much like a slice, but owned.
+///It either owns a heap allocation, or borrows a read-only region of a memory-mapped file,
+///which it keeps alive.
 #[derive(Debug)]
 pub struct CPUPrim {
     ptr: *mut u8,
     len: usize,
+    mapping: Option<Arc<Mmap>>,
 }
 
+//SAFETY: the memory is either owned exclusively by the primitive, or read-only,
+//and mutable access to it requires `&mut CPUPrim`.
+unsafe impl Send for CPUPrim {}
+unsafe impl Sync for CPUPrim {}
+
 impl CPUPrim {
     pub fn new(ptr: *mut u8, len: usize) -> Self {
-        Self { ptr, len }
+        Self {
+            ptr,
+            len,
+            mapping: None,
+        }
+    }
+
+    ///Borrows `len` bytes of `mapping` starting at `ptr`, which must lie within it.
+    pub(crate) fn mapped(mapping: Arc<Mmap>, ptr: *const u8, len: usize) -> Self {
+        Self {
+            ptr: ptr as *mut u8,
+            len,
+            mapping: Some(mapping),
+        }
+    }
+
+    ///Whether the memory belongs to a memory-mapped file, in which case it is read-only.
+    pub fn is_mapped(&self) -> bool {
+        self.mapping.is_some()
     }
 
     pub fn len(&self) -> usize {
@@ -21,6 +49,10 @@ impl CPUPrim {
         self.ptr as *const T
     }
 
+    pub fn as_mut_ptr<T>(&mut self) -> *mut T {
+        self.ptr as *mut T
+    }
+
     pub fn is_empty(&self) -> bool {
         self.len == 0
     }
@@ -30,31 +62,34 @@ impl DeviceAllocator for CPU {
     type Prim = CPUPrim;
 
     unsafe fn alloc(&self, layout: std::alloc::Layout, _mode: AllocMode) -> Self::Prim {
-        Self::Prim {
-            ptr: unsafe { std::alloc::alloc(layout) },
-            len: layout.size(),
-        }
+        //The global allocator does not support zero sized allocations.
+        let ptr = if layout.size() == 0 {
+            std::ptr::without_provenance_mut(layout.align())
+        } else {
+            unsafe { std::alloc::alloc(layout) }
+        };
+        Self::Prim::new(ptr, layout.size())
     }
 
     unsafe fn alloc_init(
         &self,
         layout: std::alloc::Layout,
         init: &[u8],
-        _mode: AllocMode,
+        mode: AllocMode,
     ) -> Self::Prim {
         if layout.size() != init.len() {
             panic!("Layout size does not match init size");
         }
-        let ptr = unsafe { std::alloc::alloc(layout) };
-        unsafe { std::ptr::copy_nonoverlapping(init.as_ptr(), ptr, init.len()) };
-        Self::Prim {
-            ptr,
-            len: layout.size(),
-        }
+        let prim = unsafe { self.alloc(layout, mode) };
+        unsafe { std::ptr::copy_nonoverlapping(init.as_ptr(), prim.ptr, init.len()) };
+        prim
     }
 
-    unsafe fn dealloc(&self, item: &mut Self::Prim, layout: std::alloc::Layout) {
-        unsafe { std::alloc::dealloc(item.ptr, layout) };
+    unsafe fn dealloc(&self, item: Self::Prim, layout: std::alloc::Layout) {
+        //Mapped memory is released when the last primitive referencing the mapping is dropped.
+        if layout.size() != 0 && !item.is_mapped() {
+            unsafe { std::alloc::dealloc(item.ptr, layout) };
+        }
     }
 }
 
@@ -65,13 +100,17 @@ impl DevicePrimitive for CPUPrim {
 }
 
 ///Default device
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct CPU;
 
 impl Device for CPU {
     type Prim = CPUPrim;
     type Allocator = CPU;
 
+    fn id(&self) -> DeviceId {
+        DeviceId::CPU
+    }
+
     fn copy_from_host(&self, src: &[u8], dst: &mut Self::Prim) -> Result<(), DeviceError> {
         if src.len() != dst.len() {
             return Err(DeviceError::CopyMismatch(src.len(), dst.len()));
@@ -88,6 +127,21 @@ impl Device for CPU {
         Ok(())
     }
 
+    ///Host memory is uploaded directly, without the intermediate copy of the default implementation.
+    fn copy_to<Ext: Device>(
+        &self,
+        src: &Self::Prim,
+        dst: &mut Ext::Prim,
+        ext: &Ext,
+    ) -> Result<(), DeviceError> {
+        let len = src.len().min(dst.len());
+        if len == 0 {
+            return Ok(());
+        }
+        let src = unsafe { std::slice::from_raw_parts(src.ptr, len) };
+        ext.copy_from_host(src, dst)
+    }
+
     fn allocate(
         &self,
         layout: std::alloc::Layout,
@@ -96,11 +150,7 @@ impl Device for CPU {
         unsafe { Ok(Self::Allocator::alloc(self, layout, mode)) }
     }
 
-    fn deallocate(
-        &self,
-        item: &mut Self::Prim,
-        layout: std::alloc::Layout,
-    ) -> Result<(), DeviceError> {
+    fn deallocate(&self, item: Self::Prim, layout: std::alloc::Layout) -> Result<(), DeviceError> {
         unsafe { Self::Allocator::dealloc(self, item, layout) };
         Ok(())
     }
@@ -2,7 +2,9 @@ use crate::AllocMode;
 
 use std::alloc::Layout;
 use std::fmt::Debug;
+use std::future::Future;
 use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 #[derive(thiserror::Error, Debug)]
 pub enum DeviceError {
@@ -14,11 +16,56 @@ pub enum DeviceError {
     TransferError(String),
     #[error("Failed to obtain required resource: {0}")]
     ResourceError(#[from] anyhow::Error),
+    #[error("Memory allocated on {actual} cannot be used by {expected}")]
+    ForeignPrimitive {
+        expected: &'static str,
+        actual: &'static str,
+    },
+}
+
+static DEVICE_COUNT: AtomicU64 = AtomicU64::new(1);
+
+///Identifies a device instance.
+///Memory allocated by one instance cannot be used by another, even of the same kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceId {
+    kind: &'static str,
+    index: u64,
+}
+
+impl DeviceId {
+    ///There is a single host, all CPU instances are interchangeable.
+    pub const CPU: DeviceId = DeviceId {
+        kind: "CPU",
+        index: 0,
+    };
+
+    ///Allocates a new, unique id for a device of the given kind.
+    pub fn new(kind: &'static str) -> Self {
+        Self {
+            kind,
+            index: DEVICE_COUNT.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        self.kind
+    }
+}
+
+impl std::fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if *self == Self::CPU {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{}#{}", self.kind, self.index)
+        }
+    }
 }
 
 ///Device is an abstraction for a device on which memory can be allocated.
 ///Devices only work on bytes, storage handles higher level types.
-pub trait Device {
+pub trait Device: Send + Sync {
     ///The allocator used to allocate memory on the device.
     ///* CPU: [`std::alloc::System`]
     ///* WEBGPU: [`wgpu::Device`]
@@ -27,27 +74,51 @@ pub trait Device {
     ///* CPU: [`CPUPrim`]
     ///* WEBGPU: [`wgpu::Buffer`]
     type Prim: DevicePrimitive;
+    ///Tensors can only be combined when their devices have equal ids.
+    fn id(&self) -> DeviceId;
     fn copy_from_host(&self, src: &[u8], dst: &mut Self::Prim) -> Result<(), DeviceError>;
     fn copy_to_host(&self, src: &Self::Prim, dst: &mut [u8]) -> Result<(), DeviceError>;
+    ///Like [`Device::copy_to_host`], without blocking the calling thread while the device
+    ///catches up. Dropping the future cancels the transfer, leaving `src` intact.
+    ///Defaults to the blocking copy, for devices whose memory is readily accessible.
+    fn copy_to_host_async(
+        &self,
+        src: &Self::Prim,
+        dst: &mut [u8],
+    ) -> impl Future<Output = Result<(), DeviceError>> + Send {
+        async move { self.copy_to_host(src, dst) }
+    }
+    ///Devices specialize the transfers they can perform directly,
+    ///falling back to [`copy_through_host`].
     fn copy_to<Ext: Device>(
         &self,
         src: &Self::Prim,
         dst: &mut Ext::Prim,
         ext: &Ext,
     ) -> Result<(), DeviceError> {
-        //Default implementation does a roundtrip through the host.
-        let mut buf: Vec<MaybeUninit<u8>> = Vec::with_capacity(src.len());
-        unsafe {
-            buf.set_len(src.len());
-        }
-        let buf_slice =
-            unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, src.len()) };
-        self.copy_to_host(src, buf_slice)?;
-        ext.copy_from_host(buf_slice, dst)?;
-        Ok(())
+        copy_through_host(self, src, dst, ext)
     }
     fn allocate(&self, layout: Layout, mode: AllocMode) -> Result<Self::Prim, DeviceError>;
-    fn deallocate(&self, item: &mut Self::Prim, layout: Layout) -> Result<(), DeviceError>;
+    fn deallocate(&self, item: Self::Prim, layout: Layout) -> Result<(), DeviceError>;
+}
+
+///Copies `src` to `dst` with a roundtrip through the host.
+///Primitives may be padded (e.g wgpu::Buffer), so only the common prefix is copied.
+pub fn copy_through_host<D: Device + ?Sized, Ext: Device>(
+    device: &D,
+    src: &D::Prim,
+    dst: &mut Ext::Prim,
+    ext: &Ext,
+) -> Result<(), DeviceError> {
+    let len = src.len().min(dst.len());
+    let mut buf: Vec<MaybeUninit<u8>> = Vec::with_capacity(len);
+    unsafe {
+        buf.set_len(len);
+    }
+    let buf_slice = unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, len) };
+    device.copy_to_host(src, buf_slice)?;
+    ext.copy_from_host(buf_slice, dst)?;
+    Ok(())
 }
 
 ///DeviceAllocator is similar to [`std::alloc::GlobalAlloc`], but allows different allocation modes.
@@ -65,17 +136,21 @@ pub trait DeviceAllocator {
     ///* The memory must be properly aligned.
     ///* The data must be of the correct length.
     unsafe fn alloc_init(&self, layout: Layout, init: &[u8], mode: AllocMode) -> Self::Prim;
-    ///Deallocates memory on the device.
+    ///Deallocates memory on the device, taking ownership of the primitive.
     ///# Safety
     ///* The memory must be properly aligned.
-    unsafe fn dealloc(&self, item: &mut Self::Prim, layout: Layout);
+    unsafe fn dealloc(&self, item: Self::Prim, layout: Layout);
 }
 
 ///Marker trait allowing for runtime type checking of device primitives.
-pub trait DevicePrimitive: Debug {
+pub trait DevicePrimitive: Debug + Send + Sync {
     ///Returns the size of the primitive in bytes.
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
+    ///The buffer backing the primitive, if it lives on a WebGPU device.
+    fn as_wgpu_buffer(&self) -> Option<&wgpu::Buffer> {
+        None
+    }
 }
@@ -1,7 +1,8 @@
+use num_traits::AsPrimitive;
 use std::fmt::{Debug, Display};
 
 /// Data types for tensors.
-#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
 pub enum DType {
     U8,
     U16,
@@ -12,9 +13,14 @@ pub enum DType {
     I32,
     I64,
     F16,
+    BF16,
     #[default]
     F32,
     F64,
+    ///Blocks of 32 4-bit values sharing an f16 scale, see [`crate::BlockQ4_0`].
+    Q4_0,
+    ///Blocks of 32 8-bit values sharing an f16 scale, see [`crate::BlockQ8_0`].
+    Q8_0,
 }
 
 macro_rules! dtype {
@@ -39,13 +45,35 @@ dtype!(i8, I8);
 dtype!(i16, I16);
 dtype!(i32, I32);
 dtype!(i64, I64);
+dtype!(half::f16, F16);
+dtype!(half::bf16, BF16);
 dtype!(f32, F32);
 dtype!(f64, F64);
 
 ///as_std! maps from our DType to the standard library type.
+///An optional leading type, e.g `as_std!(f<S>(dt)(args))`, is passed through as the first generic.
+///Block-quantized dtypes have no element type, for them the enclosing function returns
+///[`TensorError::UnsupportedDType`](crate::TensorError::UnsupportedDType).
 ///Taken from tract
 #[macro_export]
 macro_rules! as_std {
+    ($($path:ident)::* <$s:ty> ($dt:expr) ($($args:expr),*)) => { {
+        match $dt {
+          DType::U8   => $($path)::*::<$s, u8>($($args),*),
+          DType::U16  => $($path)::*::<$s, u16>($($args),*),
+          DType::U32  => $($path)::*::<$s, u32>($($args),*),
+          DType::U64  => $($path)::*::<$s, u64>($($args),*),
+          DType::I8   => $($path)::*::<$s, i8>($($args),*),
+          DType::I16  => $($path)::*::<$s, i16>($($args),*),
+          DType::I32  => $($path)::*::<$s, i32>($($args),*),
+          DType::I64  => $($path)::*::<$s, i64>($($args),*),
+          DType::F16  => $($path)::*::<$s, $crate::half::f16>($($args),*),
+          DType::BF16 => $($path)::*::<$s, $crate::half::bf16>($($args),*),
+          DType::F32  => $($path)::*::<$s, f32>($($args),*),
+          DType::F64  => $($path)::*::<$s, f64>($($args),*),
+          DType::Q4_0 | DType::Q8_0 => return Err($crate::TensorError::UnsupportedDType($dt).into()),
+        }
+    } };
     ($($path:ident)::* ($dt:expr) ($($args:expr),*)) => { {
         match $dt {
           DType::U8   => $($path)::*::<u8>($($args),*),
@@ -56,22 +84,60 @@ macro_rules! as_std {
           DType::I16  => $($path)::*::<i16>($($args),*),
           DType::I32  => $($path)::*::<i32>($($args),*),
           DType::I64  => $($path)::*::<i64>($($args),*),
-          DType::F16  => $($path)::*::<i16>($($args),*),
+          DType::F16  => $($path)::*::<$crate::half::f16>($($args),*),
+          DType::BF16 => $($path)::*::<$crate::half::bf16>($($args),*),
           DType::F32  => $($path)::*::<f32>($($args),*),
           DType::F64  => $($path)::*::<f64>($($args),*),
+          DType::Q4_0 | DType::Q8_0 => return Err($crate::TensorError::UnsupportedDType($dt).into()),
         }
     } }
 }
 
 impl DType {
+    ///Size in bytes of a block of [`DType::block_size`] elements,
+    ///which is a single element for every dtype that is not quantized.
     #[inline]
     pub fn size_of(&self) -> usize {
-        as_std!(std::mem::size_of(self)())
+        match self {
+            DType::U8 | DType::I8 => 1,
+            DType::U16 | DType::I16 | DType::F16 | DType::BF16 => 2,
+            DType::U32 | DType::I32 | DType::F32 => 4,
+            DType::U64 | DType::I64 | DType::F64 => 8,
+            DType::Q4_0 => std::mem::size_of::<crate::BlockQ4_0>(),
+            DType::Q8_0 => std::mem::size_of::<crate::BlockQ8_0>(),
+        }
     }
 
     #[inline]
     pub fn alignment(&self) -> usize {
-        self.size_of()
+        match self {
+            //Blocks start with their f16 scale.
+            DType::Q4_0 | DType::Q8_0 => 2,
+            _ => self.size_of(),
+        }
+    }
+
+    ///Number of elements stored together in a block of [`DType::size_of`] bytes.
+    #[inline]
+    pub fn block_size(&self) -> usize {
+        match self {
+            DType::Q4_0 | DType::Q8_0 => 32,
+            _ => 1,
+        }
+    }
+
+    ///Size in bytes of `numel` elements, which must be a multiple of [`DType::block_size`].
+    #[inline]
+    pub fn byte_len(&self, numel: usize) -> usize {
+        numel / self.block_size() * self.size_of()
+    }
+
+    pub fn is_quantized(&self) -> bool {
+        self.block_size() > 1
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, DType::F16 | DType::BF16 | DType::F32 | DType::F64)
     }
 }
 
@@ -81,3 +147,41 @@ pub trait TData:
     fn name() -> &'static str;
     fn dtype() -> DType;
 }
+
+///Types that can be converted to every [`DType`] with `as` semantics.
+///* float -> int: truncates toward zero, saturating at the bounds of the target. NaN maps to 0.
+///* int -> int: wraps, keeping the low bits.
+///* any -> float: rounds to the nearest representable value.
+pub trait Castable:
+    TData
+    + AsPrimitive<u8>
+    + AsPrimitive<u16>
+    + AsPrimitive<u32>
+    + AsPrimitive<u64>
+    + AsPrimitive<i8>
+    + AsPrimitive<i16>
+    + AsPrimitive<i32>
+    + AsPrimitive<i64>
+    + AsPrimitive<half::f16>
+    + AsPrimitive<half::bf16>
+    + AsPrimitive<f32>
+    + AsPrimitive<f64>
+{
+}
+
+impl<T> Castable for T where
+    T: TData
+        + AsPrimitive<u8>
+        + AsPrimitive<u16>
+        + AsPrimitive<u32>
+        + AsPrimitive<u64>
+        + AsPrimitive<i8>
+        + AsPrimitive<i16>
+        + AsPrimitive<i32>
+        + AsPrimitive<i64>
+        + AsPrimitive<half::f16>
+        + AsPrimitive<half::bf16>
+        + AsPrimitive<f32>
+        + AsPrimitive<f64>
+{
+}
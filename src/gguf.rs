@@ -0,0 +1,490 @@
+use crate::{DType, Shape, Tensor, TensorError, CPU};
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+const MAGIC: &[u8] = b"GGUF";
+
+///Alignment of the data section and of every tensor within it, unless `general.alignment` is set.
+const DEFAULT_ALIGNMENT: usize = 32;
+
+#[derive(thiserror::Error, Debug)]
+pub enum GgufError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Not a GGUF file, magic string is missing")]
+    BadMagic,
+    #[error("Unsupported GGUF version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Unexpected end of file reading {0} at byte {1}")]
+    UnexpectedEof(&'static str, usize),
+    #[error("String at byte {0} is not valid UTF-8")]
+    InvalidUtf8(usize),
+    #[error("Invalid metadata value type {0}")]
+    InvalidValueType(u32),
+    #[error("Invalid alignment {0}, must be a power of two")]
+    InvalidAlignment(u64),
+    #[error("Tensor {name:?} has unsupported GGML type {ggml_type}")]
+    UnsupportedType { name: String, ggml_type: u32 },
+    #[error(
+        "Tensor {name:?} of shape {shape:?} at offset {offset} lies outside of the data section"
+    )]
+    OutOfBounds {
+        name: String,
+        shape: Shape,
+        offset: u64,
+    },
+    #[error("No tensor named {0:?}")]
+    TensorNotFound(String),
+    #[error("Tensor error: {0}")]
+    Tensor(#[from] TensorError),
+}
+
+///A value of the metadata section.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GgufValue {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+    Bool(bool),
+    String(String),
+    Array(Vec<GgufValue>),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+}
+
+impl GgufValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            GgufValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    ///The value of any unsigned or non-negative signed integer.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            GgufValue::U8(v) => Some(v as u64),
+            GgufValue::U16(v) => Some(v as u64),
+            GgufValue::U32(v) => Some(v as u64),
+            GgufValue::U64(v) => Some(v),
+            GgufValue::I8(v) => u64::try_from(v).ok(),
+            GgufValue::I16(v) => u64::try_from(v).ok(),
+            GgufValue::I32(v) => u64::try_from(v).ok(),
+            GgufValue::I64(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    ///The value of any float, widened to f64.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            GgufValue::F32(v) => Some(v as f64),
+            GgufValue::F64(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[GgufValue]> {
+        match self {
+            GgufValue::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+///The DType of a GGML tensor type, if supported.
+fn ggml_dtype(ggml_type: u32) -> Option<DType> {
+    Some(match ggml_type {
+        0 => DType::F32,
+        1 => DType::F16,
+        2 => DType::Q4_0,
+        8 => DType::Q8_0,
+        24 => DType::I8,
+        25 => DType::I16,
+        26 => DType::I32,
+        27 => DType::I64,
+        28 => DType::F64,
+        30 => DType::BF16,
+        _ => return None,
+    })
+}
+
+///An entry of the tensor info table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GgufTensorInfo {
+    pub name: String,
+    ///Row-major shape. GGUF stores dimensions innermost first, they are reversed here.
+    pub shape: Shape,
+    pub ggml_type: u32,
+    ///Offset of the data relative to the start of the data section.
+    pub offset: u64,
+}
+
+impl GgufTensorInfo {
+    ///The dtype of the tensor, or `None` if its GGML type has no [`DType`] equivalent.
+    pub fn dt(&self) -> Option<DType> {
+        ggml_dtype(self.ggml_type)
+    }
+}
+
+///Little-endian cursor over the header of a GGUF file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize, what: &'static str) -> Result<&'a [u8], GgufError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(GgufError::UnexpectedEof(what, self.pos))?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self, what: &'static str) -> Result<[u8; N], GgufError> {
+        Ok(self.take(N, what)?.try_into().unwrap())
+    }
+
+    fn u32(&mut self, what: &'static str) -> Result<u32, GgufError> {
+        Ok(u32::from_le_bytes(self.array(what)?))
+    }
+
+    fn u64(&mut self, what: &'static str) -> Result<u64, GgufError> {
+        Ok(u64::from_le_bytes(self.array(what)?))
+    }
+
+    ///A length, which cannot exceed the remaining bytes since every item takes at least one.
+    fn len(&mut self, what: &'static str) -> Result<usize, GgufError> {
+        let start = self.pos;
+        let len = self.u64(what)?;
+        if len > (self.bytes.len() - self.pos) as u64 {
+            return Err(GgufError::UnexpectedEof(what, start));
+        }
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String, GgufError> {
+        let len = self.len("string")?;
+        let start = self.pos;
+        let bytes = self.take(len, "string")?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| GgufError::InvalidUtf8(start))
+    }
+
+    fn value(&mut self, ty: u32) -> Result<GgufValue, GgufError> {
+        Ok(match ty {
+            0 => GgufValue::U8(u8::from_le_bytes(self.array("u8")?)),
+            1 => GgufValue::I8(i8::from_le_bytes(self.array("i8")?)),
+            2 => GgufValue::U16(u16::from_le_bytes(self.array("u16")?)),
+            3 => GgufValue::I16(i16::from_le_bytes(self.array("i16")?)),
+            4 => GgufValue::U32(self.u32("u32")?),
+            5 => GgufValue::I32(i32::from_le_bytes(self.array("i32")?)),
+            6 => GgufValue::F32(f32::from_le_bytes(self.array("f32")?)),
+            7 => GgufValue::Bool(self.array::<1>("bool")?[0] != 0),
+            8 => GgufValue::String(self.string()?),
+            9 => {
+                let ty = self.u32("array type")?;
+                let len = self.len("array length")?;
+                let values = (0..len).map(|_| self.value(ty)).collect::<Result<_, _>>()?;
+                GgufValue::Array(values)
+            }
+            10 => GgufValue::U64(self.u64("u64")?),
+            11 => GgufValue::I64(i64::from_le_bytes(self.array("i64")?)),
+            12 => GgufValue::F64(f64::from_le_bytes(self.array("f64")?)),
+            _ => return Err(GgufError::InvalidValueType(ty)),
+        })
+    }
+}
+
+///A GGUF model file, as written by llama.cpp.
+///The file is memory-mapped, and its tensors are exposed as read-only views into it.
+#[derive(Debug)]
+pub struct Gguf {
+    pub version: u32,
+    pub metadata: HashMap<String, GgufValue>,
+    ///Tensor infos, in the order of the file.
+    pub tensors: Vec<GgufTensorInfo>,
+    mmap: Arc<Mmap>,
+    data_start: usize,
+}
+
+impl Gguf {
+    ///Maps a `.gguf` file and parses its metadata and tensor info table.
+    ///
+    ///# Safety
+    ///The file must not be modified while it is mapped, see [`memmap2::Mmap::map`].
+    pub unsafe fn open(path: impl AsRef<Path>) -> Result<Self, GgufError> {
+        let file = std::fs::File::open(path)?;
+        let mmap = Arc::new(unsafe { Mmap::map(&file)? });
+        let mut reader = Reader {
+            bytes: &mmap,
+            pos: 0,
+        };
+        if reader.take(4, "magic").ok() != Some(MAGIC) {
+            return Err(GgufError::BadMagic);
+        }
+        //Version 1 used 32 bit lengths and counts.
+        let version = reader.u32("version")?;
+        if !(2..=3).contains(&version) {
+            return Err(GgufError::UnsupportedVersion(version));
+        }
+        let tensor_count = reader.len("tensor count")?;
+        let metadata_count = reader.len("metadata count")?;
+
+        let mut metadata = HashMap::with_capacity(metadata_count);
+        for _ in 0..metadata_count {
+            let key = reader.string()?;
+            let ty = reader.u32("value type")?;
+            metadata.insert(key, reader.value(ty)?);
+        }
+        let mut tensors = Vec::with_capacity(tensor_count);
+        for _ in 0..tensor_count {
+            let name = reader.string()?;
+            let rank = reader.u32("rank")? as usize;
+            let mut dims = (0..rank)
+                .map(|_| reader.u64("dimension").map(|d| d as usize))
+                .collect::<Result<Vec<_>, _>>()?;
+            dims.reverse();
+            tensors.push(GgufTensorInfo {
+                name,
+                shape: dims.into(),
+                ggml_type: reader.u32("tensor type")?,
+                offset: reader.u64("tensor offset")?,
+            });
+        }
+
+        let alignment = match metadata
+            .get("general.alignment")
+            .and_then(GgufValue::as_u64)
+        {
+            Some(a) if a.is_power_of_two() => a as usize,
+            Some(a) => return Err(GgufError::InvalidAlignment(a)),
+            None => DEFAULT_ALIGNMENT,
+        };
+        let data_start = reader.pos.next_multiple_of(alignment);
+        Ok(Self {
+            version,
+            metadata,
+            tensors,
+            mmap,
+            data_start,
+        })
+    }
+
+    pub fn info(&self, name: &str) -> Option<&GgufTensorInfo> {
+        self.tensors.iter().find(|t| t.name == name)
+    }
+
+    ///A read-only view of a tensor, which keeps the file mapped.
+    ///Block-quantized tensors can be expanded with [`Tensor::dequantize`].
+    pub fn tensor(&self, name: &str) -> Result<Tensor<CPU>, GgufError> {
+        let info = self
+            .info(name)
+            .ok_or_else(|| GgufError::TensorNotFound(name.to_string()))?;
+        let dt = info.dt().ok_or_else(|| GgufError::UnsupportedType {
+            name: info.name.clone(),
+            ggml_type: info.ggml_type,
+        })?;
+        let out_of_bounds = || GgufError::OutOfBounds {
+            name: info.name.clone(),
+            shape: info.shape.clone(),
+            offset: info.offset,
+        };
+        let start = usize::try_from(info.offset)
+            .ok()
+            .and_then(|offset| offset.checked_add(self.data_start))
+            .ok_or_else(out_of_bounds)?;
+        let end = info
+            .shape
+            .iter()
+            .try_fold(1usize, |acc, &d| acc.checked_mul(d))
+            .map(|numel| dt.byte_len(numel))
+            .and_then(|len| start.checked_add(len))
+            .filter(|&end| end <= self.mmap.len())
+            .ok_or_else(out_of_bounds)?;
+        Ok(Tensor::<CPU>::from_mmap(
+            self.mmap.clone(),
+            start..end,
+            dt,
+            info.shape.clone(),
+        )?)
+    }
+
+    ///Views every tensor of the file, see [`Gguf::tensor`].
+    pub fn load_all(&self) -> Result<HashMap<String, Tensor<CPU>>, GgufError> {
+        self.tensors
+            .iter()
+            .map(|info| Ok((info.name.clone(), self.tensor(&info.name)?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use half::f16;
+
+    ///Writes a version 3 GGUF file with the default alignment.
+    fn gguf(
+        metadata: &[(&str, u32, Vec<u8>)],
+        tensors: &[(&str, &[u64], u32, Vec<u8>)],
+    ) -> Vec<u8> {
+        fn string(bytes: &mut Vec<u8>, s: &str) {
+            bytes.extend((s.len() as u64).to_le_bytes());
+            bytes.extend(s.as_bytes());
+        }
+        let mut bytes = b"GGUF".to_vec();
+        bytes.extend(3u32.to_le_bytes());
+        bytes.extend((tensors.len() as u64).to_le_bytes());
+        bytes.extend((metadata.len() as u64).to_le_bytes());
+        for (key, ty, value) in metadata {
+            string(&mut bytes, key);
+            bytes.extend(ty.to_le_bytes());
+            bytes.extend(value);
+        }
+        let mut data = vec![];
+        for (name, dims, ty, tensor) in tensors {
+            string(&mut bytes, name);
+            bytes.extend((dims.len() as u32).to_le_bytes());
+            dims.iter().for_each(|d| bytes.extend(d.to_le_bytes()));
+            bytes.extend(ty.to_le_bytes());
+            bytes.extend((data.len() as u64).to_le_bytes());
+            data.extend(tensor);
+            data.resize(data.len().next_multiple_of(32), 0);
+        }
+        bytes.resize(bytes.len().next_multiple_of(32), 0);
+        bytes.extend(data);
+        bytes
+    }
+
+    fn open(bytes: &[u8]) -> Result<Gguf, GgufError> {
+        let path = std::env::temp_dir().join(format!("{}.gguf", nanoid::nanoid!()));
+        std::fs::write(&path, bytes).unwrap();
+        let gguf = unsafe { Gguf::open(&path) };
+        std::fs::remove_file(&path).unwrap();
+        gguf
+    }
+
+    #[test]
+    fn reads_metadata_and_quantized_tensors() {
+        let mut name = 4u64.to_le_bytes().to_vec();
+        name.extend(b"tiny");
+        let mut layers = 4u32.to_le_bytes().to_vec();
+        layers.extend(2u64.to_le_bytes());
+        layers.extend(1u32.to_le_bytes());
+        layers.extend(2u32.to_le_bytes());
+
+        let dense = (0..6).map(|i| i as f32).collect::<Vec<_>>();
+        let q8 = BlockQ8_0 {
+            d: f16::from_f32(0.5),
+            qs: std::array::from_fn(|i| i as i8 - 16),
+        };
+        let q4 = BlockQ4_0 {
+            d: f16::from_f32(2.),
+            qs: [0x9F; 16],
+        };
+        let bytes = gguf(
+            &[
+                ("general.name", 8, name),
+                ("llama.layers", 9, layers),
+                ("llama.eps", 6, 1e-5f32.to_le_bytes().to_vec()),
+            ],
+            &[
+                ("dense", &[3, 2], 0, bytemuck::cast_slice(&dense).to_vec()),
+                ("q8", &[32, 2], 8, bytemuck::cast_slice(&[q8, q8]).to_vec()),
+                ("q4", &[32], 2, bytemuck::bytes_of(&q4).to_vec()),
+                ("q4_1", &[32], 3, vec![0; 20]),
+            ],
+        );
+        let gguf = open(&bytes).unwrap();
+        assert_eq!(gguf.version, 3);
+        assert_eq!(gguf.metadata["general.name"].as_str(), Some("tiny"));
+        assert_eq!(
+            gguf.metadata["llama.layers"].as_array().unwrap(),
+            &[GgufValue::U32(1), GgufValue::U32(2)]
+        );
+        assert_eq!(gguf.metadata["llama.eps"].as_f64(), Some(1e-5f32 as f64));
+        assert_eq!(gguf.tensors.len(), 4);
+
+        let t = gguf.tensor("dense").unwrap();
+        assert_eq!(t.shape(), &Shape::from(vec![2, 3]));
+        assert!(t.storage().is_mapped());
+        assert_eq!(t.to_vec::<f32>().unwrap(), dense);
+
+        let t = gguf.tensor("q8").unwrap();
+        assert_eq!(
+            (t.dt(), t.shape()),
+            (DType::Q8_0, &Shape::from(vec![2, 32]))
+        );
+        assert!(t.storage().is_mapped());
+        let values = t.dequantize().unwrap().to_vec::<f32>().unwrap();
+        assert_eq!(values[32..34], [-8., -7.5]);
+
+        let t = gguf.tensor("q4").unwrap();
+        let values = t.dequantize().unwrap().to_vec::<f32>().unwrap();
+        assert_eq!(values, [[14.; 16], [2.; 16]].concat());
+
+        assert!(matches!(
+            gguf.tensor("q4_1"),
+            Err(GgufError::UnsupportedType { ggml_type: 3, .. })
+        ));
+        assert!(matches!(
+            gguf.tensor("missing"),
+            Err(GgufError::TensorNotFound(_))
+        ));
+        assert!(matches!(
+            gguf.load_all(),
+            Err(GgufError::UnsupportedType { .. })
+        ));
+        //The tensors outlive the file handle.
+        drop(gguf);
+        assert_eq!(t.dequantize().unwrap().numel(), 32);
+    }
+
+    #[test]
+    fn malformed_gguf_files_are_rejected() {
+        assert!(matches!(open(b"GGML"), Err(GgufError::BadMagic)));
+        let mut v1 = b"GGUF".to_vec();
+        v1.extend(1u32.to_le_bytes());
+        assert!(matches!(open(&v1), Err(GgufError::UnsupportedVersion(1))));
+
+        //A string value claiming more bytes than remain in the file.
+        let mut bytes = gguf(&[], &[]);
+        bytes.truncate(24);
+        bytes[16] = 1;
+        bytes.extend(1u64.to_le_bytes());
+        bytes.push(b'k');
+        bytes.extend(8u32.to_le_bytes());
+        bytes.extend(100u64.to_le_bytes());
+        assert!(matches!(
+            open(&bytes),
+            Err(GgufError::UnexpectedEof("string", 37))
+        ));
+        let bytes = gguf(&[("x", 13, vec![])], &[]);
+        assert!(matches!(open(&bytes), Err(GgufError::InvalidValueType(13))));
+        let bytes = gguf(
+            &[("general.alignment", 4, 24u32.to_le_bytes().to_vec())],
+            &[],
+        );
+        assert!(matches!(open(&bytes), Err(GgufError::InvalidAlignment(24))));
+
+        //Tensor data extends past the end of the file.
+        let mut bytes = gguf(&[], &[("w", &[8], 0, vec![0; 32])]);
+        bytes.truncate(bytes.len() - 4);
+        let gguf = open(&bytes).unwrap();
+        assert!(matches!(
+            gguf.tensor("w"),
+            Err(GgufError::OutOfBounds { .. })
+        ));
+    }
+}
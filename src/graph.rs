@@ -0,0 +1,352 @@
+use crate::{CachedPipeline, GPUHandle, Kernel, KernelArg, Tensor, WebGPU, Workgroups, CPU};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+///Identifies a buffer read or written by a recorded kernel.
+pub type BufferKey = wgpu::Id<wgpu::Buffer>;
+
+///A kernel recorded with its bindings, waiting to be encoded.
+#[derive(Debug)]
+struct Node {
+    id: u64,
+    label: &'static str,
+    pipeline: Arc<CachedPipeline>,
+    bind_group: wgpu::BindGroup,
+    workgroups: Workgroups,
+    buffers: Vec<BufferKey>,
+    ///Pending nodes which write buffers bound by this one.
+    deps: Vec<u64>,
+}
+
+#[derive(Debug, Default)]
+struct GraphInner {
+    next_id: u64,
+    ///Pending nodes, in recording order, which is a topological order.
+    nodes: Vec<Node>,
+    ///The pending node which last wrote each buffer.
+    writers: HashMap<BufferKey, u64>,
+    ///Buffers freed while still bound by a pending node.
+    released: Vec<wgpu::Buffer>,
+    submissions: u64,
+}
+
+///A DAG of kernels recorded in lazy mode, whose edges are the buffers they share.
+///
+///By default kernels are submitted as soon as they are dispatched.
+///Once [`Graph::set_lazy`] is enabled, [`Kernel::dispatch`] instead records a node,
+///and nothing executes until the graph is realized, either explicitly with
+///[`Tensor::realize`] or [`GPUHandle::realize`], or implicitly when a result is
+///copied to the host or a kernel is dispatched eagerly.
+///Realizing encodes every required node into a single [`wgpu::CommandEncoder`].
+///
+///Buffers freed while a pending node binds them are returned to the [`crate::BufferPool`]
+///once that node executes, so that they are not reused before they are read.
+#[derive(Debug, Default)]
+pub struct Graph {
+    lazy: AtomicBool,
+    inner: Mutex<GraphInner>,
+}
+
+impl Graph {
+    pub fn is_lazy(&self) -> bool {
+        self.lazy.load(Ordering::Relaxed)
+    }
+
+    ///Enables or disables lazy mode. Pending nodes are kept when it is disabled.
+    pub fn set_lazy(&self, lazy: bool) {
+        self.lazy.store(lazy, Ordering::Relaxed)
+    }
+
+    ///Number of pending nodes.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///Number of command buffers submitted by realizing the graph.
+    pub fn submissions(&self) -> u64 {
+        self.inner.lock().unwrap().submissions
+    }
+
+    ///Whether `buffer` has a pending write.
+    pub fn is_pending(&self, buffer: &wgpu::Buffer) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .writers
+            .contains_key(&buffer.global_id())
+    }
+
+    ///Records a kernel as a node, depending on the pending writers of its buffers.
+    pub(crate) fn push(
+        &self,
+        handle: &GPUHandle,
+        kernel: &Kernel,
+        args: &[KernelArg],
+        workgroups: Workgroups,
+    ) {
+        let (pipeline, bind_group) = kernel.prepare(handle, args);
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        let buffers = args
+            .iter()
+            .map(|a| a.buffer().global_id())
+            .collect::<Vec<_>>();
+        let mut deps = buffers
+            .iter()
+            .filter_map(|b| inner.writers.get(b).copied())
+            .collect::<Vec<_>>();
+        deps.sort_unstable();
+        deps.dedup();
+        for arg in args.iter().filter(|a| a.is_writable()) {
+            inner.writers.insert(arg.buffer().global_id(), id);
+        }
+        inner.nodes.push(Node {
+            id,
+            label: kernel.label(),
+            pipeline,
+            bind_group,
+            workgroups,
+            buffers,
+            deps,
+        });
+    }
+
+    ///Takes ownership of a freed buffer if a pending node binds it,
+    ///otherwise hands it back.
+    pub(crate) fn defer_release(&self, buffer: wgpu::Buffer) -> Option<wgpu::Buffer> {
+        let mut inner = self.inner.lock().unwrap();
+        let key = buffer.global_id();
+        if inner.nodes.iter().any(|n| n.buffers.contains(&key)) {
+            inner.released.push(buffer);
+            None
+        } else {
+            Some(buffer)
+        }
+    }
+
+    ///Encodes the pending nodes that the write to `target` depends on, or every
+    ///pending node if `target` is `None`, and submits them as one command buffer.
+    ///Returns the freed buffers which are no longer bound by a pending node.
+    pub(crate) fn realize(
+        &self,
+        handle: &GPUHandle,
+        target: Option<BufferKey>,
+    ) -> Vec<wgpu::Buffer> {
+        let mut inner = self.inner.lock().unwrap();
+        let required = match target {
+            None => inner.nodes.iter().map(|n| n.id).collect::<HashSet<_>>(),
+            Some(key) => {
+                let mut required = HashSet::new();
+                let mut stack = inner
+                    .writers
+                    .get(&key)
+                    .copied()
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                while let Some(id) = stack.pop() {
+                    if required.insert(id) {
+                        let node = inner.nodes.iter().find(|n| n.id == id).unwrap();
+                        stack.extend(&node.deps);
+                    }
+                }
+                required
+            }
+        };
+        if required.is_empty() {
+            return vec![];
+        }
+
+        let mut encoder = handle
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("graph"),
+            });
+        let (ready, pending) = std::mem::take(&mut inner.nodes)
+            .into_iter()
+            .partition::<Vec<_>, _>(|n| required.contains(&n.id));
+        for node in &ready {
+            Kernel::encode(
+                &mut encoder,
+                node.label,
+                &node.pipeline,
+                &node.bind_group,
+                node.workgroups,
+            );
+        }
+        handle.queue().submit(Some(encoder.finish()));
+        inner.submissions += 1;
+        //Submitted nodes are no longer pending, so they stop being dependencies.
+        inner.nodes = pending
+            .into_iter()
+            .map(|mut n| {
+                n.deps.retain(|id| !required.contains(id));
+                n
+            })
+            .collect();
+        inner.writers.retain(|_, id| !required.contains(id));
+
+        let GraphInner {
+            nodes, released, ..
+        } = &mut *inner;
+        let (bound, free) = std::mem::take(released).into_iter().partition(|b| {
+            let key = b.global_id();
+            nodes.iter().any(|n| n.buffers.contains(&key))
+        });
+        *released = bound;
+        free
+    }
+}
+
+impl GPUHandle {
+    ///Encodes and submits every pending node of the [`Graph`].
+    pub fn realize(&self) {
+        self.realize_target(None)
+    }
+
+    pub(crate) fn realize_target(&self, target: Option<BufferKey>) {
+        for buffer in self.graph().realize(self, target) {
+            self.pool().release(buffer);
+        }
+    }
+}
+
+impl Tensor<WebGPU> {
+    ///Executes the pending kernels this tensor depends on, if any.
+    pub fn realize(&self) -> &Self {
+        let key = self.storage().data().global_id();
+        self.device().handle().realize_target(Some(key));
+        self
+    }
+
+    ///Whether the contents of the tensor have been computed.
+    pub fn is_realized(&self) -> bool {
+        !self
+            .device()
+            .handle()
+            .graph()
+            .is_pending(self.storage().data())
+    }
+}
+
+///CPU tensors are always computed eagerly.
+impl Tensor<CPU> {
+    pub fn realize(&self) -> &Self {
+        self
+    }
+
+    pub fn is_realized(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn lazy_graph_submits_once() {
+        let device = Arc::new(WebGPU::new().await.unwrap());
+        let data = (0..256).map(|i| i as f32 / 64. - 2.).collect::<Vec<_>>();
+        let cpu = Tensor::<CPU>::new(vec![16, 16].into(), data.clone()).unwrap();
+        let x = Tensor::<WebGPU>::from_bytes(
+            device.clone(),
+            DType::F32,
+            cpu.shape().clone(),
+            bytemuck::cast_slice(&data),
+        )
+        .unwrap();
+        let graph = device.handle().graph();
+        graph.set_lazy(true);
+
+        let a = x.exp().unwrap();
+        let b = a.add(&x).unwrap().sum(&[1], false).unwrap();
+        //Freed while still bound, so `c` must not be allocated in its buffer.
+        drop(a);
+        let c = x.abs().unwrap();
+        assert_eq!(graph.len(), 4);
+        assert!(!b.is_realized());
+
+        let submissions = graph.submissions();
+        b.realize();
+        assert_eq!(graph.submissions(), submissions + 1);
+        assert!(b.is_realized());
+        assert!(!c.is_realized());
+        assert_eq!(graph.len(), 1);
+
+        let expected = cpu
+            .exp()
+            .unwrap()
+            .add(&cpu)
+            .unwrap()
+            .sum(&[1], false)
+            .unwrap();
+        let actual = b.to(&Arc::new(CPU)).unwrap().to_vec::<f32>().unwrap();
+        for (a, e) in actual.iter().zip(expected.to_vec::<f32>().unwrap()) {
+            assert!((a - e).abs() <= 1e-4 * e.abs().max(1.), "{} != {}", a, e);
+        }
+        //Copying to the host realizes the remaining nodes.
+        assert_eq!(c.to(&Arc::new(CPU)).unwrap(), cpu.abs().unwrap());
+        assert!(graph.is_empty());
+        graph.set_lazy(false);
+    }
+
+    #[tokio::test]
+    async fn eager_dispatch_flushes_pending_nodes() {
+        let device = Arc::new(WebGPU::new().await.unwrap());
+        let data = [1f32, -2., 3., -4.];
+        let x = Tensor::<WebGPU>::from_bytes(
+            device.clone(),
+            DType::F32,
+            vec![4].into(),
+            bytemuck::cast_slice(&data),
+        )
+        .unwrap();
+        let graph = device.handle().graph();
+        graph.set_lazy(true);
+        let y = x.neg().unwrap();
+        graph.set_lazy(false);
+        assert_eq!(graph.len(), 1);
+
+        let z = y.relu().unwrap();
+        assert!(graph.is_empty());
+        let expected = Tensor::<CPU>::new(vec![4].into(), vec![0f32, 2., 0., 4.]).unwrap();
+        assert_eq!(z.to(&Arc::new(CPU)).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn partial_realize_of_siblings() {
+        let device = Arc::new(WebGPU::new().await.unwrap());
+        let data = [0.5f32, -1., 2.];
+        let x = Tensor::<WebGPU>::from_bytes(
+            device.clone(),
+            DType::F32,
+            vec![3].into(),
+            bytemuck::cast_slice(&data),
+        )
+        .unwrap();
+        let graph = device.handle().graph();
+        graph.set_lazy(true);
+        let a = x.exp().unwrap();
+        let b = a.abs().unwrap();
+        let c = a.neg().unwrap();
+        b.realize();
+        //`c` depends on `a`, which was submitted along with `b`.
+        c.realize();
+        assert!(graph.is_empty());
+        graph.set_lazy(false);
+
+        let cpu = Tensor::<CPU>::new(vec![3].into(), data.to_vec()).unwrap();
+        let expected = cpu.exp().unwrap().neg().unwrap();
+        let actual = c.to(&Arc::new(CPU)).unwrap().to_vec::<f32>().unwrap();
+        for (a, e) in actual.iter().zip(expected.to_vec::<f32>().unwrap()) {
+            assert!((a - e).abs() <= 1e-5 * e.abs().max(1.), "{} != {}", a, e);
+        }
+    }
+}
@@ -0,0 +1,469 @@
+use crate::{
+    AllocMode, DType, Device, GPUHandle, Shape, Storage, StorageError, Tensor, TensorError, WebGPU,
+};
+use std::alloc::Layout;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use wgpu::util::DeviceExt;
+
+///How a buffer is bound to a kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BindingKind {
+    ///`var<storage, read>`
+    ReadOnly,
+    ///`var<storage, read_write>`
+    ReadWrite,
+    ///`var<uniform>`
+    Uniform,
+}
+
+impl BindingKind {
+    fn layout_entry(self, binding: u32) -> wgpu::BindGroupLayoutEntry {
+        let ty = match self {
+            BindingKind::ReadOnly => wgpu::BufferBindingType::Storage { read_only: true },
+            BindingKind::ReadWrite => wgpu::BufferBindingType::Storage { read_only: false },
+            BindingKind::Uniform => wgpu::BufferBindingType::Uniform,
+        };
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+}
+
+///A buffer passed to a kernel. Arguments are bound to group 0, in order.
+#[derive(Debug, Clone, Copy)]
+pub struct KernelArg<'a> {
+    buffer: &'a wgpu::Buffer,
+    kind: BindingKind,
+}
+
+impl<'a> KernelArg<'a> {
+    pub fn read(buffer: &'a wgpu::Buffer) -> Self {
+        Self {
+            buffer,
+            kind: BindingKind::ReadOnly,
+        }
+    }
+
+    pub fn write(buffer: &'a wgpu::Buffer) -> Self {
+        Self {
+            buffer,
+            kind: BindingKind::ReadWrite,
+        }
+    }
+
+    pub fn uniform(buffer: &'a wgpu::Buffer) -> Self {
+        Self {
+            buffer,
+            kind: BindingKind::Uniform,
+        }
+    }
+
+    ///Binds the storage of a tensor as read only input.
+    pub fn tensor(tensor: &'a Tensor<WebGPU>) -> Self {
+        Self::read(tensor.storage().data())
+    }
+
+    ///Binds the storage of a tensor as writable output.
+    pub fn output(tensor: &'a Tensor<WebGPU>) -> Self {
+        Self::write(tensor.storage().data())
+    }
+
+    pub fn buffer(&self) -> &'a wgpu::Buffer {
+        self.buffer
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.kind == BindingKind::ReadWrite
+    }
+}
+
+impl Tensor<WebGPU> {
+    ///Allocates a contiguous tensor for a kernel to write into.
+    ///The contents are unspecified until written.
+    pub(crate) fn uninit(
+        device: Arc<WebGPU>,
+        dt: DType,
+        shape: Shape,
+    ) -> Result<Tensor<WebGPU>, TensorError> {
+        let layout = Layout::from_size_align(dt.byte_len(shape.numel()), dt.alignment())
+            .map_err(StorageError::from)?;
+        let dst = device.allocate(
+            layout,
+            AllocMode::STORAGE | AllocMode::COPY_SRC | AllocMode::COPY_DST,
+        )?;
+        let storage = Storage::from_prim(dst, dt, layout, device);
+        Ok(Tensor::from_storage(dt, shape, storage))
+    }
+
+    ///Allocates a contiguous tensor initialized with `bytes` from the host.
+    pub(crate) fn from_bytes(
+        device: Arc<WebGPU>,
+        dt: DType,
+        shape: Shape,
+        bytes: &[u8],
+    ) -> Result<Tensor<WebGPU>, TensorError> {
+        let layout = Layout::from_size_align(dt.byte_len(shape.numel()), dt.alignment())
+            .map_err(StorageError::from)?;
+        let mut dst = device.allocate(
+            layout,
+            AllocMode::STORAGE | AllocMode::COPY_SRC | AllocMode::COPY_DST,
+        )?;
+        device.copy_from_host(bytes, &mut dst)?;
+        let storage = Storage::from_prim(dst, dt, layout, device);
+        Ok(Tensor::from_storage(dt, shape, storage))
+    }
+}
+
+///Number of workgroups to dispatch in each dimension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Workgroups {
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+}
+
+impl Workgroups {
+    pub fn new(x: u32, y: u32, z: u32) -> Self {
+        Self { x, y, z }
+    }
+
+    ///Enough workgroups of [`GPUHandle::WORKGROUP_SIZE`] for `invocations` threads.
+    ///Workgroups are spread over x and y to stay within the per-dimension limit,
+    ///kernels recover the linear index with [`wgsl::GLOBAL_INDEX`].
+    pub fn linear(invocations: usize) -> Self {
+        let groups = (invocations as u32).div_ceil(GPUHandle::WORKGROUP_SIZE);
+        let x = groups.clamp(1, GPUHandle::MAX_WORKGROUPS);
+        Self::new(x, groups.div_ceil(x), 1)
+    }
+}
+
+///A compute shader, with entry point `main`.
+#[derive(Debug, Clone)]
+pub struct Kernel {
+    label: &'static str,
+    source: String,
+    dt: DType,
+}
+
+impl Kernel {
+    ///`dt` is the primary DType the source was generated for.
+    pub fn new(label: &'static str, source: String, dt: DType) -> Self {
+        Self { label, source, dt }
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    ///Fetches the pipeline for `args` from the cache, and binds them.
+    pub(crate) fn prepare(
+        &self,
+        handle: &GPUHandle,
+        args: &[KernelArg],
+    ) -> (Arc<CachedPipeline>, wgpu::BindGroup) {
+        let kinds = args.iter().map(|a| a.kind).collect::<Vec<_>>();
+        let pipeline = handle
+            .pipelines()
+            .get_or_create(handle.device(), self, kinds);
+        let entries = args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| wgpu::BindGroupEntry {
+                binding: i as u32,
+                resource: arg.buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>();
+        let bind_group = handle
+            .device()
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(self.label),
+                layout: &pipeline.bind_group_layout,
+                entries: &entries,
+            });
+        (pipeline, bind_group)
+    }
+
+    ///Encodes a compute pass for a prepared kernel.
+    pub(crate) fn encode(
+        encoder: &mut wgpu::CommandEncoder,
+        label: &'static str,
+        pipeline: &CachedPipeline,
+        bind_group: &wgpu::BindGroup,
+        workgroups: Workgroups,
+    ) {
+        let mut pass =
+            encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some(label) });
+        pass.set_pipeline(&pipeline.pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.dispatch_workgroups(workgroups.x, workgroups.y, workgroups.z);
+    }
+
+    ///Records the kernel into `encoder`, without submitting it.
+    pub fn record(
+        &self,
+        handle: &GPUHandle,
+        encoder: &mut wgpu::CommandEncoder,
+        args: &[KernelArg],
+        workgroups: Workgroups,
+    ) {
+        let (pipeline, bind_group) = self.prepare(handle, args);
+        Self::encode(encoder, self.label, &pipeline, &bind_group, workgroups);
+    }
+
+    ///Records the kernel into its own command encoder and submits it.
+    ///In lazy mode, the kernel is instead recorded into the [`crate::Graph`].
+    ///Otherwise, pending nodes of the graph are submitted first.
+    pub fn dispatch(&self, handle: &GPUHandle, args: &[KernelArg], workgroups: Workgroups) {
+        if handle.graph().is_lazy() {
+            handle.graph().push(handle, self, args, workgroups);
+            return;
+        }
+        handle.realize();
+        let mut encoder = handle
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some(self.label),
+            });
+        self.record(handle, &mut encoder, args, workgroups);
+        handle.queue().submit(Some(encoder.finish()));
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct CachedPipeline {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    source: String,
+    dt: DType,
+    kinds: Vec<BindingKind>,
+}
+
+///Compiled pipelines, keyed by kernel source, DType and binding layout.
+#[derive(Debug, Default)]
+pub struct PipelineCache {
+    pipelines: Mutex<HashMap<PipelineKey, Arc<CachedPipeline>>>,
+}
+
+impl PipelineCache {
+    pub fn len(&self) -> usize {
+        self.pipelines.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.pipelines.lock().unwrap().clear()
+    }
+
+    fn get_or_create(
+        &self,
+        device: &wgpu::Device,
+        kernel: &Kernel,
+        kinds: Vec<BindingKind>,
+    ) -> Arc<CachedPipeline> {
+        let key = PipelineKey {
+            source: kernel.source.clone(),
+            dt: kernel.dt,
+            kinds,
+        };
+        if let Some(cached) = self.pipelines.lock().unwrap().get(&key).cloned() {
+            return cached;
+        }
+        let entries = key
+            .kinds
+            .iter()
+            .enumerate()
+            .map(|(i, kind)| kind.layout_entry(i as u32))
+            .collect::<Vec<_>>();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(kernel.label),
+            entries: &entries,
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(kernel.label),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(kernel.label),
+            source: wgpu::ShaderSource::Wgsl(kernel.source.as_str().into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(kernel.label),
+            layout: Some(&layout),
+            module: &module,
+            entry_point: "main",
+        });
+        let cached = Arc::new(CachedPipeline {
+            bind_group_layout,
+            pipeline,
+        });
+        //Another thread may have compiled the same pipeline meanwhile, either is equivalent.
+        self.pipelines
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert(cached)
+            .clone()
+    }
+}
+
+///Scalar kernel parameters (sizes, shapes, strides, offsets), uploaded as a uniform buffer.
+///Kernels read them with `param(i)`, declared by [`Metadata::declaration`].
+#[derive(Debug, Clone, Default)]
+pub struct Metadata(Vec<u32>);
+
+impl Metadata {
+    ///Uniform buffers have a fixed size in WGSL, so that kernel sources don't depend on rank.
+    pub const CAPACITY: usize = 128;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, value: usize) -> &mut Self {
+        self.0.push(value as u32);
+        self
+    }
+
+    ///Pushes `[rank, offset, shape.., strides..]`, read in WGSL by [`wgsl::STRIDED_OFFSET`].
+    pub fn push_layout(&mut self, tensor: &Tensor<WebGPU>) -> &mut Self {
+        self.push(tensor.shape().rank()).push(tensor.offset());
+        for &d in tensor.shape().iter() {
+            self.push(d);
+        }
+        for &s in tensor.strides().iter() {
+            self.push(s);
+        }
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    ///WGSL declaring the uniform at `binding`, and the `param(i)` accessor.
+    pub fn declaration(binding: u32) -> String {
+        format!(
+            r#"
+@group(0) @binding({binding}) var<uniform> params_: array<vec4<u32>, {vecs}>;
+
+fn param(i: u32) -> u32 {{
+    return params_[i / 4u][i % 4u];
+}}
+"#,
+            vecs = Self::CAPACITY / 4
+        )
+    }
+
+    ///Uploads the parameters, padded to [`Metadata::CAPACITY`].
+    ///Returns [`TensorError::MetadataOverflow`] if more values were pushed, e.g for tensors of very high rank.
+    pub fn upload(&self, handle: &GPUHandle) -> Result<wgpu::Buffer, TensorError> {
+        if self.0.len() > Self::CAPACITY {
+            return Err(TensorError::MetadataOverflow(self.0.len()));
+        }
+        let mut contents = self.0.clone();
+        contents.resize(Self::CAPACITY, 0);
+        Ok(handle
+            .device()
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("metadata"),
+                contents: bytemuck::cast_slice(&contents),
+                usage: wgpu::BufferUsages::UNIFORM,
+            }))
+    }
+}
+
+///WGSL snippets shared between kernels.
+pub mod wgsl {
+    use crate::DType;
+
+    ///The WGSL scalar type for DTypes that kernels can index directly.
+    pub fn scalar_type(dt: DType) -> Option<&'static str> {
+        match dt {
+            DType::F32 => Some("f32"),
+            DType::I32 => Some("i32"),
+            DType::U32 => Some("u32"),
+            _ => None,
+        }
+    }
+
+    ///`fn global_index(gid, groups) -> u32`, the linear invocation index for [`super::Workgroups::linear`].
+    ///Kernels using it must declare `@workgroup_size(64)`, i.e [`crate::GPUHandle::WORKGROUP_SIZE`].
+    pub const GLOBAL_INDEX: &str = r#"
+fn global_index(gid: vec3<u32>, groups: vec3<u32>) -> u32 {
+    return gid.y * groups.x * 64u + gid.x;
+}
+"#;
+
+    ///`fn safe_tanh(x: f32) -> f32`, clamping the input so that large magnitudes
+    ///saturate to ±1 instead of overflowing to NaN on some drivers.
+    pub const SAFE_TANH: &str = r#"
+fn safe_tanh(x: f32) -> f32 {
+    return tanh(clamp(x, -15.0, 15.0));
+}
+"#;
+
+    ///`fn strided_offset(base, index) -> u32`, mapping a row-major element index
+    ///to an element offset in storage, for a layout pushed by [`super::Metadata::push_layout`]
+    ///starting at `param(base)`.
+    pub const STRIDED_OFFSET: &str = r#"
+fn strided_offset(base: u32, index: u32) -> u32 {
+    let rank = param(base);
+    var rem = index;
+    var off = param(base + 1u);
+    for (var d = i32(rank) - 1; d >= 0; d--) {
+        let dim = param(base + 2u + u32(d));
+        let stride = param(base + 2u + rank + u32(d));
+        off += (rem % dim) * stride;
+        rem /= dim;
+    }
+    return off;
+}
+"#;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn pipelines_are_cached() {
+        let device = Arc::new(WebGPU::new().await.unwrap());
+        let input = Tensor::<CPU>::new(vec![4].into(), vec![1f32, 2., 3., 4.])
+            .unwrap()
+            .to(&device)
+            .unwrap();
+        let handle = input.device().handle();
+        handle.pipelines().clear();
+        let a = input.cast(DType::I32).unwrap();
+        let b = input.cast(DType::I32).unwrap();
+        assert_eq!(handle.pipelines().len(), 1);
+        input.cast(DType::U8).unwrap();
+        assert_eq!(handle.pipelines().len(), 2);
+        assert_eq!(a.to(&Arc::new(CPU)).unwrap(), b.to(&Arc::new(CPU)).unwrap());
+    }
+}
@@ -1,22 +1,55 @@
 #![feature(allocator_api)]
+#![feature(portable_simd)]
 pub mod alloc_mode;
+pub mod any_device;
+pub mod autograd;
+pub mod binary;
 pub mod buffer_id;
+mod cast;
 pub mod cpu;
 pub mod device;
 pub mod dtype;
+pub mod gguf;
+pub mod graph;
+pub mod kernel;
+mod matmul;
+mod norm;
+pub mod npy;
+pub mod pool;
+pub mod quant;
+pub mod reduce;
+pub mod safetensors;
 pub mod shape;
+pub mod slice;
 pub mod storage;
 pub mod tensor;
+pub mod unary;
+mod view;
 pub mod webgpu;
 
+pub use half;
+
 pub use alloc_mode::*;
+pub use any_device::*;
+pub use autograd::*;
+pub use binary::*;
 pub use buffer_id::*;
 pub use cpu::*;
 pub use device::*;
 pub use dtype::*;
+pub use gguf::*;
+pub use graph::*;
+pub use kernel::*;
+pub use npy::*;
+pub use pool::*;
+pub use quant::*;
+pub use reduce::*;
+pub use safetensors::*;
 pub use shape::*;
+pub use slice::*;
 pub use storage::*;
 pub use tensor::*;
+pub use unary::*;
 pub use webgpu::*;
 
 #[cfg(test)]
@@ -27,10 +60,23 @@ mod tests {
         let data: Vec<f32> = vec![1., 2., 3., 4., 5., 6., 7., 8.];
         let original = Tensor::<CPU>::new(vec![2, 4].into(), data.clone()).unwrap();
 
-        let wgpu_device = WebGPU::new().await.unwrap();
+        let wgpu_device = std::sync::Arc::new(WebGPU::new().await.unwrap());
 
-        let gpu_tensor = original.to(wgpu_device).unwrap();
-        let returned = gpu_tensor.to(CPU).unwrap();
+        let gpu_tensor = original.to(&wgpu_device).unwrap();
+        let returned = gpu_tensor.to(&std::sync::Arc::new(CPU)).unwrap();
         assert_eq!(returned.as_slice::<f32>().unwrap(), data.as_slice());
     }
+
+    #[tokio::test]
+    async fn half_roundtrip() {
+        let data = [1.5f32, -2.25, 3.0].map(half::f16::from_f32).to_vec();
+        let original = Tensor::<CPU>::new(vec![3].into(), data.clone()).unwrap();
+
+        let wgpu_device = std::sync::Arc::new(WebGPU::new().await.unwrap());
+
+        let gpu_tensor = original.to(&wgpu_device).unwrap();
+        let returned = gpu_tensor.to(&std::sync::Arc::new(CPU)).unwrap();
+        assert_eq!(returned.as_slice::<half::f16>().unwrap(), data.as_slice());
+        assert_eq!(returned.to_string(), "[1.5, -2.25, 3]");
+    }
 }
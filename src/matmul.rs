@@ -0,0 +1,706 @@
+use crate::{
+    as_std, DType, Device, GPUHandle, GradOp, Kernel, KernelArg, Metadata, Numeric, Shape,
+    StridedIndex, Strides, Tensor, TensorError, WebGPU, Workgroups, CPU,
+};
+use half::{bf16, f16};
+use std::simd::Simd;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+impl<D: Device> Tensor<D> {
+    ///Broadcasts the leading (batch) dimensions of `self` and `rhs`, returning views
+    ///of shape `[..batch, m, k]` and `[..batch, k, n]`.
+    pub(crate) fn matmul_operands(
+        &self,
+        rhs: &Tensor<D>,
+    ) -> Result<(Tensor<D>, Tensor<D>), TensorError> {
+        self.check_device(rhs)?;
+        if self.dt() != rhs.dt() {
+            return Err(TensorError::DTypeMismatch {
+                expected: self.dt(),
+                actual: rhs.dt(),
+            });
+        }
+        let mismatch = || TensorError::MatmulMismatch(self.shape().clone(), rhs.shape().clone());
+        let (a, b) = (self.shape().as_slice(), rhs.shape().as_slice());
+        if a.len() < 2 || b.len() < 2 {
+            return Err(mismatch());
+        }
+        let (m, k) = (a[a.len() - 2], a[a.len() - 1]);
+        let (k_rhs, n) = (b[b.len() - 2], b[b.len() - 1]);
+        if k != k_rhs {
+            return Err(mismatch());
+        }
+        let batch = Shape::from(a[..a.len() - 2].to_vec())
+            .broadcast(&Shape::from(b[..b.len() - 2].to_vec()))
+            .ok_or_else(mismatch)?;
+        let mut lhs_shape = batch.as_slice().to_vec();
+        let mut rhs_shape = lhs_shape.clone();
+        lhs_shape.extend([m, k]);
+        rhs_shape.extend([k, n]);
+        Ok((
+            self.expand(lhs_shape.into())?,
+            rhs.expand(rhs_shape.into())?,
+        ))
+    }
+}
+
+impl Tensor<WebGPU> {
+    ///Matrix product of the last two dimensions, broadcasting the leading dimensions.
+    ///Operands of shape `[..batch, m, k]` and `[..batch, k, n]` produce `[..batch, m, n]`.
+    ///
+    ///Only F32 is supported. Strided operands, e.g transposes, are read in place.
+    ///Operands whose unit stride dimension is a multiple of 4 are loaded as `vec4<f32>`.
+    pub fn matmul(&self, rhs: &Tensor<WebGPU>) -> Result<Tensor<WebGPU>, TensorError> {
+        let (lhs, rhs) = self.matmul_operands(rhs)?;
+        let dt = lhs.dt();
+        if dt != DType::F32 {
+            return Err(TensorError::UnsupportedDType(dt));
+        }
+        let rank = lhs.shape().rank();
+        let (m, k, n) = (
+            lhs.shape()[rank - 2],
+            lhs.shape()[rank - 1],
+            rhs.shape()[rank - 1],
+        );
+        let mut shape = lhs.shape().as_slice().to_vec();
+        shape[rank - 1] = n;
+        let batch = shape[..rank - 2].iter().product::<usize>();
+        let dst = Tensor::uninit(self.storage().device().clone(), dt, shape.into())?;
+        if dst.numel() > 0 {
+            let handle = self.device().handle();
+            let meta = Metadata::new()
+                .push(m)
+                .push(n)
+                .push(k)
+                .push_layout(&lhs)
+                .push_layout(&rhs)
+                .upload(handle)?;
+            let source = MatmulKernel::source(
+                MatmulKernel::vector_axis(&lhs),
+                MatmulKernel::vector_axis(&rhs),
+            );
+            //Batches beyond the z limit continue along y, after the row tiles of the first.
+            let tiles = |d: usize| d.div_ceil(MatmulKernel::TILE);
+            let z = batch.min(GPUHandle::MAX_WORKGROUPS as usize);
+            let y = tiles(m) * batch.div_ceil(z);
+            let max = GPUHandle::MAX_WORKGROUPS as usize;
+            if tiles(n) > max || y > max {
+                return Err(TensorError::DispatchLimit(
+                    lhs.shape().clone(),
+                    rhs.shape().clone(),
+                ));
+            }
+            Kernel::new("matmul", source, dt).dispatch(
+                handle,
+                &[
+                    KernelArg::tensor(&lhs),
+                    KernelArg::tensor(&rhs),
+                    KernelArg::output(&dst),
+                    KernelArg::uniform(&meta),
+                ],
+                Workgroups::new(tiles(n) as u32, y as u32, z as u32),
+            );
+        }
+        Ok(dst.record(GradOp::Matmul, &[&lhs, &rhs]))
+    }
+}
+
+///Shared memory tiled matmul.
+///Each workgroup of 8x8 invocations computes a 32x32 tile of the output, each invocation a 4x4 block.
+///Tiles of both operands are staged in workgroup memory, 32 elements of `k` at a time.
+///Metadata layout: `[m, n, k, lhs layout.., rhs layout..]`, both layouts having the same rank.
+///Batches run along z, overflowing into multiples of the row tiles along y.
+struct MatmulKernel;
+
+impl MatmulKernel {
+    const TILE: usize = 32;
+
+    ///The matrix dimension (0 for rows, 1 for columns) along which `operand` can be loaded as `vec4<f32>`.
+    ///This requires a unit stride and a size that is a multiple of 4 in that dimension,
+    ///with the offset and every other stride also multiples of 4, so each vec4 is aligned.
+    fn vector_axis(operand: &Tensor<WebGPU>) -> Option<usize> {
+        let rank = operand.shape().rank();
+        let strides = operand.strides().as_slice();
+        if !operand.offset().is_multiple_of(4) {
+            return None;
+        }
+        [1, 0].into_iter().find(|&axis| {
+            let dim = rank - 2 + axis;
+            strides[dim] == 1
+                && operand.shape()[dim].is_multiple_of(4)
+                && strides
+                    .iter()
+                    .enumerate()
+                    .all(|(d, &s)| d == dim || s.is_multiple_of(4))
+        })
+    }
+
+    ///WGSL for `fn load_{name}(l, base, r0, c0, rows, cols, s0, s1)`, where invocation `l`
+    ///stages its share of the 32x32 tile starting at `(r0, c0)` into `{name}_tile`.
+    fn loader(name: &str, vector_axis: Option<usize>) -> String {
+        let body = match vector_axis {
+            None => format!(
+                r#"for (var i = 0u; i < 16u; i++) {{
+        let e = l + i * 64u;
+        let r = e / 32u;
+        let c = e % 32u;
+        var v = 0.0;
+        if (r0 + r < rows && c0 + c < cols) {{
+            v = {name}[base + (r0 + r) * s0 + (c0 + c) * s1];
+        }}
+        {name}_tile[e] = v;
+    }}"#
+            ),
+            Some(axis) => {
+                let (r, c, index, step) = if axis == 1 {
+                    (
+                        "v / 8u",
+                        "(v % 8u) * 4u",
+                        "base + (r0 + r) * s0 + c0 + c",
+                        "1u",
+                    )
+                } else {
+                    (
+                        "(v % 8u) * 4u",
+                        "v / 8u",
+                        "base + r0 + r + (c0 + c) * s1",
+                        "32u",
+                    )
+                };
+                format!(
+                    r#"for (var i = 0u; i < 4u; i++) {{
+        let v = l + i * 64u;
+        let r = {r};
+        let c = {c};
+        var q = vec4<f32>(0.0);
+        if (r0 + r < rows && c0 + c < cols) {{
+            q = {name}[({index}) / 4u];
+        }}
+        let t = r * 32u + c;
+        {name}_tile[t] = q.x;
+        {name}_tile[t + {step}] = q.y;
+        {name}_tile[t + 2u * {step}] = q.z;
+        {name}_tile[t + 3u * {step}] = q.w;
+    }}"#
+                )
+            }
+        };
+        format!(
+            r#"
+fn load_{name}(l: u32, base: u32, r0: u32, c0: u32, rows: u32, cols: u32, s0: u32, s1: u32) {{
+    {body}
+}}
+"#
+        )
+    }
+
+    fn source(lhs_axis: Option<usize>, rhs_axis: Option<usize>) -> String {
+        let ty = |axis: Option<usize>| if axis.is_some() { "vec4<f32>" } else { "f32" };
+        format!(
+            r#"
+@group(0) @binding(0) var<storage, read> lhs: array<{lhs_ty}>;
+@group(0) @binding(1) var<storage, read> rhs: array<{rhs_ty}>;
+@group(0) @binding(2) var<storage, read_write> dst: array<f32>;
+{meta}
+var<workgroup> lhs_tile: array<f32, 1024>;
+var<workgroup> rhs_tile: array<f32, 1024>;
+
+//Offset of matrix `batch` for the layout at `param(base)`, walking all but the last two dimensions.
+fn batch_offset(base: u32, batch: u32) -> u32 {{
+    let rank = param(base);
+    var rem = batch;
+    var off = param(base + 1u);
+    for (var d = i32(rank) - 3; d >= 0; d--) {{
+        let dim = param(base + 2u + u32(d));
+        let stride = param(base + 2u + rank + u32(d));
+        off += (rem % dim) * stride;
+        rem /= dim;
+    }}
+    return off;
+}}
+{load_lhs}{load_rhs}
+@compute @workgroup_size(8, 8, 1)
+fn main(
+    @builtin(workgroup_id) wg: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+    @builtin(local_invocation_id) lid: vec3<u32>
+) {{
+    let m = param(0u);
+    let n = param(1u);
+    let k = param(2u);
+    let rank = param(3u);
+    let lhs_layout = 3u;
+    let rhs_layout = 5u + 2u * rank;
+    var batches = 1u;
+    for (var d = 0u; d + 2u < rank; d++) {{
+        batches *= param(lhs_layout + 2u + d);
+    }}
+    let row_tiles = (m + 31u) / 32u;
+    let batch = wg.z + (wg.y / row_tiles) * groups.z;
+    if (batch >= batches) {{
+        return;
+    }}
+    let lhs_base = batch_offset(lhs_layout, batch);
+    let rhs_base = batch_offset(rhs_layout, batch);
+    let lhs_s0 = param(lhs_layout + 2u * rank);
+    let lhs_s1 = param(lhs_layout + 2u * rank + 1u);
+    let rhs_s0 = param(rhs_layout + 2u * rank);
+    let rhs_s1 = param(rhs_layout + 2u * rank + 1u);
+
+    let l = lid.y * 8u + lid.x;
+    let row0 = (wg.y % row_tiles) * 32u;
+    let col0 = wg.x * 32u;
+    var acc: array<vec4<f32>, 4>;
+    for (var k0 = 0u; k0 < k; k0 += 32u) {{
+        load_lhs(l, lhs_base, row0, k0, m, k, lhs_s0, lhs_s1);
+        load_rhs(l, rhs_base, k0, col0, k, n, rhs_s0, rhs_s1);
+        workgroupBarrier();
+        for (var kk = 0u; kk < 32u; kk++) {{
+            let b = rhs_tile[kk * 32u + lid.x * 4u];
+            let bv = vec4<f32>(
+                b,
+                rhs_tile[kk * 32u + lid.x * 4u + 1u],
+                rhs_tile[kk * 32u + lid.x * 4u + 2u],
+                rhs_tile[kk * 32u + lid.x * 4u + 3u]
+            );
+            for (var i = 0u; i < 4u; i++) {{
+                acc[i] += lhs_tile[(lid.y * 4u + i) * 32u + kk] * bv;
+            }}
+        }}
+        workgroupBarrier();
+    }}
+
+    let dst_base = batch * m * n;
+    for (var i = 0u; i < 4u; i++) {{
+        let row = row0 + lid.y * 4u + i;
+        for (var j = 0u; j < 4u; j++) {{
+            let col = col0 + lid.x * 4u + j;
+            if (row < m && col < n) {{
+                dst[dst_base + row * n + col] = acc[i][j];
+            }}
+        }}
+    }}
+}}
+"#,
+            lhs_ty = ty(lhs_axis),
+            rhs_ty = ty(rhs_axis),
+            meta = Metadata::declaration(3),
+            load_lhs = Self::loader("lhs", lhs_axis),
+            load_rhs = Self::loader("rhs", rhs_axis),
+        )
+    }
+}
+
+impl Tensor<CPU> {
+    ///Matrix product of the last two dimensions, broadcasting the leading dimensions.
+    ///Operands of shape `[..batch, m, k]` and `[..batch, k, n]` produce `[..batch, m, n]`.
+    ///
+    ///Supports F32, F64 and the integer DTypes, integer products wrap on overflow.
+    ///Strided operands, e.g transposes, are read in place while packing.
+    pub fn matmul(&self, rhs: &Tensor<CPU>) -> Result<Tensor<CPU>, TensorError> {
+        let (lhs, rhs) = self.matmul_operands(rhs)?;
+        fn matmul_t<T: Gemm>(
+            lhs: &Tensor<CPU>,
+            rhs: &Tensor<CPU>,
+        ) -> Result<Tensor<CPU>, TensorError> {
+            if !T::SUPPORTED {
+                return Err(TensorError::UnsupportedDType(T::dtype()));
+            }
+            let rank = lhs.shape().rank();
+            let (m, k, n) = (
+                lhs.shape()[rank - 2],
+                lhs.shape()[rank - 1],
+                rhs.shape()[rank - 1],
+            );
+            let mut shape = lhs.shape().as_slice().to_vec();
+            shape[rank - 1] = n;
+            let (a, b) = (GemmOperand::<T>::new(lhs)?, GemmOperand::<T>::new(rhs)?);
+            let threads = if m * n * k < GEMM_PARALLEL_THRESHOLD {
+                1
+            } else {
+                std::thread::available_parallelism().map_or(1, |t| t.get())
+            };
+            let c = gemm(&a, &b, (m, n, k), threads);
+            Tensor::new(shape.into(), c)
+        }
+        let dst = as_std!(matmul_t(self.dt())(&lhs, &rhs))?;
+        Ok(dst.record(GradOp::Matmul, &[&lhs, &rhs]))
+    }
+}
+
+///Register block of the CPU microkernel, in rows of A and columns of B.
+const MR: usize = 4;
+const NR: usize = 8;
+///Cache blocking of the CPU matmul: each job computes an `MC x NC` tile of the output,
+///packing `MC x KC` blocks of A and `KC x NC` blocks of B.
+const MC: usize = 64;
+const KC: usize = 256;
+const NC: usize = 256;
+///Products with fewer multiply-adds than this run on the calling thread.
+const GEMM_PARALLEL_THRESHOLD: usize = 1 << 18;
+
+///Element types of the CPU matmul.
+trait Gemm: Numeric {
+    const SUPPORTED: bool = true;
+
+    ///Multiplies an `MR x kc` panel of A, packed column by column,
+    ///with a `kc x NR` panel of B, packed row by row.
+    fn microkernel(kc: usize, a: &[Self], b: &[Self]) -> [[Self; NR]; MR] {
+        let mut acc = [[Self::zeroed(); NR]; MR];
+        for p in 0..kc {
+            let (a, b) = (&a[p * MR..p * MR + MR], &b[p * NR..p * NR + NR]);
+            for i in 0..MR {
+                for j in 0..NR {
+                    acc[i][j] = acc[i][j].add(a[i].mul(b[j]));
+                }
+            }
+        }
+        acc
+    }
+}
+
+macro_rules! gemm_simd {
+    ($t:ty) => {
+        impl Gemm for $t {
+            fn microkernel(kc: usize, a: &[Self], b: &[Self]) -> [[Self; NR]; MR] {
+                let mut rows = [Simd::<$t, NR>::splat(0.); MR];
+                for p in 0..kc {
+                    let bv = Simd::<$t, NR>::from_slice(&b[p * NR..p * NR + NR]);
+                    for (i, row) in rows.iter_mut().enumerate() {
+                        *row += Simd::splat(a[p * MR + i]) * bv;
+                    }
+                }
+                rows.map(|row| row.to_array())
+            }
+        }
+    };
+}
+
+gemm_simd!(f32);
+gemm_simd!(f64);
+
+impl Gemm for u8 {}
+impl Gemm for u16 {}
+impl Gemm for u32 {}
+impl Gemm for u64 {}
+impl Gemm for i8 {}
+impl Gemm for i16 {}
+impl Gemm for i32 {}
+impl Gemm for i64 {}
+
+///Half precision products would accumulate in half precision, cast to F32 instead.
+impl Gemm for f16 {
+    const SUPPORTED: bool = false;
+}
+
+impl Gemm for bf16 {
+    const SUPPORTED: bool = false;
+}
+
+///A strided `[..batch, rows, cols]` operand of the CPU matmul.
+struct GemmOperand<'a, T> {
+    data: &'a [T],
+    batch_offsets: Vec<usize>,
+    row_stride: usize,
+    col_stride: usize,
+}
+
+impl<'a, T: Gemm> GemmOperand<'a, T> {
+    fn new(tensor: &'a Tensor<CPU>) -> Result<Self, TensorError> {
+        let rank = tensor.shape().rank();
+        let batch_shape = Shape::from(tensor.shape().as_slice()[..rank - 2].to_vec());
+        let batch_strides = Strides::new(tensor.strides().as_slice()[..rank - 2].to_vec());
+        let batch_offsets = StridedIndex::new(&batch_shape, &batch_strides)
+            .map(|o| tensor.offset() + o)
+            .collect();
+        Ok(Self {
+            data: tensor.storage_slice::<T>()?,
+            batch_offsets,
+            row_stride: tensor.strides()[rank - 2],
+            col_stride: tensor.strides()[rank - 1],
+        })
+    }
+
+    fn get(&self, base: usize, row: usize, col: usize) -> T {
+        self.data[base + row * self.row_stride + col * self.col_stride]
+    }
+}
+
+///The output of the CPU matmul, shared between threads that each write disjoint tiles.
+#[derive(Clone, Copy)]
+struct TilePtr<T>(*mut T);
+
+unsafe impl<T: Send> Send for TilePtr<T> {}
+unsafe impl<T: Send> Sync for TilePtr<T> {}
+
+impl<T> TilePtr<T> {
+    fn get(self) -> *mut T {
+        self.0
+    }
+}
+
+///Computes the contiguous `[batch, m, n]` product of `a` and `b` on `threads` threads,
+///which take `MC x NC` output tiles of every batch from a shared counter.
+fn gemm<T: Gemm>(
+    a: &GemmOperand<T>,
+    b: &GemmOperand<T>,
+    (m, n, k): (usize, usize, usize),
+    threads: usize,
+) -> Vec<T> {
+    let batch = a.batch_offsets.len();
+    let mut c = vec![T::zeroed(); batch * m * n];
+    let (row_tiles, col_tiles) = (m.div_ceil(MC), n.div_ceil(NC));
+    let jobs = batch * row_tiles * col_tiles;
+    let next = AtomicUsize::new(0);
+    let out = TilePtr(c.as_mut_ptr());
+    let work = || {
+        let mut a_pack = vec![T::zeroed(); MC.next_multiple_of(MR) * KC];
+        let mut b_pack = vec![T::zeroed(); KC * NC.next_multiple_of(NR)];
+        loop {
+            let job = next.fetch_add(1, Ordering::Relaxed);
+            if job >= jobs {
+                break;
+            }
+            let (bi, tile) = (job / (row_tiles * col_tiles), job % (row_tiles * col_tiles));
+            let (ic, jc) = ((tile / col_tiles) * MC, (tile % col_tiles) * NC);
+            let (mc, nc) = (MC.min(m - ic), NC.min(n - jc));
+            for pc in (0..k).step_by(KC) {
+                let kc = KC.min(k - pc);
+                pack_a(a, a.batch_offsets[bi], (ic, pc), (mc, kc), &mut a_pack);
+                pack_b(b, b.batch_offsets[bi], (pc, jc), (kc, nc), &mut b_pack);
+                for jr in (0..nc).step_by(NR) {
+                    let b_panel = &b_pack[jr * kc..(jr + NR) * kc];
+                    for ir in (0..mc).step_by(MR) {
+                        let a_panel = &a_pack[ir * kc..(ir + MR) * kc];
+                        let acc = T::microkernel(kc, a_panel, b_panel);
+                        let width = NR.min(nc - jr);
+                        for (i, row) in acc.iter().enumerate().take(mc - ir) {
+                            let start = bi * m * n + (ic + ir + i) * n + jc + jr;
+                            //SAFETY: only this job writes columns `jc..jc + nc` of the rows
+                            //`ic..ic + mc` of batch `bi`, and `c` outlives the threads.
+                            //The slice covers `width <= nc - jr` of those columns in one row.
+                            let dst = unsafe {
+                                std::slice::from_raw_parts_mut(out.get().add(start), width)
+                            };
+                            for (d, &v) in dst.iter_mut().zip(row) {
+                                *d = d.add(v);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    if threads <= 1 || jobs <= 1 {
+        work();
+    } else {
+        std::thread::scope(|s| {
+            for _ in 0..threads.min(jobs) {
+                s.spawn(work);
+            }
+        });
+    }
+    c
+}
+
+///Packs the `mc x kc` block of A at `(row, col)` into panels of `MR` rows,
+///each stored column by column and zero padded.
+fn pack_a<T: Gemm>(
+    a: &GemmOperand<T>,
+    base: usize,
+    (row, col): (usize, usize),
+    (mc, kc): (usize, usize),
+    pack: &mut [T],
+) {
+    for ir in (0..mc).step_by(MR) {
+        let panel = &mut pack[ir * kc..(ir + MR) * kc];
+        for p in 0..kc {
+            for i in 0..MR {
+                panel[p * MR + i] = if ir + i < mc {
+                    a.get(base, row + ir + i, col + p)
+                } else {
+                    T::zeroed()
+                };
+            }
+        }
+    }
+}
+
+///Packs the `kc x nc` block of B at `(row, col)` into panels of `NR` columns,
+///each stored row by row and zero padded.
+fn pack_b<T: Gemm>(
+    b: &GemmOperand<T>,
+    base: usize,
+    (row, col): (usize, usize),
+    (kc, nc): (usize, usize),
+    pack: &mut [T],
+) {
+    for jr in (0..nc).step_by(NR) {
+        let panel = &mut pack[jr * kc..(jr + NR) * kc];
+        for p in 0..kc {
+            for j in 0..NR {
+                panel[p * NR + j] = if jr + j < nc {
+                    b.get(base, row + p, col + jr + j)
+                } else {
+                    T::zeroed()
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+
+    fn matrix(shape: Vec<usize>, seed: usize) -> Tensor<CPU> {
+        let n = shape.iter().product::<usize>();
+        let data = (0..n)
+            .map(|i| ((i * 7 + seed) % 13) as f32 - 6.)
+            .collect::<Vec<_>>();
+        Tensor::<CPU>::new(shape.into(), data).unwrap()
+    }
+
+    ///Naive reference for contiguous 2-D operands.
+    fn reference(a: &Tensor<CPU>, b: &Tensor<CPU>) -> Vec<f32> {
+        let (m, k, n) = (a.shape()[0], a.shape()[1], b.shape()[1]);
+        let (a, b) = (a.to_vec::<f32>().unwrap(), b.to_vec::<f32>().unwrap());
+        let mut out = vec![0.; m * n];
+        for i in 0..m {
+            for j in 0..n {
+                out[i * n + j] = (0..k).map(|p| a[i * k + p] * b[p * n + j]).sum();
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn cpu_matmul_blocked() {
+        //Crosses the MC, KC and NC block boundaries, with transposed operands.
+        let (m, k, n) = (70, 300, 260);
+        let (a, b) = (matrix(vec![m, k], 1), matrix(vec![n, k], 2));
+        let bt = b.transpose(0, 1).unwrap();
+        let expected = reference(&a, &bt.contiguous().unwrap());
+        assert_eq!(a.matmul(&bt).unwrap().to_vec::<f32>().unwrap(), expected);
+
+        let (a64, b64) = (a.cast(DType::F64).unwrap(), bt.cast(DType::F64).unwrap());
+        let result = a64.matmul(&b64).unwrap().cast(DType::F32).unwrap();
+        assert_eq!(result.to_vec::<f32>().unwrap(), expected);
+
+        let lhs = super::GemmOperand::<f32>::new(&a).unwrap();
+        let rhs = super::GemmOperand::<f32>::new(&bt).unwrap();
+        assert_eq!(super::gemm(&lhs, &rhs, (m, n, k), 4), expected);
+    }
+
+    #[test]
+    fn cpu_matmul_integers_and_batches() {
+        let a = Tensor::<CPU>::new(vec![1, 2, 2].into(), vec![i32::MAX, 1, 2, 3]).unwrap();
+        let b = Tensor::<CPU>::new(vec![3, 2, 1].into(), vec![2i32, 0, 1, 1, 0, 5]).unwrap();
+        let c = a.matmul(&b).unwrap();
+        assert_eq!(c.shape(), &Shape::from(vec![3, 2, 1]));
+        assert_eq!(
+            c.to_vec::<i32>().unwrap(),
+            vec![-2, 4, i32::MAX.wrapping_add(1), 5, 5, 15]
+        );
+
+        let f = matrix(vec![2, 3], 0);
+        assert!(matches!(
+            f.cast(DType::F16)
+                .unwrap()
+                .matmul(&f.cast(DType::F16).unwrap()),
+            Err(TensorError::MatmulMismatch(..))
+        ));
+        let h = f.cast(DType::BF16).unwrap();
+        assert!(matches!(
+            h.matmul(&h.transpose(0, 1).unwrap()),
+            Err(TensorError::UnsupportedDType(DType::BF16))
+        ));
+    }
+
+    #[tokio::test]
+    async fn gpu_matmul_2d() {
+        let device = std::sync::Arc::new(WebGPU::new().await.unwrap());
+        //Multiples of the tile (vec4 loads), ragged sizes (scalar loads) and transposed operands.
+        for (m, k, n) in [(64, 64, 64), (37, 45, 29), (1, 3, 1), (33, 8, 12)] {
+            let (a, b) = (matrix(vec![m, k], 1), matrix(vec![k, n], 2));
+            let expected = reference(&a, &b);
+            let result = a
+                .clone()
+                .to(&device)
+                .unwrap()
+                .matmul(&b.clone().to(&device).unwrap())
+                .unwrap()
+                .to(&Arc::new(CPU))
+                .unwrap();
+            assert_eq!(result.shape(), &Shape::from(vec![m, n]));
+            assert_eq!(result.to_vec::<f32>().unwrap(), expected);
+
+            let (at, bt) = (matrix(vec![k, m], 1), matrix(vec![n, k], 2));
+            let expected = reference(
+                &at.transpose(0, 1).unwrap().contiguous().unwrap(),
+                &bt.transpose(0, 1).unwrap().contiguous().unwrap(),
+            );
+            let (gpu_at, gpu_bt) = (
+                at.clone().to(&device).unwrap(),
+                bt.clone().to(&device).unwrap(),
+            );
+            let result = gpu_at
+                .transpose(0, 1)
+                .unwrap()
+                .matmul(&gpu_bt.transpose(0, 1).unwrap())
+                .unwrap();
+            assert_eq!(
+                result.to(&Arc::new(CPU)).unwrap().to_vec::<f32>().unwrap(),
+                expected
+            );
+        }
+        let a = matrix(vec![3, 4], 0).to(&device).unwrap();
+        assert!(matches!(a.matmul(&a), Err(TensorError::MatmulMismatch(..))));
+    }
+
+    #[tokio::test]
+    async fn gpu_matmul_batched_broadcast() {
+        let device = std::sync::Arc::new(WebGPU::new().await.unwrap());
+        let (a, b) = (matrix(vec![2, 1, 5, 8], 3), matrix(vec![3, 8, 7], 4));
+        let result = a
+            .clone()
+            .to(&device)
+            .unwrap()
+            .matmul(&b.clone().to(&device).unwrap())
+            .unwrap()
+            .to(&Arc::new(CPU))
+            .unwrap();
+        assert_eq!(result.shape(), &Shape::from(vec![2, 3, 5, 7]));
+        for i in 0..2 {
+            for j in 0..3 {
+                let expected = reference(
+                    &a.select(0, i).unwrap().select(0, 0).unwrap(),
+                    &b.select(0, j).unwrap(),
+                );
+                let actual = result.select(0, i).unwrap().select(0, j).unwrap();
+                assert_eq!(actual.to_vec::<f32>().unwrap(), expected);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn gpu_matmul_batch_beyond_dispatch_limit() {
+        let device = std::sync::Arc::new(WebGPU::new().await.unwrap());
+        let (a, b) = (matrix(vec![70000, 1, 2], 5), matrix(vec![70000, 2, 1], 6));
+        let result = a
+            .clone()
+            .to(&device)
+            .unwrap()
+            .matmul(&b.clone().to(&device).unwrap())
+            .unwrap()
+            .to(&Arc::new(CPU))
+            .unwrap();
+        let expected = a.matmul(&b).unwrap();
+        assert_eq!(result.shape(), expected.shape());
+        assert_eq!(
+            result.to_vec::<f32>().unwrap(),
+            expected.to_vec::<f32>().unwrap()
+        );
+    }
+}
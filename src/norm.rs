@@ -0,0 +1,521 @@
+use crate::{
+    as_std, wgsl, Castable, DType, Device, GPUHandle, GradOp, Kernel, KernelArg, Metadata, Shape,
+    Tensor, TensorError, WebGPU, Workgroups, CPU,
+};
+use num_traits::AsPrimitive;
+
+///Normalizations over the last dimension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NormOp {
+    Softmax,
+    LogSoftmax,
+    LayerNorm,
+    RmsNorm,
+}
+
+impl<D: Device> Tensor<D> {
+    ///Size of the last dimension, normalized over.
+    fn norm_dim(&self) -> Result<usize, TensorError> {
+        match self.shape().as_slice().last() {
+            Some(&n) => Ok(n),
+            None => Err(TensorError::InvalidDims {
+                dims: vec![0],
+                rank: 0,
+            }),
+        }
+    }
+
+    ///Records `dst` as the result of normalizing `self`.
+    fn record_norm(
+        &self,
+        dst: Tensor<D>,
+        op: NormOp,
+        weight: Option<&Tensor<D>>,
+        bias: Option<&Tensor<D>>,
+        eps: f32,
+    ) -> Tensor<D> {
+        let grad_op = match op {
+            NormOp::Softmax => GradOp::Softmax,
+            NormOp::LogSoftmax => GradOp::LogSoftmax,
+            NormOp::LayerNorm => GradOp::LayerNorm {
+                eps,
+                weight: weight.is_some(),
+                bias: bias.is_some(),
+            },
+            NormOp::RmsNorm => GradOp::RmsNorm {
+                eps,
+                weight: weight.is_some(),
+            },
+        };
+        let inputs = std::iter::once(self)
+            .chain(weight)
+            .chain(bias)
+            .collect::<Vec<_>>();
+        dst.record(grad_op, &inputs)
+    }
+
+    ///Checks an optional weight or bias has the DType of `self` and shape `[n]`.
+    fn check_affine(&self, param: Option<&Tensor<D>>, n: usize) -> Result<(), TensorError> {
+        let Some(param) = param else {
+            return Ok(());
+        };
+        self.check_device(param)?;
+        if param.dt() != self.dt() {
+            return Err(TensorError::DTypeMismatch {
+                expected: self.dt(),
+                actual: param.dt(),
+            });
+        }
+        if param.shape().as_slice() != [n] {
+            return Err(TensorError::BroadcastMismatch(
+                param.shape().clone(),
+                Shape::from(vec![n]),
+            ));
+        }
+        Ok(())
+    }
+}
+
+///Normalizations are computed in f64, and support the float DTypes.
+impl Tensor<CPU> {
+    ///`exp(x - max) / sum(exp(x - max))` over the last dimension.
+    pub fn softmax(&self) -> Result<Tensor<CPU>, TensorError> {
+        self.norm(NormOp::Softmax, None, None, 0.)
+    }
+
+    ///`x - max - log(sum(exp(x - max)))` over the last dimension.
+    pub fn log_softmax(&self) -> Result<Tensor<CPU>, TensorError> {
+        self.norm(NormOp::LogSoftmax, None, None, 0.)
+    }
+
+    ///`(x - mean) / sqrt(var + eps) * weight + bias` over the last dimension,
+    ///using the biased variance.
+    pub fn layer_norm(
+        &self,
+        weight: Option<&Tensor<CPU>>,
+        bias: Option<&Tensor<CPU>>,
+        eps: f32,
+    ) -> Result<Tensor<CPU>, TensorError> {
+        self.norm(NormOp::LayerNorm, weight, bias, eps)
+    }
+
+    ///`x / sqrt(mean(x²) + eps) * weight` over the last dimension.
+    pub fn rms_norm(
+        &self,
+        weight: Option<&Tensor<CPU>>,
+        eps: f32,
+    ) -> Result<Tensor<CPU>, TensorError> {
+        self.norm(NormOp::RmsNorm, weight, None, eps)
+    }
+
+    fn norm(
+        &self,
+        op: NormOp,
+        weight: Option<&Tensor<CPU>>,
+        bias: Option<&Tensor<CPU>>,
+        eps: f32,
+    ) -> Result<Tensor<CPU>, TensorError> {
+        let n = self.norm_dim()?;
+        self.check_affine(weight, n)?;
+        self.check_affine(bias, n)?;
+        if !self.dt().is_float() {
+            return Err(TensorError::UnsupportedDType(self.dt()));
+        }
+
+        fn norm_t<T: Castable>(
+            x: &Tensor<CPU>,
+            op: NormOp,
+            weight: Option<&Tensor<CPU>>,
+            bias: Option<&Tensor<CPU>>,
+            eps: f64,
+        ) -> Result<Tensor<CPU>, TensorError>
+        where
+            f64: AsPrimitive<T>,
+        {
+            let to_f64 = |t: &Tensor<CPU>| -> Result<Vec<f64>, TensorError> {
+                Ok(t.to_vec::<T>()?.into_iter().map(|v| v.as_()).collect())
+            };
+            let n = x.norm_dim()?;
+            let weight = weight.map(to_f64).transpose()?;
+            let bias = bias.map(to_f64).transpose()?;
+            let mut out = Vec::with_capacity(x.numel());
+            for row in to_f64(x)?.chunks(n.max(1)) {
+                let mean =
+                    |f: &dyn Fn(f64) -> f64| row.iter().map(|&v| f(v)).sum::<f64>() / n as f64;
+                let y: Box<dyn Fn(f64) -> f64> = match op {
+                    NormOp::Softmax | NormOp::LogSoftmax => {
+                        let max = row.iter().fold(f64::NEG_INFINITY, |m, &v| {
+                            if v.is_nan() || m.is_nan() {
+                                f64::NAN
+                            } else {
+                                m.max(v)
+                            }
+                        });
+                        let sum = row.iter().map(|&v| (v - max).exp()).sum::<f64>();
+                        if op == NormOp::Softmax {
+                            Box::new(move |v| (v - max).exp() / sum)
+                        } else {
+                            Box::new(move |v| v - max - sum.ln())
+                        }
+                    }
+                    NormOp::LayerNorm => {
+                        let mu = mean(&|v| v);
+                        let var = mean(&|v| (v - mu) * (v - mu));
+                        let inv = 1. / (var + eps).sqrt();
+                        Box::new(move |v| (v - mu) * inv)
+                    }
+                    NormOp::RmsNorm => {
+                        let inv = 1. / (mean(&|v| v * v) + eps).sqrt();
+                        Box::new(move |v| v * inv)
+                    }
+                };
+                for (j, &v) in row.iter().enumerate() {
+                    let w = weight.as_ref().map_or(1., |w| w[j]);
+                    let b = bias.as_ref().map_or(0., |b| b[j]);
+                    out.push((y(v) * w + b).as_());
+                }
+            }
+            Tensor::new(x.shape().clone(), out)
+        }
+        let dst = as_std!(norm_t(self.dt())(self, op, weight, bias, eps as f64))?;
+        Ok(self.record_norm(dst, op, weight, bias, eps))
+    }
+}
+
+///Normalizations on the GPU support F32, and match the CPU within a relative error of 1e-5.
+///Each is a single fused kernel, which gathers the statistics of a row in one pass
+///(online softmax, Welford's algorithm) before writing it.
+impl Tensor<WebGPU> {
+    ///`exp(x - max) / sum(exp(x - max))` over the last dimension.
+    pub fn softmax(&self) -> Result<Tensor<WebGPU>, TensorError> {
+        self.norm(NormOp::Softmax, None, None, 0.)
+    }
+
+    ///`x - max - log(sum(exp(x - max)))` over the last dimension.
+    pub fn log_softmax(&self) -> Result<Tensor<WebGPU>, TensorError> {
+        self.norm(NormOp::LogSoftmax, None, None, 0.)
+    }
+
+    ///`(x - mean) / sqrt(var + eps) * weight + bias` over the last dimension,
+    ///using the biased variance.
+    pub fn layer_norm(
+        &self,
+        weight: Option<&Tensor<WebGPU>>,
+        bias: Option<&Tensor<WebGPU>>,
+        eps: f32,
+    ) -> Result<Tensor<WebGPU>, TensorError> {
+        self.norm(NormOp::LayerNorm, weight, bias, eps)
+    }
+
+    ///`x / sqrt(mean(x²) + eps) * weight` over the last dimension.
+    pub fn rms_norm(
+        &self,
+        weight: Option<&Tensor<WebGPU>>,
+        eps: f32,
+    ) -> Result<Tensor<WebGPU>, TensorError> {
+        self.norm(NormOp::RmsNorm, weight, None, eps)
+    }
+
+    fn norm(
+        &self,
+        op: NormOp,
+        weight: Option<&Tensor<WebGPU>>,
+        bias: Option<&Tensor<WebGPU>>,
+        eps: f32,
+    ) -> Result<Tensor<WebGPU>, TensorError> {
+        let n = self.norm_dim()?;
+        self.check_affine(weight, n)?;
+        self.check_affine(bias, n)?;
+        let dt = self.dt();
+        if dt != DType::F32 {
+            return Err(TensorError::UnsupportedDType(dt));
+        }
+        let weight = weight.map(Tensor::<WebGPU>::contiguous).transpose()?;
+        let bias = bias.map(Tensor::<WebGPU>::contiguous).transpose()?;
+        let dst = Tensor::uninit(self.storage().device().clone(), dt, self.shape().clone())?;
+        let rows = self.numel() / n.max(1);
+        if dst.numel() > 0 {
+            let handle = self.device().handle();
+            let meta = Metadata::new()
+                .push(rows)
+                .push(n)
+                .push(eps.to_bits() as usize)
+                .push_layout(self)
+                .upload(handle)?;
+            let mut args = vec![KernelArg::tensor(self), KernelArg::output(&dst)];
+            args.extend(weight.iter().chain(&bias).map(KernelArg::tensor));
+            args.push(KernelArg::uniform(&meta));
+            let source = NormKernel::source(op, weight.is_some(), bias.is_some());
+            Kernel::new("norm", source, dt).dispatch(
+                handle,
+                &args,
+                Workgroups::linear(rows * GPUHandle::WORKGROUP_SIZE as usize),
+            );
+        }
+        Ok(self.record_norm(dst, op, weight.as_ref(), bias.as_ref(), eps))
+    }
+}
+
+///One workgroup per row: invocations gather statistics over a strided share of the row,
+///combine them as a tree in workgroup memory, then write their share of the output.
+///Metadata layout: `[rows, n, eps bits, rank, offset, shape.., strides..]`.
+struct NormKernel;
+
+impl NormKernel {
+    ///WGSL for the statistics of a row, held in `(a, b, c)`:
+    ///`fn init() -> vec3<f32>`, `fn push(s, x) -> vec3<f32>` and `fn merge(s, t) -> vec3<f32>`.
+    fn statistics(op: NormOp) -> &'static str {
+        match op {
+            NormOp::Softmax | NormOp::LogSoftmax => {
+                r#"
+//(running max, sum of exp(x - max), unused)
+fn init() -> vec3<f32> {
+    return vec3<f32>(bitcast<f32>(0xff800000u), 0.0, 0.0);
+}
+
+fn rescale(s: f32, old_max: f32, new_max: f32) -> f32 {
+    if (old_max == new_max) {
+        return s;
+    }
+    return s * exp(old_max - new_max);
+}
+
+fn merge(s: vec3<f32>, t: vec3<f32>) -> vec3<f32> {
+    let m = max(s.x, t.x);
+    return vec3<f32>(m, rescale(s.y, s.x, m) + rescale(t.y, t.x, m), 0.0);
+}
+
+fn push(s: vec3<f32>, x: f32) -> vec3<f32> {
+    return merge(s, vec3<f32>(x, 1.0, 0.0));
+}
+"#
+            }
+            NormOp::LayerNorm => {
+                r#"
+//(count, mean, sum of squared deviations), combined with Chan's parallel Welford update.
+fn init() -> vec3<f32> {
+    return vec3<f32>(0.0);
+}
+
+fn merge(s: vec3<f32>, t: vec3<f32>) -> vec3<f32> {
+    let count = s.x + t.x;
+    if (count == 0.0) {
+        return s;
+    }
+    let delta = t.y - s.y;
+    let mean = s.y + delta * t.x / count;
+    return vec3<f32>(count, mean, s.z + t.z + delta * delta * s.x * t.x / count);
+}
+
+fn push(s: vec3<f32>, x: f32) -> vec3<f32> {
+    let count = s.x + 1.0;
+    let delta = x - s.y;
+    let mean = s.y + delta / count;
+    return vec3<f32>(count, mean, s.z + delta * (x - mean));
+}
+"#
+            }
+            NormOp::RmsNorm => {
+                r#"
+//(sum of squares, unused, unused)
+fn init() -> vec3<f32> {
+    return vec3<f32>(0.0);
+}
+
+fn merge(s: vec3<f32>, t: vec3<f32>) -> vec3<f32> {
+    return s + t;
+}
+
+fn push(s: vec3<f32>, x: f32) -> vec3<f32> {
+    return vec3<f32>(s.x + x * x, 0.0, 0.0);
+}
+"#
+            }
+        }
+    }
+
+    ///WGSL expression normalizing `x` given the statistics `s` of its row.
+    fn normalize(op: NormOp) -> &'static str {
+        match op {
+            NormOp::Softmax => "exp(x - s.x) / s.y",
+            NormOp::LogSoftmax => "x - s.x - log(s.y)",
+            NormOp::LayerNorm => "(x - s.y) * inverseSqrt(s.z / f32(n) + eps)",
+            NormOp::RmsNorm => "x * inverseSqrt(s.x / f32(n) + eps)",
+        }
+    }
+
+    fn source(op: NormOp, weight: bool, bias: bool) -> String {
+        let mut bindings = String::new();
+        let mut binding = 2;
+        let mut affine = Self::normalize(op).to_string();
+        if weight {
+            bindings +=
+                &format!("@group(0) @binding({binding}) var<storage, read> weight: array<f32>;\n");
+            binding += 1;
+            affine = format!("({affine}) * weight[j]");
+        }
+        if bias {
+            bindings +=
+                &format!("@group(0) @binding({binding}) var<storage, read> bias: array<f32>;\n");
+            binding += 1;
+            affine = format!("{affine} + bias[j]");
+        }
+        format!(
+            r#"
+@group(0) @binding(0) var<storage, read> src: array<f32>;
+@group(0) @binding(1) var<storage, read_write> dst: array<f32>;
+{bindings}{meta}{strided_offset}{statistics}
+var<workgroup> stats: array<vec3<f32>, {wg}>;
+
+@compute @workgroup_size({wg})
+fn main(@builtin(workgroup_id) wg: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>, @builtin(local_invocation_index) lid: u32) {{
+    let row = wg.y * groups.x + wg.x;
+    let n = param(1u);
+    let eps = bitcast<f32>(param(2u));
+    if (row >= param(0u)) {{
+        return;
+    }}
+    var s = init();
+    for (var j = lid; j < n; j += {wg}u) {{
+        s = push(s, src[strided_offset(3u, row * n + j)]);
+    }}
+    stats[lid] = s;
+    workgroupBarrier();
+    for (var k = {half}u; k > 0u; k >>= 1u) {{
+        if (lid < k) {{
+            stats[lid] = merge(stats[lid], stats[lid + k]);
+        }}
+        workgroupBarrier();
+    }}
+    s = stats[0];
+    for (var j = lid; j < n; j += {wg}u) {{
+        let x = src[strided_offset(3u, row * n + j)];
+        dst[row * n + j] = {affine};
+    }}
+}}
+"#,
+            meta = Metadata::declaration(binding),
+            strided_offset = wgsl::STRIDED_OFFSET,
+            statistics = Self::statistics(op),
+            wg = GPUHandle::WORKGROUP_SIZE,
+            half = GPUHandle::WORKGROUP_SIZE / 2,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+
+    fn assert_close(actual: &Tensor<CPU>, expected: &[f32]) {
+        for (a, e) in actual.to_vec::<f32>().unwrap().iter().zip(expected) {
+            assert!((a - e).abs() <= 1e-5 * e.abs().max(1.), "{} != {}", a, e);
+        }
+    }
+
+    #[test]
+    fn cpu_norms() {
+        let x =
+            Tensor::<CPU>::new(vec![2, 3].into(), vec![1f32, 2., 3., 1000., 1000., 1000.]).unwrap();
+        let s = x.softmax().unwrap();
+        assert_close(
+            &s,
+            &[
+                0.09003057,
+                0.24472847,
+                0.66524096,
+                1. / 3.,
+                1. / 3.,
+                1. / 3.,
+            ],
+        );
+        assert_close(
+            &x.log_softmax().unwrap(),
+            &[
+                -2.407606,
+                -1.4076059,
+                -0.40760595,
+                -1.0986123,
+                -1.0986123,
+                -1.0986123,
+            ],
+        );
+
+        let w = Tensor::<CPU>::new(vec![3].into(), vec![1f32, 2., 0.5]).unwrap();
+        let b = Tensor::<CPU>::new(vec![3].into(), vec![0f32, 1., -1.]).unwrap();
+        let ln = x.layer_norm(Some(&w), Some(&b), 1e-5).unwrap();
+        assert_close(&ln, &[-1.2247449, 1., -0.38762756, 0., 1., -1.]);
+        let rms = x.rms_norm(None, 1e-6).unwrap();
+        assert_close(&rms, &[0.46291006, 0.9258201, 1.3887302, 1., 1., 1.]);
+
+        let half = x.cast(DType::F16).unwrap();
+        assert_eq!(half.softmax().unwrap().dt(), DType::F16);
+        assert!(matches!(
+            x.layer_norm(Some(&b.narrow(0, 0, 2).unwrap()), None, 0.),
+            Err(TensorError::BroadcastMismatch(..))
+        ));
+        assert!(matches!(
+            x.cast(DType::I32).unwrap().softmax(),
+            Err(TensorError::UnsupportedDType(DType::I32))
+        ));
+    }
+
+    #[tokio::test]
+    async fn gpu_norms_match_cpu() {
+        let device = std::sync::Arc::new(WebGPU::new().await.unwrap());
+        let data = (0..4 * 150)
+            .map(|i| ((i * 53) % 97) as f32 * 0.25 - 12.)
+            .collect::<Vec<_>>();
+        //Transposed, so the normalized dimension is strided.
+        let x = Tensor::<CPU>::new(vec![150, 4].into(), data)
+            .unwrap()
+            .transpose(0, 1)
+            .unwrap();
+        let w = Tensor::<CPU>::new(vec![150].into(), (0..150).map(|i| i as f32 / 75.).collect())
+            .unwrap();
+        let b = Tensor::<CPU>::new(
+            vec![150].into(),
+            (0..150).map(|i| 1. - i as f32 / 50.).collect(),
+        )
+        .unwrap();
+        let g = x.clone().to(&device).unwrap();
+        let gx = x.contiguous().unwrap().to(&device).unwrap();
+        assert!(!g.is_contiguous());
+        let (gw, gb) = (
+            w.clone().to(&device).unwrap(),
+            b.clone().to(&device).unwrap(),
+        );
+
+        for (cpu, gpu) in [(&x, &gx), (&x, &g)] {
+            assert_close(
+                &gpu.softmax().unwrap().to(&Arc::new(CPU)).unwrap(),
+                &cpu.softmax().unwrap().to_vec::<f32>().unwrap(),
+            );
+            assert_close(
+                &gpu.log_softmax().unwrap().to(&Arc::new(CPU)).unwrap(),
+                &cpu.log_softmax().unwrap().to_vec::<f32>().unwrap(),
+            );
+            assert_close(
+                &gpu.layer_norm(Some(&gw), Some(&gb), 1e-5)
+                    .unwrap()
+                    .to(&Arc::new(CPU))
+                    .unwrap(),
+                &cpu.layer_norm(Some(&w), Some(&b), 1e-5)
+                    .unwrap()
+                    .to_vec::<f32>()
+                    .unwrap(),
+            );
+            assert_close(
+                &gpu.rms_norm(Some(&gw), 1e-5)
+                    .unwrap()
+                    .to(&Arc::new(CPU))
+                    .unwrap(),
+                &cpu.rms_norm(Some(&w), 1e-5)
+                    .unwrap()
+                    .to_vec::<f32>()
+                    .unwrap(),
+            );
+        }
+    }
+}
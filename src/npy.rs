@@ -0,0 +1,459 @@
+use crate::{DType, Shape, Strides, Tensor, TensorError, CPU};
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+///The preamble and header of a file are padded to a multiple of this many bytes.
+const HEADER_ALIGNMENT: usize = 64;
+
+#[derive(thiserror::Error, Debug)]
+pub enum NpyError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Archive error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Not an NPY file, magic string is missing")]
+    BadMagic,
+    #[error("Unsupported NPY format version {0}.{1}")]
+    UnsupportedVersion(u8, u8),
+    #[error("Invalid NPY header {header:?}: {reason}")]
+    InvalidHeader {
+        header: String,
+        reason: &'static str,
+    },
+    #[error("NumPy dtype {0:?} is not supported")]
+    UnsupportedDescr(String),
+    #[error("DType {0:?} has no NumPy equivalent")]
+    UnsupportedDType(DType),
+    #[error("Array of shape {shape:?} and dtype {dt:?} does not fit in {len} bytes")]
+    ShapeMismatch { shape: Shape, dt: DType, len: usize },
+    #[error("Tensor error: {0}")]
+    Tensor(#[from] TensorError),
+}
+
+///Parses a NumPy type string such as `<f4`, returning the dtype and whether it is big-endian.
+fn parse_descr(descr: &str) -> Option<(DType, bool)> {
+    let (order, ty) = descr.split_at_checked(1)?;
+    let dt = match ty {
+        "u1" => DType::U8,
+        "u2" => DType::U16,
+        "u4" => DType::U32,
+        "u8" => DType::U64,
+        "i1" => DType::I8,
+        "i2" => DType::I16,
+        "i4" => DType::I32,
+        "i8" => DType::I64,
+        "f2" => DType::F16,
+        "f4" => DType::F32,
+        "f8" => DType::F64,
+        _ => return None,
+    };
+    let big_endian = match order {
+        "<" => false,
+        ">" => true,
+        "=" => cfg!(target_endian = "big"),
+        "|" if dt.size_of() == 1 => false,
+        _ => return None,
+    };
+    Some((dt, big_endian))
+}
+
+///The native-endian NumPy type string of `dt`. NumPy has no bfloat16 nor block-quantized types.
+fn descr(dt: DType) -> Result<String, NpyError> {
+    let ty = match dt {
+        DType::U8 => "u1",
+        DType::U16 => "u2",
+        DType::U32 => "u4",
+        DType::U64 => "u8",
+        DType::I8 => "i1",
+        DType::I16 => "i2",
+        DType::I32 => "i4",
+        DType::I64 => "i8",
+        DType::F16 => "f2",
+        DType::F32 => "f4",
+        DType::F64 => "f8",
+        DType::BF16 | DType::Q4_0 | DType::Q8_0 => return Err(NpyError::UnsupportedDType(dt)),
+    };
+    let order = match dt.size_of() {
+        1 => '|',
+        _ if cfg!(target_endian = "big") => '>',
+        _ => '<',
+    };
+    Ok(format!("{}{}", order, ty))
+}
+
+///A value of the Python dict literal in an NPY header.
+#[derive(Debug, PartialEq)]
+enum HeaderValue {
+    Str(String),
+    Bool(bool),
+    Tuple(Vec<usize>),
+}
+
+///Parser for the subset of Python literals used by NPY headers, e.g.
+///`{'descr': '<f4', 'fortran_order': False, 'shape': (3, 4), }`.
+struct HeaderParser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> HeaderParser<'a> {
+    fn parse(header: &'a str) -> Option<HashMap<String, HeaderValue>> {
+        let mut parser = Self { rest: header };
+        let mut dict = HashMap::new();
+        parser.expect("{")?;
+        while !parser.eat("}") {
+            let key = parser.string()?;
+            parser.expect(":")?;
+            dict.insert(key, parser.value()?);
+            if !parser.eat(",") {
+                parser.expect("}")?;
+                break;
+            }
+        }
+        parser.rest.trim().is_empty().then_some(dict)
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Option<()> {
+        self.eat(token).then_some(())
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.rest = self.rest.trim_start();
+        let quote = self
+            .rest
+            .chars()
+            .next()
+            .filter(|&c| c == '\'' || c == '"')?;
+        let (value, rest) = self.rest[1..].split_once(quote)?;
+        self.rest = rest;
+        Some(value.to_string())
+    }
+
+    fn value(&mut self) -> Option<HeaderValue> {
+        if self.eat("True") {
+            return Some(HeaderValue::Bool(true));
+        }
+        if self.eat("False") {
+            return Some(HeaderValue::Bool(false));
+        }
+        if !self.eat("(") {
+            return self.string().map(HeaderValue::Str);
+        }
+        let mut dims = vec![];
+        while !self.eat(")") {
+            self.rest = self.rest.trim_start();
+            let end = self
+                .rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(self.rest.len());
+            dims.push(self.rest[..end].parse().ok()?);
+            self.rest = &self.rest[end..];
+            if !self.eat(",") {
+                self.expect(")")?;
+                break;
+            }
+        }
+        Some(HeaderValue::Tuple(dims))
+    }
+}
+
+///Reads an array in the NPY format.
+///Big-endian data is converted to native order, and Fortran-ordered arrays are returned
+///as column-major views, without reordering their elements.
+pub fn read_npy(mut reader: impl Read) -> Result<Tensor<CPU>, NpyError> {
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble)?;
+    if &preamble[..6] != MAGIC {
+        return Err(NpyError::BadMagic);
+    }
+    let len = match (preamble[6], preamble[7]) {
+        (1, 0) => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        }
+        (2 | 3, 0) => {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        (major, minor) => return Err(NpyError::UnsupportedVersion(major, minor)),
+    };
+    let mut header = vec![0u8; len];
+    reader.read_exact(&mut header)?;
+    let header = String::from_utf8_lossy(&header).into_owned();
+    let invalid = |reason| NpyError::InvalidHeader {
+        header: header.clone(),
+        reason,
+    };
+
+    let mut dict = HeaderParser::parse(&header).ok_or_else(|| invalid("not a Python dict"))?;
+    let (dt, big_endian) = match dict.remove("descr") {
+        Some(HeaderValue::Str(descr)) => {
+            parse_descr(&descr).ok_or(NpyError::UnsupportedDescr(descr))?
+        }
+        _ => return Err(invalid("missing string `descr`")),
+    };
+    let fortran_order = match dict.remove("fortran_order") {
+        Some(HeaderValue::Bool(fortran_order)) => fortran_order,
+        _ => return Err(invalid("missing bool `fortran_order`")),
+    };
+    let shape = match dict.remove("shape") {
+        Some(HeaderValue::Tuple(dims)) => Shape::from(dims),
+        _ => return Err(invalid("missing tuple `shape`")),
+    };
+
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+    //The shape is untrusted, its extent must fit in memory even along empty dimensions.
+    let len = shape
+        .iter()
+        .filter(|&&d| d != 0)
+        .try_fold(dt.size_of(), |acc, &d| acc.checked_mul(d))
+        .map(|len| {
+            if shape.as_slice().contains(&0) {
+                0
+            } else {
+                len
+            }
+        });
+    if len != Some(data.len()) {
+        return Err(NpyError::ShapeMismatch {
+            shape,
+            dt,
+            len: data.len(),
+        });
+    }
+    if big_endian != cfg!(target_endian = "big") {
+        data.chunks_exact_mut(dt.size_of())
+            .for_each(|element| element.reverse());
+    }
+
+    let flat = Tensor::<CPU>::from_bytes(dt, vec![shape.numel()].into(), &data)?;
+    let strides = if fortran_order {
+        Strides::column_major(&shape)
+    } else {
+        Strides::from(&shape)
+    };
+    Ok(flat.view_of(shape, strides))
+}
+
+///Writes a tensor in the NPY format, in native byte order.
+///Strided views are written in row-major order.
+pub fn write_npy(tensor: &Tensor<CPU>, mut writer: impl Write) -> Result<(), NpyError> {
+    let shape = match tensor.shape().as_slice() {
+        [dim] => format!("({},)", dim),
+        dims => format!(
+            "({})",
+            dims.iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr(tensor.dt())?,
+        shape
+    );
+    //The header is terminated by a newline, and padded with spaces before it.
+    let preamble = MAGIC.len() + 4;
+    let padded = (preamble + header.len() + 1).next_multiple_of(HEADER_ALIGNMENT);
+    header.extend(std::iter::repeat_n(
+        ' ',
+        padded - preamble - header.len() - 1,
+    ));
+    header.push('\n');
+    let len = u16::try_from(header.len()).map_err(|_| NpyError::InvalidHeader {
+        header: header.clone(),
+        reason: "too long for format version 1.0",
+    })?;
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(&tensor.to_bytes()?)?;
+    Ok(())
+}
+
+///Loads a `.npy` file, see [`read_npy`].
+pub fn load_npy(path: impl AsRef<Path>) -> Result<Tensor<CPU>, NpyError> {
+    read_npy(std::io::BufReader::new(std::fs::File::open(path)?))
+}
+
+///Saves a tensor to a `.npy` file, see [`write_npy`].
+pub fn save_npy(tensor: &Tensor<CPU>, path: impl AsRef<Path>) -> Result<(), NpyError> {
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+    write_npy(tensor, &mut writer)?;
+    Ok(writer.flush()?)
+}
+
+///Reads every array of an NPZ archive, as written by `numpy.savez` or `numpy.savez_compressed`.
+///Arrays are keyed by their name in the archive, without the `.npy` extension.
+pub fn read_npz(reader: impl Read + Seek) -> Result<HashMap<String, Tensor<CPU>>, NpyError> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    let mut tensors = HashMap::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        let name = file.name();
+        let name = name.strip_suffix(".npy").unwrap_or(name).to_string();
+        tensors.insert(name, read_npy(file)?);
+    }
+    Ok(tensors)
+}
+
+///Writes tensors as an uncompressed NPZ archive, like `numpy.savez`, ordered by name.
+pub fn write_npz(
+    tensors: &HashMap<String, Tensor<CPU>>,
+    writer: impl Write + Seek,
+) -> Result<(), NpyError> {
+    let mut names = tensors.keys().collect::<Vec<_>>();
+    names.sort();
+    let mut archive = zip::ZipWriter::new(writer);
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for name in names {
+        archive.start_file(format!("{}.npy", name), options)?;
+        write_npy(&tensors[name], &mut archive)?;
+    }
+    archive.finish()?;
+    Ok(())
+}
+
+///Loads a `.npz` file, see [`read_npz`].
+pub fn load_npz(path: impl AsRef<Path>) -> Result<HashMap<String, Tensor<CPU>>, NpyError> {
+    read_npz(std::io::BufReader::new(std::fs::File::open(path)?))
+}
+
+///Saves tensors to a `.npz` file, see [`write_npz`].
+pub fn save_npz(
+    tensors: &HashMap<String, Tensor<CPU>>,
+    path: impl AsRef<Path>,
+) -> Result<(), NpyError> {
+    write_npz(tensors, std::fs::File::create(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use half::f16;
+    use std::collections::HashMap;
+
+    fn npy(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn reads_fortran_order_and_big_endian() {
+        //numpy.asfortranarray(numpy.arange(6, dtype='>i4').reshape(2, 3))
+        let data = [0i32, 3, 1, 4, 2, 5]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect::<Vec<_>>();
+        let bytes = npy(
+            "{'descr': '>i4', 'fortran_order': True, 'shape': (2, 3), }  \n",
+            &data,
+        );
+        let tensor = read_npy(bytes.as_slice()).unwrap();
+        assert_eq!(tensor.shape(), &Shape::from(vec![2, 3]));
+        assert_eq!(tensor.strides(), &Strides::new(vec![1, 2]));
+        assert!(!tensor.is_contiguous());
+        assert_eq!(tensor.to_vec::<i32>().unwrap(), vec![0, 1, 2, 3, 4, 5]);
+
+        let scalar = npy(
+            "{\"descr\":\"<f8\",\"fortran_order\":False,\"shape\":()}\n",
+            &2.5f64.to_le_bytes(),
+        );
+        assert_eq!(
+            read_npy(scalar.as_slice()).unwrap().item::<f64>().unwrap(),
+            2.5
+        );
+
+        let read = |header: &str, data: &[u8]| read_npy(npy(header, data).as_slice()).unwrap_err();
+        assert!(matches!(
+            read_npy(&b"PK\x03\x04\x00\x00\x00\x00\x00\x00"[..]),
+            Err(NpyError::BadMagic)
+        ));
+        assert!(matches!(
+            read("{'descr': '<c8', 'fortran_order': False, 'shape': (1,), }", &[0; 8]),
+            NpyError::UnsupportedDescr(descr) if descr == "<c8"
+        ));
+        assert!(matches!(
+            read("{'descr': '<f4', 'shape': (1,), }", &[0; 4]),
+            NpyError::InvalidHeader { .. }
+        ));
+        assert!(matches!(
+            read(
+                "{'descr': '<f4', 'fortran_order': False, 'shape': (2,), }",
+                &[0; 4]
+            ),
+            NpyError::ShapeMismatch { len: 4, .. }
+        ));
+        //The extent wraps around to 0 bytes without overflow checks.
+        for shape in ["(4611686018427387904, 4)", "(0, 4294967296, 4294967296)"] {
+            let header = format!(
+                "{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}",
+                shape
+            );
+            assert!(matches!(
+                read(&header, &[]),
+                NpyError::ShapeMismatch { len: 0, .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn npy_and_npz_roundtrip() {
+        let matrix = Tensor::<CPU>::new(vec![2, 3].into(), vec![1f32, 2., 3., 4., 5., 6.]).unwrap();
+        let mut bytes = vec![];
+        write_npy(&matrix, &mut bytes).unwrap();
+        assert_eq!((bytes.len() - 24) % 64, 0);
+        assert_eq!(bytes[..8], *b"\x93NUMPY\x01\x00");
+        assert_eq!(read_npy(bytes.as_slice()).unwrap(), matrix);
+
+        let tensors = HashMap::from([
+            ("transposed".to_string(), matrix.transpose(0, 1).unwrap()),
+            (
+                "half".to_string(),
+                Tensor::<CPU>::new(vec![3].into(), [0.5, -2., 3.25].map(f16::from_f32).to_vec())
+                    .unwrap(),
+            ),
+            (
+                "scalar".to_string(),
+                Tensor::<CPU>::new(vec![].into(), vec![-7i64]).unwrap(),
+            ),
+        ]);
+        let path = std::env::temp_dir().join(format!("{}.npz", nanoid::nanoid!()));
+        save_npz(&tensors, &path).unwrap();
+        let loaded = load_npz(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.len(), 3);
+        for (name, tensor) in &tensors {
+            assert_eq!(&loaded[name], tensor, "{}", name);
+        }
+
+        let bf16 = Tensor::<CPU>::new(vec![1].into(), vec![half::bf16::ONE]).unwrap();
+        assert!(matches!(
+            write_npy(&bf16, vec![]),
+            Err(NpyError::UnsupportedDType(DType::BF16))
+        ));
+    }
+}
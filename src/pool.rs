@@ -0,0 +1,205 @@
+use crate::AllocMode;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+///Limits on how many freed buffers the [`BufferPool`] holds on to.
+///Once either limit would be exceeded, freed buffers are destroyed instead of pooled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+    ///Maximum number of free buffers kept in a single (size, mode) bucket.
+    pub max_buffers_per_bucket: usize,
+    ///Maximum number of bytes held across all free buffers.
+    pub max_pooled_bytes: u64,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_buffers_per_bucket: 64,
+            max_pooled_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+///Counters describing how effective the pool has been.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    ///Allocations served from a pooled buffer.
+    pub hits: u64,
+    ///Allocations that required a new buffer.
+    pub misses: u64,
+    ///Freed buffers returned to the pool.
+    pub recycled: u64,
+    ///Freed buffers destroyed because a high-water mark was reached.
+    pub evicted: u64,
+    ///Number of free buffers currently held.
+    pub pooled_buffers: usize,
+    ///Number of bytes currently held in free buffers.
+    pub pooled_bytes: u64,
+}
+
+impl PoolStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+///Buffers are bucketed by their exact byte size and usage.
+type BucketKey = (u64, AllocMode);
+
+#[derive(Debug, Default)]
+struct PoolInner {
+    free: HashMap<BucketKey, Vec<wgpu::Buffer>>,
+    stats: PoolStats,
+}
+
+///BufferPool recycles freed [`wgpu::Buffer`]s, so that repeated allocations
+///of the same size and [`AllocMode`] avoid a round trip to the driver.
+#[derive(Debug, Default)]
+pub struct BufferPool {
+    config: PoolConfig,
+    inner: Mutex<PoolInner>,
+}
+
+impl BufferPool {
+    pub fn new(config: PoolConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(PoolInner::default()),
+        }
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.inner.lock().unwrap().stats
+    }
+
+    ///Takes a free buffer matching the size and mode, if one exists.
+    ///Records a hit or a miss.
+    pub fn acquire(&self, size: u64, mode: AllocMode) -> Option<wgpu::Buffer> {
+        let mut inner = self.inner.lock().unwrap();
+        let buffer = inner.free.get_mut(&(size, mode)).and_then(Vec::pop);
+        match buffer {
+            Some(_) => {
+                inner.stats.hits += 1;
+                inner.stats.pooled_buffers -= 1;
+                inner.stats.pooled_bytes -= size;
+            }
+            None => inner.stats.misses += 1,
+        }
+        buffer
+    }
+
+    ///Returns a buffer to the pool.
+    ///If a high-water mark would be exceeded, the buffer is destroyed.
+    pub fn release(&self, buffer: wgpu::Buffer) {
+        let size = buffer.size();
+        let mode = AllocMode::from(buffer.usage());
+        let mut inner = self.inner.lock().unwrap();
+        let over_bytes = inner.stats.pooled_bytes + size > self.config.max_pooled_bytes;
+        let bucket_len = inner.free.get(&(size, mode)).map_or(0, Vec::len);
+        if over_bytes || bucket_len >= self.config.max_buffers_per_bucket {
+            buffer.destroy();
+            inner.stats.evicted += 1;
+            return;
+        }
+        inner.free.entry((size, mode)).or_default().push(buffer);
+        inner.stats.recycled += 1;
+        inner.stats.pooled_buffers += 1;
+        inner.stats.pooled_bytes += size;
+    }
+
+    ///Destroys every free buffer held by the pool.
+    pub fn trim(&self) {
+        self.trim_to(0);
+    }
+
+    ///Destroys free buffers, largest first, until at most `max_bytes` are pooled.
+    pub fn trim_to(&self, max_bytes: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let PoolInner { free, stats } = &mut *inner;
+        let mut keys = free.keys().copied().collect::<Vec<_>>();
+        keys.sort_by_key(|k| std::cmp::Reverse(k.0));
+        for key in keys {
+            let bucket = free.get_mut(&key).unwrap();
+            while stats.pooled_bytes > max_bytes {
+                let Some(buffer) = bucket.pop() else {
+                    break;
+                };
+                buffer.destroy();
+                stats.pooled_buffers -= 1;
+                stats.pooled_bytes -= key.0;
+            }
+        }
+        free.retain(|_, bucket| !bucket.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[tokio::test]
+    async fn recycles_freed_buffers() {
+        let device = WebGPU::new().await.unwrap();
+        let layout = std::alloc::Layout::from_size_align(64, 4).unwrap();
+        let alloc = || {
+            device
+                .allocate(layout, AllocMode::COPY_SRC | AllocMode::COPY_DST)
+                .unwrap()
+        };
+
+        let first = alloc();
+        device.deallocate(first, layout).unwrap();
+        let second = alloc();
+
+        let pool = device.handle().pool();
+        assert_eq!(pool.stats().misses, 1);
+        assert_eq!(pool.stats().hits, 1);
+
+        device.deallocate(second, layout).unwrap();
+        assert_eq!(pool.stats().pooled_buffers, 1);
+        assert_eq!(pool.stats().pooled_bytes, 64);
+        pool.trim();
+        assert_eq!(pool.stats().pooled_buffers, 0);
+    }
+
+    #[tokio::test]
+    async fn reinitializes_pooled_buffers_with_unaligned_data() {
+        let device = WebGPU::new().await.unwrap();
+        let handle = device.handle();
+        let layout = std::alloc::Layout::from_size_align(6, 2).unwrap();
+        let mode = AllocMode::COPY_SRC | AllocMode::COPY_DST;
+        let first = unsafe { handle.alloc_init(layout, &[0; 6], mode) };
+        device.deallocate(first, layout).unwrap();
+        let second = unsafe { handle.alloc_init(layout, &[1, 2, 3, 4, 5, 6], mode) };
+        assert_eq!(handle.pool().stats().hits, 1);
+        let mut host = [0u8; 6];
+        device.copy_to_host(&second, &mut host).unwrap();
+        assert_eq!(host, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[tokio::test]
+    async fn eviction_leaves_no_empty_bucket() {
+        let device = WebGPU::with_pool_config(PoolConfig {
+            max_buffers_per_bucket: 4,
+            max_pooled_bytes: 0,
+        })
+        .await
+        .unwrap();
+        let layout = std::alloc::Layout::from_size_align(64, 4).unwrap();
+        let buffer = device.allocate(layout, AllocMode::COPY_DST).unwrap();
+        device.deallocate(buffer, layout).unwrap();
+        let pool = device.handle().pool();
+        assert_eq!(pool.stats().evicted, 1);
+        assert!(pool.inner.lock().unwrap().free.is_empty());
+    }
+}
@@ -0,0 +1,478 @@
+use crate::{
+    no_grad, wgsl, DType, GPUHandle, Kernel, KernelArg, Metadata, Shape, Tensor, TensorError,
+    WebGPU, Workgroups, CPU,
+};
+use half::f16;
+use std::fmt::Debug;
+
+///Elements per block of every block-quantized dtype.
+pub const QK: usize = 32;
+
+///A block of [`DType::Q4_0`], in the layout used by GGML.
+///Element `i` of the low half is `((qs[i] & 0xF) - 8) * d`, element `i` of the high half
+///is `((qs[i] >> 4) - 8) * d`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockQ4_0 {
+    pub d: f16,
+    pub qs: [u8; QK / 2],
+}
+
+///A block of [`DType::Q8_0`], in the layout used by GGML.
+///Element `i` is `qs[i] * d`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockQ8_0 {
+    pub d: f16,
+    pub qs: [i8; QK],
+}
+
+//SAFETY: both blocks are repr(C), made of Pod fields without padding.
+unsafe impl bytemuck::Zeroable for BlockQ4_0 {}
+unsafe impl bytemuck::Pod for BlockQ4_0 {}
+unsafe impl bytemuck::Zeroable for BlockQ8_0 {}
+unsafe impl bytemuck::Pod for BlockQ8_0 {}
+
+///The storage unit of a block-quantized [`DType`], holding [`QK`] elements.
+pub trait QuantBlock: bytemuck::Pod + Debug {
+    const DTYPE: DType;
+
+    ///Quantizes [`QK`] elements, following the reference implementation of GGML.
+    fn quantize(src: &[f32]) -> Self;
+
+    ///Writes the [`QK`] elements of the block to `dst`.
+    fn dequantize(&self, dst: &mut [f32]);
+}
+
+impl QuantBlock for BlockQ4_0 {
+    const DTYPE: DType = DType::Q4_0;
+
+    ///The element of largest magnitude maps to -8, keeping its sign in the scale,
+    ///so the range is used asymmetrically in its favour.
+    fn quantize(src: &[f32]) -> Self {
+        let max = src
+            .iter()
+            .copied()
+            .fold(0f32, |max, x| if x.abs() > max.abs() { x } else { max });
+        let d = max / -8.;
+        let id = if d != 0. { 1. / d } else { 0. };
+        let q = |x: f32| ((x * id + 8.5) as u8).min(15);
+        let (lo, hi) = src.split_at(QK / 2);
+        Self {
+            d: f16::from_f32(d),
+            qs: std::array::from_fn(|i| q(lo[i]) | (q(hi[i]) << 4)),
+        }
+    }
+
+    fn dequantize(&self, dst: &mut [f32]) {
+        let d = self.d.to_f32();
+        let (lo, hi) = dst.split_at_mut(QK / 2);
+        for ((q, lo), hi) in self.qs.iter().zip(lo).zip(hi) {
+            *lo = ((q & 0xF) as i32 - 8) as f32 * d;
+            *hi = ((q >> 4) as i32 - 8) as f32 * d;
+        }
+    }
+}
+
+impl QuantBlock for BlockQ8_0 {
+    const DTYPE: DType = DType::Q8_0;
+
+    fn quantize(src: &[f32]) -> Self {
+        let amax = src.iter().fold(0f32, |max, x| max.max(x.abs()));
+        let d = amax / 127.;
+        let id = if d != 0. { 1. / d } else { 0. };
+        Self {
+            d: f16::from_f32(d),
+            qs: std::array::from_fn(|i| (src[i] * id).round() as i8),
+        }
+    }
+
+    fn dequantize(&self, dst: &mut [f32]) {
+        let d = self.d.to_f32();
+        for (q, x) in self.qs.iter().zip(dst) {
+            *x = *q as f32 * d;
+        }
+    }
+}
+
+///Quantized tensors are stored as whole blocks of each row, so the last dimension
+///must be a multiple of the block size.
+pub(crate) fn check_blocks(dt: DType, shape: &Shape) -> Result<(), TensorError> {
+    let last = shape.as_slice().last().copied().unwrap_or(1);
+    if last % dt.block_size() != 0 {
+        return Err(TensorError::BlockMismatch(shape.clone(), dt.block_size()));
+    }
+    Ok(())
+}
+
+impl Tensor<CPU> {
+    ///Borrows the blocks of a block-quantized tensor laid out densely from the start of its storage.
+    pub fn as_blocks<B: QuantBlock>(&self) -> Result<&[B], TensorError> {
+        if self.dt() != B::DTYPE {
+            return Err(TensorError::DTypeMismatch {
+                expected: B::DTYPE,
+                actual: self.dt(),
+            });
+        }
+        if !self.is_packed() {
+            return Err(TensorError::NotContiguous(
+                self.shape().clone(),
+                self.strides().clone(),
+            ));
+        }
+        let bytes = &self.storage().as_bytes()[..self.dt().byte_len(self.numel())];
+        Ok(bytemuck::cast_slice(bytes))
+    }
+
+    ///Quantizes a float tensor to the block-quantized `dt`, blocks running along the last dimension.
+    pub fn quantize(&self, dt: DType) -> Result<Tensor<CPU>, TensorError> {
+        fn quantize_t<B: QuantBlock>(
+            src: &[f32],
+            shape: Shape,
+        ) -> Result<Tensor<CPU>, TensorError> {
+            let blocks = src.chunks_exact(QK).map(B::quantize).collect::<Vec<_>>();
+            Tensor::<CPU>::from_bytes(B::DTYPE, shape, bytemuck::cast_slice(&blocks))
+        }
+        if !self.dt().is_float() {
+            return Err(TensorError::UnsupportedDType(self.dt()));
+        }
+        check_blocks(dt, self.shape())?;
+        let src = no_grad(|| self.cast(DType::F32))?.to_vec::<f32>()?;
+        match dt {
+            DType::Q4_0 => quantize_t::<BlockQ4_0>(&src, self.shape().clone()),
+            DType::Q8_0 => quantize_t::<BlockQ8_0>(&src, self.shape().clone()),
+            _ => Err(TensorError::UnsupportedDType(dt)),
+        }
+    }
+
+    ///Converts to an F32 tensor of the same shape, expanding block-quantized dtypes.
+    ///Other dtypes are cast, see [`Tensor::cast`].
+    pub fn dequantize(&self) -> Result<Tensor<CPU>, TensorError> {
+        fn dequantize_t<B: QuantBlock>(t: &Tensor<CPU>) -> Result<Tensor<CPU>, TensorError> {
+            let mut dst = vec![0f32; t.numel()];
+            for (block, dst) in t.as_blocks::<B>()?.iter().zip(dst.chunks_exact_mut(QK)) {
+                block.dequantize(dst);
+            }
+            Tensor::new(t.shape().clone(), dst)
+        }
+        match self.dt() {
+            DType::Q4_0 => dequantize_t::<BlockQ4_0>(self),
+            DType::Q8_0 => dequantize_t::<BlockQ8_0>(self),
+            _ => self.cast(DType::F32),
+        }
+    }
+}
+
+impl<D: crate::Device> Tensor<D> {
+    ///Checks that `weight` is a block-quantized `[n, k]` matrix matching the last dimension of `self`,
+    ///returning the shape of the product and `(rows, n, k)`.
+    fn qmatmul_shape(
+        &self,
+        weight: &Tensor<D>,
+    ) -> Result<(Shape, (usize, usize, usize)), TensorError> {
+        self.check_device(weight)?;
+        let mismatch = || TensorError::MatmulMismatch(self.shape().clone(), weight.shape().clone());
+        if !weight.dt().is_quantized() {
+            return Err(TensorError::UnsupportedDType(weight.dt()));
+        }
+        let (&[n, k], Some(&last)) = (weight.shape().as_slice(), self.shape().as_slice().last())
+        else {
+            return Err(mismatch());
+        };
+        if last != k || self.shape().rank() < 2 {
+            return Err(mismatch());
+        }
+        let mut shape = self.shape().as_slice().to_vec();
+        *shape.last_mut().unwrap() = n;
+        Ok((shape.into(), (self.numel() / k.max(1), n, k)))
+    }
+}
+
+///`x.qmatmul(w)` computes `x @ w^T` for a block-quantized weight `w` of shape `[n, k]`,
+///the layout of linear layers in GGUF files, and `x` of shape `[..batch, m, k]`.
+///Gradients are not recorded through quantized products.
+impl Tensor<CPU> {
+    ///Dequantizes `weight` before multiplying, see [`Tensor::dequantize`].
+    pub fn qmatmul(&self, weight: &Tensor<CPU>) -> Result<Tensor<CPU>, TensorError> {
+        self.qmatmul_shape(weight)?;
+        let weight = weight.dequantize()?.transpose(0, 1)?;
+        no_grad(|| self.cast(DType::F32)?.matmul(&weight))
+    }
+}
+
+impl Tensor<WebGPU> {
+    ///Dequantizes blocks of `weight` as they are read, so it never expands in memory.
+    ///`self` must be F32.
+    pub fn qmatmul(&self, weight: &Tensor<WebGPU>) -> Result<Tensor<WebGPU>, TensorError> {
+        let (shape, (rows, n, k)) = self.qmatmul_shape(weight)?;
+        if self.dt() != DType::F32 {
+            return Err(TensorError::UnsupportedDType(self.dt()));
+        }
+        if !weight.is_packed() {
+            return Err(TensorError::NotContiguous(
+                weight.shape().clone(),
+                weight.strides().clone(),
+            ));
+        }
+        let x = self.contiguous()?;
+        let dst = Tensor::uninit(self.storage().device().clone(), DType::F32, shape)?;
+        if dst.numel() > 0 {
+            let handle = self.device().handle();
+            let meta = Metadata::new().push(rows).push(n).push(k).upload(handle)?;
+            Kernel::new("qmatmul", QMatmulKernel::source(weight.dt()), weight.dt()).dispatch(
+                handle,
+                &[
+                    KernelArg::tensor(&x),
+                    KernelArg::tensor(weight),
+                    KernelArg::output(&dst),
+                    KernelArg::uniform(&meta),
+                ],
+                Workgroups::linear(dst.numel()),
+            );
+        }
+        Ok(dst)
+    }
+}
+
+///Each invocation computes one element of `x @ w^T`, decoding the blocks of its row of `w`
+///from the raw bytes of the buffer.
+///Metadata layout: `[rows, n, k]`.
+struct QMatmulKernel;
+
+impl QMatmulKernel {
+    ///WGSL for `fn block_dot(o, xb) -> f32`, the dot product of the block at byte `o`
+    ///with the 32 elements of `x` starting at `xb`.
+    fn block_dot(dt: DType) -> &'static str {
+        match dt {
+            DType::Q4_0 => {
+                r#"
+fn block_dot(o: u32, xb: u32) -> f32 {
+    var sum = 0.0;
+    for (var j = 0u; j < 16u; j++) {
+        let q = byte_at(o + 2u + j);
+        sum += f32(i32(q & 15u) - 8) * x[xb + j] + f32(i32(q >> 4u) - 8) * x[xb + j + 16u];
+    }
+    return scale_at(o) * sum;
+}
+"#
+            }
+            DType::Q8_0 => {
+                r#"
+fn block_dot(o: u32, xb: u32) -> f32 {
+    var sum = 0.0;
+    for (var j = 0u; j < 32u; j++) {
+        let q = bitcast<i32>(byte_at(o + 2u + j) << 24u) >> 24u;
+        sum += f32(q) * x[xb + j];
+    }
+    return scale_at(o) * sum;
+}
+"#
+            }
+            _ => unreachable!("{:?} is not block-quantized", dt),
+        }
+    }
+
+    fn source(dt: DType) -> String {
+        format!(
+            r#"
+@group(0) @binding(0) var<storage, read> x: array<f32>;
+@group(0) @binding(1) var<storage, read> weights: array<u32>;
+@group(0) @binding(2) var<storage, read_write> dst: array<f32>;
+{meta}{global_index}
+fn byte_at(i: u32) -> u32 {{
+    return (weights[i / 4u] >> ((i % 4u) * 8u)) & 255u;
+}}
+
+fn scale_at(o: u32) -> f32 {{
+    return unpack2x16float(byte_at(o) | (byte_at(o + 1u) << 8u)).x;
+}}
+{block_dot}
+@compute @workgroup_size({wg})
+fn main(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {{
+    let i = global_index(gid, groups);
+    let n = param(1u);
+    let k = param(2u);
+    if (i >= param(0u) * n) {{
+        return;
+    }}
+    let row = i / n;
+    let col = i % n;
+    let blocks = k / 32u;
+    var acc = 0.0;
+    for (var b = 0u; b < blocks; b++) {{
+        acc += block_dot((col * blocks + b) * {block_bytes}u, row * k + b * 32u);
+    }}
+    dst[i] = acc;
+}}
+"#,
+            meta = Metadata::declaration(3),
+            global_index = wgsl::GLOBAL_INDEX,
+            block_dot = Self::block_dot(dt),
+            block_bytes = dt.size_of(),
+            wg = GPUHandle::WORKGROUP_SIZE,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use half::f16;
+    use std::sync::Arc;
+
+    #[test]
+    fn quantized_blocks_dequantize() {
+        let mut q4 = BlockQ4_0 {
+            d: f16::from_f32(0.5),
+            qs: [0; QK / 2],
+        };
+        for (i, q) in q4.qs.iter_mut().enumerate() {
+            *q = (i as u8) | ((15 - i as u8) << 4);
+        }
+        let q8 = BlockQ8_0 {
+            d: f16::from_f32(-0.25),
+            qs: std::array::from_fn(|i| i as i8 - 16),
+        };
+        assert_eq!(DType::Q4_0.size_of(), 18);
+        assert_eq!(DType::Q8_0.size_of(), 34);
+        assert_eq!(DType::Q8_0.byte_len(64), 68);
+
+        let bytes = bytemuck::bytes_of(&q4);
+        let t = Tensor::<CPU>::from_bytes(DType::Q4_0, vec![1, 32].into(), bytes).unwrap();
+        assert_eq!(t.numel(), 32);
+        assert_eq!(t.storage().numel(), 32);
+        assert_eq!(t.to_bytes().unwrap(), bytes);
+        let expected = (0..16)
+            .map(|i| (i - 8) as f32 * 0.5)
+            .chain((0..16).map(|i| (7 - i) as f32 * 0.5))
+            .collect::<Vec<_>>();
+        assert_eq!(t.dequantize().unwrap().to_vec::<f32>().unwrap(), expected);
+
+        let blocks = [q8, q8];
+        let t = Tensor::<CPU>::from_bytes(
+            DType::Q8_0,
+            vec![2, 32].into(),
+            bytemuck::cast_slice(&blocks),
+        )
+        .unwrap();
+        let dense = t.dequantize().unwrap();
+        assert_eq!(dense.shape(), t.shape());
+        assert_eq!(dense.to_vec::<f32>().unwrap()[33], 15. * 0.25);
+        assert_eq!(t.as_blocks::<BlockQ8_0>().unwrap(), &blocks);
+    }
+
+    #[test]
+    fn quantized_tensors_reject_elementwise_access() {
+        let block = [0u8; 34];
+        assert!(matches!(
+            Tensor::<CPU>::from_bytes(DType::Q8_0, vec![2, 16].into(), &block),
+            Err(TensorError::BlockMismatch(_, 32))
+        ));
+        assert!(matches!(
+            Tensor::<CPU>::from_bytes(DType::Q8_0, vec![64].into(), &block),
+            Err(TensorError::ShapeMismatch(_, 32))
+        ));
+        let t = Tensor::<CPU>::from_bytes(DType::Q8_0, vec![32].into(), &block).unwrap();
+        assert!(matches!(
+            t.exp(),
+            Err(TensorError::UnsupportedDType(DType::Q8_0))
+        ));
+        assert!(matches!(
+            t.as_blocks::<BlockQ4_0>(),
+            Err(TensorError::DTypeMismatch { .. })
+        ));
+        assert_eq!(t.to_string(), format!("[{}]", vec!["0"; 32].join(", ")));
+    }
+
+    #[test]
+    fn quantize_roundtrip_error_is_bounded() {
+        let data = (0..128)
+            .map(|i| ((i * 37 % 101) as f32 - 50.) / 7.)
+            .collect::<Vec<_>>();
+        let x = Tensor::<CPU>::new(vec![2, 64].into(), data.clone()).unwrap();
+        for (dt, bound) in [(DType::Q8_0, 0.5), (DType::Q4_0, 1.)] {
+            let q = x.quantize(dt).unwrap();
+            assert_eq!((q.dt(), q.shape()), (dt, x.shape()));
+            assert_eq!(q.to_bytes().unwrap().len(), dt.byte_len(128));
+            let y = q.dequantize().unwrap().to_vec::<f32>().unwrap();
+            for (block, (x, y)) in data.chunks(QK).zip(y.chunks(QK)).enumerate() {
+                let amax = x.iter().fold(0f32, |m, v| m.max(v.abs()));
+                let d = amax / if dt == DType::Q8_0 { 127. } else { 8. };
+                for (a, b) in x.iter().zip(y) {
+                    assert!(
+                        (a - b).abs() <= bound * d * 1.01,
+                        "block {}: {} vs {}",
+                        block,
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+        let zeros = Tensor::<CPU>::new(vec![32].into(), vec![0f32; 32]).unwrap();
+        assert_eq!(
+            zeros.quantize(DType::Q4_0).unwrap().dequantize().unwrap(),
+            zeros
+        );
+        assert!(matches!(
+            x.quantize(DType::F16),
+            Err(TensorError::UnsupportedDType(DType::F16))
+        ));
+        assert!(matches!(
+            x.reshape(vec![8, 16].into()).unwrap().quantize(DType::Q8_0),
+            Err(TensorError::BlockMismatch(_, 32))
+        ));
+    }
+
+    #[tokio::test]
+    async fn gpu_qmatmul_matches_cpu() {
+        let device = std::sync::Arc::new(WebGPU::new().await.unwrap());
+        let x = (0..2 * 3 * 64)
+            .map(|i| ((i * 13 % 29) as f32 - 14.) / 10.)
+            .collect::<Vec<_>>();
+        let w = (0..5 * 64)
+            .map(|i| ((i * 7 % 23) as f32 - 11.) / 5.)
+            .collect::<Vec<_>>();
+        let x = Tensor::<CPU>::new(vec![2, 3, 64].into(), x).unwrap();
+        let w = Tensor::<CPU>::new(vec![5, 64].into(), w).unwrap();
+        let gpu_x = Tensor::<WebGPU>::from_bytes(
+            device.clone(),
+            DType::F32,
+            x.shape().clone(),
+            &x.to_bytes().unwrap(),
+        )
+        .unwrap();
+        for dt in [DType::Q8_0, DType::Q4_0] {
+            let q = w.quantize(dt).unwrap();
+            let expected = x.qmatmul(&q).unwrap();
+            assert_eq!(expected.shape(), &Shape::from(vec![2, 3, 5]));
+            let gpu_q = Tensor::<WebGPU>::from_bytes(
+                device.clone(),
+                dt,
+                q.shape().clone(),
+                &q.to_bytes().unwrap(),
+            )
+            .unwrap();
+            assert_eq!(gpu_q.storage().layout().size(), dt.byte_len(320));
+            let actual = gpu_x.qmatmul(&gpu_q).unwrap().to(&Arc::new(CPU)).unwrap();
+            for (a, e) in actual
+                .to_vec::<f32>()
+                .unwrap()
+                .iter()
+                .zip(expected.to_vec::<f32>().unwrap())
+            {
+                assert!(
+                    (a - e).abs() <= 1e-4 * e.abs().max(1.),
+                    "{:?}: {} != {}",
+                    dt,
+                    a,
+                    e
+                );
+            }
+        }
+        assert!(matches!(
+            gpu_x.qmatmul(&gpu_x),
+            Err(TensorError::UnsupportedDType(DType::F32))
+        ));
+    }
+}
@@ -0,0 +1,749 @@
+use crate::{
+    as_std, wgsl, DType, Device, GPUHandle, GradOp, Kernel, KernelArg, Metadata, Numeric, Shape,
+    StridedIndex, Tensor, TensorError, WebGPU, Workgroups, CPU,
+};
+use half::{bf16, f16};
+
+///Reductions over one or more dimensions.
+///* `Sum` of integers accumulates in a wider type, a result outside the DType returns [`TensorError::IntegerOverflow`].
+///* `Mean` is only defined for floats.
+///* `Prod` of integers wraps on overflow.
+///* `Max` and `Min` of floats propagate NaN.
+///* `ArgMax` and `ArgMin` return U32 indices into the reduced dimensions, flattened in row-major order.
+///  Ties resolve to the first index, and NaN is considered the extreme value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReduceOp {
+    Sum,
+    Mean,
+    Max,
+    Min,
+    Prod,
+    ArgMax,
+    ArgMin,
+}
+
+impl ReduceOp {
+    pub fn is_arg(self) -> bool {
+        matches!(self, ReduceOp::ArgMax | ReduceOp::ArgMin)
+    }
+
+    ///The DType of the result of reducing a tensor of `dt`.
+    pub fn output_dtype(self, dt: DType) -> DType {
+        if self.is_arg() {
+            DType::U32
+        } else {
+            dt
+        }
+    }
+
+    ///Whether reducing zero elements is an error, as there is no identity.
+    fn requires_elements(self) -> bool {
+        matches!(
+            self,
+            ReduceOp::Max | ReduceOp::Min | ReduceOp::ArgMax | ReduceOp::ArgMin
+        )
+    }
+}
+
+///Reductions for every [`DType`], see [`ReduceOp`].
+pub trait Reduce: Numeric + PartialOrd {
+    const FLOAT: bool;
+    fn one() -> Self;
+    fn is_nan(self) -> bool;
+    ///Sums `values`, returning `None` if an integer sum does not fit in `Self`.
+    fn sum(values: impl Iterator<Item = Self>) -> Option<Self>;
+    ///Mean of the `n` `values`, only called for floats.
+    fn mean(values: impl Iterator<Item = Self>, n: usize) -> Self;
+}
+
+///Integers sum in a 128 bit accumulator, which cannot overflow for any realistic count.
+macro_rules! reduce_int {
+    ($t:ty, $acc:ty) => {
+        impl Reduce for $t {
+            const FLOAT: bool = false;
+            fn one() -> Self {
+                1
+            }
+            fn is_nan(self) -> bool {
+                false
+            }
+            fn sum(values: impl Iterator<Item = Self>) -> Option<Self> {
+                <$t>::try_from(values.map(<$acc>::from).sum::<$acc>()).ok()
+            }
+            fn mean(_: impl Iterator<Item = Self>, _: usize) -> Self {
+                unreachable!("Mean is not defined for {}", stringify!($t))
+            }
+        }
+    };
+}
+
+reduce_int!(u8, u128);
+reduce_int!(u16, u128);
+reduce_int!(u32, u128);
+reduce_int!(u64, u128);
+reduce_int!(i8, i128);
+reduce_int!(i16, i128);
+reduce_int!(i32, i128);
+reduce_int!(i64, i128);
+
+///Floats accumulate in a wider type: f64 for f32, f32 for half precision.
+macro_rules! reduce_float {
+    ($t:ty, $acc:ty, $to:expr, $from:expr) => {
+        impl Reduce for $t {
+            const FLOAT: bool = true;
+            fn one() -> Self {
+                $from(1.)
+            }
+            fn is_nan(self) -> bool {
+                self.is_nan()
+            }
+            fn sum(values: impl Iterator<Item = Self>) -> Option<Self> {
+                Some($from(values.map($to).sum::<$acc>()))
+            }
+            fn mean(values: impl Iterator<Item = Self>, n: usize) -> Self {
+                $from(values.map($to).sum::<$acc>() / n as $acc)
+            }
+        }
+    };
+}
+
+reduce_float!(f32, f64, f64::from, |x: f64| x as f32);
+reduce_float!(f64, f64, |x| x, |x| x);
+reduce_float!(f16, f32, f16::to_f32, f16::from_f32);
+reduce_float!(bf16, f32, bf16::to_f32, bf16::from_f32);
+
+///Index of the extreme value of `values`, see [`ReduceOp`].
+fn arg<T: Reduce>(values: impl Iterator<Item = T>, op: ReduceOp) -> u32 {
+    let mut best: Option<(usize, T)> = None;
+    for (i, v) in values.enumerate() {
+        let better = match best {
+            None => true,
+            Some((_, b)) if b.is_nan() => false,
+            Some((_, b)) => {
+                v.is_nan() || (op == ReduceOp::ArgMax && v > b) || (op == ReduceOp::ArgMin && v < b)
+            }
+        };
+        if better {
+            best = Some((i, v));
+        }
+    }
+    best.map_or(0, |(i, _)| i as u32)
+}
+
+impl<D: Device> Tensor<D> {
+    ///Permutes the reduced `dims` after the kept ones.
+    ///Returns the permuted view, the shape of the result,
+    ///and the number of elements reduced into each element of the result.
+    fn reduction_view(
+        &self,
+        op: ReduceOp,
+        dims: &[usize],
+        keepdim: bool,
+    ) -> Result<(Tensor<D>, Shape, usize), TensorError> {
+        let rank = self.shape().rank();
+        let mut reduced = vec![false; rank];
+        for &d in dims {
+            if d >= rank || std::mem::replace(&mut reduced[d], true) {
+                return Err(TensorError::InvalidDims {
+                    dims: dims.to_vec(),
+                    rank,
+                });
+            }
+        }
+        let order = (0..rank)
+            .filter(|&d| !reduced[d])
+            .chain((0..rank).filter(|&d| reduced[d]))
+            .collect::<Vec<_>>();
+        let inner = dims.iter().map(|&d| self.shape()[d]).product::<usize>();
+        if inner == 0 && op.requires_elements() {
+            return Err(TensorError::EmptyReduction(self.shape().clone()));
+        }
+        let shape = (0..rank)
+            .filter_map(|d| match reduced[d] {
+                true => keepdim.then_some(1),
+                false => Some(self.shape()[d]),
+            })
+            .collect::<Vec<_>>();
+        Ok((self.permute(&order)?, shape.into(), inner))
+    }
+
+    fn all_dims(&self) -> Vec<usize> {
+        (0..self.shape().rank()).collect()
+    }
+}
+
+impl Tensor<CPU> {
+    ///Reduces `dims` with `op`, removing them from the shape unless `keepdim` is set.
+    pub fn reduce(
+        &self,
+        op: ReduceOp,
+        dims: &[usize],
+        keepdim: bool,
+    ) -> Result<Tensor<CPU>, TensorError> {
+        let (view, shape, inner) = self.reduction_view(op, dims, keepdim)?;
+
+        fn reduce_t<T: Reduce>(
+            view: &Tensor<CPU>,
+            op: ReduceOp,
+            shape: Shape,
+            inner: usize,
+        ) -> Result<Tensor<CPU>, TensorError> {
+            if op == ReduceOp::Mean && !T::FLOAT {
+                return Err(TensorError::UnsupportedDType(T::dtype()));
+            }
+            let src = view.storage_slice::<T>()?;
+            let mut values =
+                StridedIndex::new(view.shape(), view.strides()).map(|i| src[view.offset() + i]);
+            let outer = shape.numel();
+            if op.is_arg() {
+                let result = (0..outer)
+                    .map(|_| arg(values.by_ref().take(inner), op))
+                    .collect();
+                return Tensor::new(shape, result);
+            }
+            let result = (0..outer)
+                .map(|_| {
+                    let group = values.by_ref().take(inner);
+                    match op {
+                        ReduceOp::Sum => T::sum(group),
+                        ReduceOp::Mean => Some(T::mean(group, inner)),
+                        ReduceOp::Max => group.reduce(T::maximum),
+                        ReduceOp::Min => group.reduce(T::minimum),
+                        ReduceOp::Prod => Some(group.fold(T::one(), T::mul)),
+                        ReduceOp::ArgMax | ReduceOp::ArgMin => unreachable!(),
+                    }
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or(TensorError::IntegerOverflow(T::dtype()))?;
+            Tensor::new(shape, result)
+        }
+        let dst = as_std!(reduce_t(self.dt())(&view, op, shape, inner))?;
+        Ok(dst.record(GradOp::reduce(op, dims, keepdim), &[self]))
+    }
+}
+
+///Reductions on the GPU support F32, I32 and U32, other DTypes return [`TensorError::UnsupportedDType`].
+///Float sums are accumulated in f32 as a tree, and can differ from the CPU by a relative error of 1e-5.
+impl Tensor<WebGPU> {
+    ///Reduces `dims` with `op`, removing them from the shape unless `keepdim` is set.
+    pub fn reduce(
+        &self,
+        op: ReduceOp,
+        dims: &[usize],
+        keepdim: bool,
+    ) -> Result<Tensor<WebGPU>, TensorError> {
+        let (view, shape, inner) = self.reduction_view(op, dims, keepdim)?;
+        let dt = self.dt();
+        let checked = op == ReduceOp::Sum && dt != DType::F32;
+        let source = ReduceKernel::source(op, dt, checked)?;
+        let outer = shape.numel();
+        let device = self.storage().device();
+        let dst = Tensor::uninit(device.clone(), op.output_dtype(dt), shape)?;
+        if outer > 0 {
+            let handle = self.device().handle();
+            let meta = Metadata::new()
+                .push(outer)
+                .push(inner)
+                .push_layout(&view)
+                .upload(handle)?;
+            let overflow = checked
+                .then(|| {
+                    Tensor::<WebGPU>::from_bytes(
+                        device.clone(),
+                        DType::U32,
+                        vec![1].into(),
+                        &[0; 4],
+                    )
+                })
+                .transpose()?;
+            let mut args = vec![KernelArg::tensor(&view), KernelArg::output(&dst)];
+            if let Some(overflow) = &overflow {
+                args.push(KernelArg::output(overflow));
+            }
+            args.push(KernelArg::uniform(&meta));
+            Kernel::new("reduce", source, dt).dispatch(
+                handle,
+                &args,
+                Workgroups::linear(outer * GPUHandle::WORKGROUP_SIZE as usize),
+            );
+            if let Some(overflow) = overflow {
+                let mut flag = [0u8; 4];
+                device.copy_to_host(overflow.storage().data(), &mut flag)?;
+                if flag != [0; 4] {
+                    return Err(TensorError::IntegerOverflow(dt));
+                }
+            }
+        }
+        Ok(dst.record(GradOp::reduce(op, dims, keepdim), &[self]))
+    }
+}
+
+macro_rules! reduce_methods {
+    ($device:ty) => {
+        impl Tensor<$device> {
+            pub fn sum(
+                &self,
+                dims: &[usize],
+                keepdim: bool,
+            ) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::Sum, dims, keepdim)
+            }
+
+            pub fn mean(
+                &self,
+                dims: &[usize],
+                keepdim: bool,
+            ) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::Mean, dims, keepdim)
+            }
+
+            pub fn max(
+                &self,
+                dims: &[usize],
+                keepdim: bool,
+            ) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::Max, dims, keepdim)
+            }
+
+            pub fn min(
+                &self,
+                dims: &[usize],
+                keepdim: bool,
+            ) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::Min, dims, keepdim)
+            }
+
+            pub fn prod(
+                &self,
+                dims: &[usize],
+                keepdim: bool,
+            ) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::Prod, dims, keepdim)
+            }
+
+            pub fn argmax(
+                &self,
+                dim: usize,
+                keepdim: bool,
+            ) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::ArgMax, &[dim], keepdim)
+            }
+
+            pub fn argmin(
+                &self,
+                dim: usize,
+                keepdim: bool,
+            ) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::ArgMin, &[dim], keepdim)
+            }
+
+            ///Sums every element into a tensor of rank 0.
+            pub fn sum_all(&self) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::Sum, &self.all_dims(), false)
+            }
+
+            pub fn mean_all(&self) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::Mean, &self.all_dims(), false)
+            }
+
+            pub fn max_all(&self) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::Max, &self.all_dims(), false)
+            }
+
+            pub fn min_all(&self) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::Min, &self.all_dims(), false)
+            }
+
+            pub fn prod_all(&self) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::Prod, &self.all_dims(), false)
+            }
+
+            ///Index of the largest element, as if the tensor was flattened.
+            pub fn argmax_all(&self) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::ArgMax, &self.all_dims(), false)
+            }
+
+            ///Index of the smallest element, as if the tensor was flattened.
+            pub fn argmin_all(&self) -> Result<Tensor<$device>, TensorError> {
+                self.reduce(ReduceOp::ArgMin, &self.all_dims(), false)
+            }
+        }
+    };
+}
+
+reduce_methods!(CPU);
+reduce_methods!(WebGPU);
+
+///Workgroup tree reduction, each workgroup produces one element of the output.
+///Invocations first accumulate a strided share of the group, then combine pairwise in workgroup memory.
+///Integer sums accumulate in 64 bits and only the total is checked against the DType.
+///Metadata layout: `[outer, inner, rank, offset, shape.., strides..]`,
+///the layout having the reduced dimensions last.
+struct ReduceKernel;
+
+impl ReduceKernel {
+    ///Identity element of `op`, the initial value of each accumulator.
+    fn identity(op: ReduceOp, dt: DType) -> &'static str {
+        match (op, dt) {
+            (ReduceOp::Sum | ReduceOp::Mean, DType::F32) => "0.0",
+            (ReduceOp::Prod, DType::F32) => "1.0",
+            (ReduceOp::Prod, DType::I32) => "1",
+            (ReduceOp::Prod, _) => "1u",
+            (ReduceOp::Max, DType::F32) => "bitcast<f32>(0xff800000u)",
+            (ReduceOp::Max, DType::I32) => "(-2147483647 - 1)",
+            (ReduceOp::Min, DType::F32) => "bitcast<f32>(0x7f800000u)",
+            (ReduceOp::Min, DType::I32) => "2147483647",
+            (ReduceOp::Min, _) => "0xFFFFFFFFu",
+            _ => "{ty}(0)",
+        }
+    }
+
+    ///WGSL for `fn combine(a, b)` for the non arg ops.
+    fn combine(op: ReduceOp, dt: DType) -> String {
+        let body = match (op, dt) {
+            (ReduceOp::Sum | ReduceOp::Mean, _) => "return a + b;",
+            (ReduceOp::Prod, _) => "return a * b;",
+            (ReduceOp::Max, _) => {
+                r#"if (a != a) {
+        return a;
+    }
+    if (b != b) {
+        return b;
+    }
+    return max(a, b);"#
+            }
+            _ => {
+                r#"if (a != a) {
+        return a;
+    }
+    if (b != b) {
+        return b;
+    }
+    return min(a, b);"#
+            }
+        };
+        format!("fn combine(a: {{ty}}, b: {{ty}}) -> {{ty}} {{\n    {body}\n}}")
+    }
+
+    ///WGSL for the checked integer sum, accumulating 64 bit `vec2<u32>(low, high)` pairs.
+    ///`widen` extends each value, `narrow` flags a total outside the range of `dt`.
+    fn wide_sum(dt: DType) -> String {
+        let (widen, fits, narrow) = if dt == DType::I32 {
+            (
+                "vec2<u32>(bitcast<u32>(v), select(0u, 0xFFFFFFFFu, v < 0))",
+                "a.y == select(0u, 0xFFFFFFFFu, bitcast<i32>(a.x) < 0)",
+                "bitcast<i32>(a.x)",
+            )
+        } else {
+            ("vec2<u32>(v, 0u)", "a.y == 0u", "a.x")
+        };
+        format!(
+            r#"fn widen(v: {{ty}}) -> vec2<u32> {{
+    return {widen};
+}}
+
+fn combine(a: vec2<u32>, b: vec2<u32>) -> vec2<u32> {{
+    let lo = a.x + b.x;
+    return vec2<u32>(lo, a.y + b.y + select(0u, 1u, lo < a.x));
+}}
+
+fn narrow(a: vec2<u32>) -> {{ty}} {{
+    if (!({fits})) {{
+        atomicStore(&overflow[0], 1u);
+    }}
+    return {narrow};
+}}"#
+        )
+    }
+
+    ///WGSL for `fn better(v, i, best, bi)`, whether `v` at index `i` replaces the current `best`.
+    fn better(op: ReduceOp) -> String {
+        let cmp = if op == ReduceOp::ArgMax { ">" } else { "<" };
+        format!(
+            r#"fn better(v: {{ty}}, i: u32, best: {{ty}}, bi: u32) -> bool {{
+    if (i == NONE) {{
+        return false;
+    }}
+    if (bi == NONE) {{
+        return true;
+    }}
+    if (best != best) {{
+        return v != v && i < bi;
+    }}
+    if (v != v) {{
+        return true;
+    }}
+    return v {cmp} best || (v == best && i < bi);
+}}"#
+        )
+    }
+
+    fn source(op: ReduceOp, dt: DType, checked: bool) -> Result<String, TensorError> {
+        let ty = wgsl::scalar_type(dt).ok_or(TensorError::UnsupportedDType(dt))?;
+        if op == ReduceOp::Mean && dt != DType::F32 {
+            return Err(TensorError::UnsupportedDType(dt));
+        }
+        let (helper, accumulate, tree, result) = if op.is_arg() {
+            (
+                Self::better(op),
+                "if (better(v, r, acc, idx)) {\n            acc = v;\n            idx = r;\n        }",
+                "if (better(values[lid + s], indices[lid + s], values[lid], indices[lid])) {\n                values[lid] = values[lid + s];\n                indices[lid] = indices[lid + s];\n            }",
+                "indices[0]",
+            )
+        } else if checked {
+            (
+                Self::wide_sum(dt),
+                "acc = combine(acc, widen(v));",
+                "values[lid] = combine(values[lid], values[lid + s]);",
+                "narrow(values[0])",
+            )
+        } else {
+            (
+                Self::combine(op, dt),
+                "acc = combine(acc, v);",
+                "values[lid] = combine(values[lid], values[lid + s]);",
+                if op == ReduceOp::Mean {
+                    "values[0] / f32(inner)"
+                } else {
+                    "values[0]"
+                },
+            )
+        };
+        let out_ty = wgsl::scalar_type(op.output_dtype(dt)).unwrap();
+        let overflow = if checked {
+            "@group(0) @binding(2) var<storage, read_write> overflow: array<atomic<u32>>;\n"
+        } else {
+            ""
+        };
+        let source = format!(
+            r#"
+@group(0) @binding(0) var<storage, read> src: array<{{ty}}>;
+@group(0) @binding(1) var<storage, read_write> dst: array<{out_ty}>;
+{overflow}{meta}{strided_offset}
+const NONE = 0xFFFFFFFFu;
+var<workgroup> values: array<{acc}, 64>;
+var<workgroup> indices: array<u32, 64>;
+
+{helper}
+
+@compute @workgroup_size({wg})
+fn main(@builtin(workgroup_id) wg: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>, @builtin(local_invocation_index) lid: u32) {{
+    let o = wg.y * groups.x + wg.x;
+    let outer = param(0u);
+    let inner = param(1u);
+    if (o >= outer) {{
+        return;
+    }}
+    var acc = {identity};
+    var idx = NONE;
+    for (var r = lid; r < inner; r += {wg}u) {{
+        let v = src[strided_offset(2u, o * inner + r)];
+        {accumulate}
+    }}
+    values[lid] = acc;
+    indices[lid] = idx;
+    workgroupBarrier();
+    for (var s = {half}u; s > 0u; s >>= 1u) {{
+        if (lid < s) {{
+            {tree}
+        }}
+        workgroupBarrier();
+    }}
+    if (lid == 0u) {{
+        dst[o] = {result};
+    }}
+}}
+"#,
+            meta = Metadata::declaration(if checked { 3 } else { 2 }),
+            strided_offset = wgsl::STRIDED_OFFSET,
+            acc = if checked { "vec2<u32>" } else { "{ty}" },
+            identity = if checked {
+                "vec2<u32>(0u, 0u)"
+            } else {
+                Self::identity(op, dt)
+            },
+            wg = GPUHandle::WORKGROUP_SIZE,
+            half = GPUHandle::WORKGROUP_SIZE / 2,
+        );
+        Ok(source.replace("{ty}", ty))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+
+    fn arange(shape: Vec<usize>) -> Tensor<CPU> {
+        let n = shape.iter().product::<usize>();
+        Tensor::<CPU>::new(shape.into(), (0..n).map(|i| i as f32).collect()).unwrap()
+    }
+
+    #[test]
+    fn cpu_reductions() {
+        let t = arange(vec![2, 3, 4]);
+        let s = t.sum(&[0, 2], true).unwrap();
+        assert_eq!(s.shape(), &Shape::from(vec![1, 3, 1]));
+        assert_eq!(s.to_vec::<f32>().unwrap(), vec![60., 92., 124.]);
+        assert_eq!(
+            t.mean(&[1], false).unwrap().to_vec::<f32>().unwrap()[..2],
+            [4., 5.]
+        );
+        assert_eq!(t.max_all().unwrap().item::<f32>().unwrap(), 23.);
+        assert_eq!(
+            t.argmin(2, false).unwrap().shape(),
+            &Shape::from(vec![2, 3])
+        );
+
+        let x =
+            Tensor::<CPU>::new(vec![2, 3].into(), vec![1f32, 5., 5., 2., f32::NAN, -1.]).unwrap();
+        assert_eq!(
+            x.argmax(1, false).unwrap().to_vec::<u32>().unwrap(),
+            vec![1, 1]
+        );
+        assert_eq!(x.argmin_all().unwrap().item::<u32>().unwrap(), 4);
+        assert!(x.max(&[1], false).unwrap().to_vec::<f32>().unwrap()[1].is_nan());
+        assert_eq!(
+            x.prod(&[0], false).unwrap().to_vec::<f32>().unwrap()[..1],
+            [2.]
+        );
+        assert!(matches!(
+            t.sum(&[1, 1], false),
+            Err(TensorError::InvalidDims { .. })
+        ));
+        let empty = arange(vec![2, 0]);
+        assert_eq!(
+            empty.sum(&[1], false).unwrap().to_vec::<f32>().unwrap(),
+            [0., 0.]
+        );
+        assert!(matches!(
+            empty.max(&[1], false),
+            Err(TensorError::EmptyReduction(_))
+        ));
+    }
+
+    #[test]
+    fn cpu_integer_sum_is_checked() {
+        let t = Tensor::<CPU>::new(vec![3].into(), vec![100i8, 20, 7]).unwrap();
+        assert_eq!(t.sum_all().unwrap().item::<i8>().unwrap(), 127);
+        let t = Tensor::<CPU>::new(vec![3].into(), vec![100i8, 20, 8]).unwrap();
+        assert!(matches!(
+            t.sum_all(),
+            Err(TensorError::IntegerOverflow(DType::I8))
+        ));
+        assert!(matches!(
+            t.mean_all(),
+            Err(TensorError::UnsupportedDType(DType::I8))
+        ));
+        assert_eq!(
+            t.prod_all().unwrap().item::<i8>().unwrap(),
+            100i8.wrapping_mul(20).wrapping_mul(8)
+        );
+        //Only the total has to fit, not the partial sums.
+        let t = Tensor::<CPU>::new(vec![3].into(), vec![100i8, 100, -100]).unwrap();
+        assert_eq!(t.sum_all().unwrap().item::<i8>().unwrap(), 100);
+        let t = Tensor::<CPU>::new(vec![2].into(), vec![u64::MAX, 1]).unwrap();
+        assert!(matches!(
+            t.sum_all(),
+            Err(TensorError::IntegerOverflow(DType::U64))
+        ));
+    }
+
+    #[tokio::test]
+    async fn gpu_reductions_match_cpu() {
+        let device = std::sync::Arc::new(WebGPU::new().await.unwrap());
+        let data = (0..3 * 70 * 5)
+            .map(|i| ((i * 37) % 101) as f32 - 50.)
+            .collect::<Vec<_>>();
+        let cpu = Tensor::<CPU>::new(vec![3, 70, 5].into(), data.clone()).unwrap();
+        let gpu = Tensor::<WebGPU>::from_bytes(
+            device.clone(),
+            DType::F32,
+            cpu.shape().clone(),
+            bytemuck::cast_slice(&data),
+        )
+        .unwrap();
+        let ops = [
+            ReduceOp::Sum,
+            ReduceOp::Mean,
+            ReduceOp::Max,
+            ReduceOp::Min,
+            ReduceOp::ArgMax,
+            ReduceOp::ArgMin,
+        ];
+        let (cpu_t, gpu_t) = (cpu.transpose(1, 2).unwrap(), gpu.transpose(1, 2).unwrap());
+        for op in ops {
+            for (dims, keepdim) in [(vec![2], false), (vec![0, 2], true), (vec![0, 1, 2], false)] {
+                let expected = cpu_t.reduce(op, &dims, keepdim).unwrap();
+                let result = gpu_t
+                    .reduce(op, &dims, keepdim)
+                    .unwrap()
+                    .to(&Arc::new(CPU))
+                    .unwrap();
+                assert_eq!(result.shape(), expected.shape());
+                if op.is_arg() {
+                    assert_eq!(result, expected, "{:?} over {:?}", op, dims);
+                    continue;
+                }
+                let (r, e) = (
+                    result.to_vec::<f32>().unwrap(),
+                    expected.to_vec::<f32>().unwrap(),
+                );
+                for (r, e) in r.iter().zip(&e) {
+                    assert!(
+                        (r - e).abs() <= 1e-5 * e.abs().max(1.),
+                        "{:?}: {} != {}",
+                        op,
+                        r,
+                        e
+                    );
+                }
+            }
+        }
+
+        let ints = Tensor::<WebGPU>::from_bytes(
+            device.clone(),
+            DType::I32,
+            vec![3].into(),
+            bytemuck::cast_slice(&[i32::MAX - 10, 10, -5]),
+        )
+        .unwrap();
+        assert_eq!(
+            ints.sum_all()
+                .unwrap()
+                .to(&Arc::new(CPU))
+                .unwrap()
+                .item::<i32>()
+                .unwrap(),
+            i32::MAX - 5
+        );
+        //The tree first adds `i32::MAX + 10`, which only overflows as a partial sum.
+        let ints = Tensor::<WebGPU>::from_bytes(
+            device.clone(),
+            DType::I32,
+            vec![4].into(),
+            bytemuck::cast_slice(&[i32::MAX, -5, 10, -5]),
+        )
+        .unwrap();
+        assert_eq!(
+            ints.sum_all()
+                .unwrap()
+                .to(&Arc::new(CPU))
+                .unwrap()
+                .item::<i32>()
+                .unwrap(),
+            i32::MAX
+        );
+        let ints = Tensor::<WebGPU>::from_bytes(
+            device.clone(),
+            DType::I32,
+            vec![2].into(),
+            bytemuck::cast_slice(&[i32::MAX, 1]),
+        )
+        .unwrap();
+        assert!(matches!(
+            ints.sum_all(),
+            Err(TensorError::IntegerOverflow(DType::I32))
+        ));
+    }
+}
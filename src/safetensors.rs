@@ -0,0 +1,466 @@
+use crate::{DType, Shape, Tensor, TensorError, CPU};
+use memmap2::Mmap;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+///Headers larger than this are rejected before being read, as in the reference implementation.
+const MAX_HEADER_SIZE: u64 = 100_000_000;
+
+///Key of the optional string-to-string metadata map of the header.
+const METADATA_KEY: &str = "__metadata__";
+
+#[derive(thiserror::Error, Debug)]
+pub enum SafetensorsError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("File of {0} bytes is too small to hold a header")]
+    TooSmall(usize),
+    #[error("Header of {size} bytes does not fit in a file of {file} bytes")]
+    HeaderTooLarge { size: u64, file: usize },
+    #[error("Header is not valid JSON: {0}")]
+    InvalidHeader(#[from] serde_json::Error),
+    #[error("Header entry {name:?} is invalid: {reason}")]
+    InvalidEntry { name: String, reason: &'static str },
+    #[error("Tensor {name:?} has unsupported dtype {dtype:?}")]
+    UnsupportedDType { name: String, dtype: String },
+    #[error("Tensor {name:?} of shape {shape:?} and dtype {dt:?} does not fit in {len} bytes")]
+    ShapeMismatch {
+        name: String,
+        shape: Shape,
+        dt: DType,
+        len: usize,
+    },
+    #[error("Tensor {name:?} has offsets [{start}, {end}), expected it to start at {expected}")]
+    InvalidOffsets {
+        name: String,
+        start: usize,
+        end: usize,
+        expected: usize,
+    },
+    #[error("Data section of {len} bytes has {unused} trailing bytes not covered by any tensor")]
+    TrailingBytes { len: usize, unused: usize },
+    #[error("Tensor error: {0}")]
+    Tensor(#[from] TensorError),
+}
+
+impl DType {
+    ///The dtype string used by safetensors headers, which have no block-quantized types.
+    pub fn safetensors_name(&self) -> Option<&'static str> {
+        Some(match self {
+            DType::U8 => "U8",
+            DType::U16 => "U16",
+            DType::U32 => "U32",
+            DType::U64 => "U64",
+            DType::I8 => "I8",
+            DType::I16 => "I16",
+            DType::I32 => "I32",
+            DType::I64 => "I64",
+            DType::F16 => "F16",
+            DType::BF16 => "BF16",
+            DType::F32 => "F32",
+            DType::F64 => "F64",
+            DType::Q4_0 | DType::Q8_0 => return None,
+        })
+    }
+
+    ///Inverse of [`DType::safetensors_name`]. `BOOL` and the FP8 types have no equivalent.
+    pub fn from_safetensors_name(name: &str) -> Option<DType> {
+        Some(match name {
+            "U8" => DType::U8,
+            "U16" => DType::U16,
+            "U32" => DType::U32,
+            "U64" => DType::U64,
+            "I8" => DType::I8,
+            "I16" => DType::I16,
+            "I32" => DType::I32,
+            "I64" => DType::I64,
+            "F16" => DType::F16,
+            "BF16" => DType::BF16,
+            "F32" => DType::F32,
+            "F64" => DType::F64,
+            _ => return None,
+        })
+    }
+}
+
+///A tensor described by the header, with offsets relative to the start of the data section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafetensorsEntry {
+    pub name: String,
+    pub dt: DType,
+    pub shape: Shape,
+    pub start: usize,
+    pub end: usize,
+}
+
+///Parses the header of a safetensors file, returning its entries sorted by offset
+///and the offset at which the data section starts.
+///
+///The entries are validated to tile the data section exactly, without gaps or overlaps,
+///and each to hold exactly `shape.numel()` elements of its dtype.
+pub fn read_safetensors_header(
+    bytes: &[u8],
+) -> Result<(Vec<SafetensorsEntry>, usize), SafetensorsError> {
+    let size = bytes
+        .get(..8)
+        .ok_or(SafetensorsError::TooSmall(bytes.len()))?;
+    let size = u64::from_le_bytes(size.try_into().unwrap());
+    let data_start = 8u64.saturating_add(size);
+    if size > MAX_HEADER_SIZE || data_start > bytes.len() as u64 {
+        return Err(SafetensorsError::HeaderTooLarge {
+            size,
+            file: bytes.len(),
+        });
+    }
+    let data_start = data_start as usize;
+    let header: Map<String, Value> = serde_json::from_slice(&bytes[8..data_start])?;
+
+    let mut entries = header
+        .into_iter()
+        .filter(|(name, _)| name != METADATA_KEY)
+        .map(|(name, value)| parse_entry(name, value))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|e| (e.start, e.end));
+
+    let len = bytes.len() - data_start;
+    let mut expected = 0;
+    for entry in &entries {
+        if entry.start != expected || entry.end > len {
+            return Err(SafetensorsError::InvalidOffsets {
+                name: entry.name.clone(),
+                start: entry.start,
+                end: entry.end,
+                expected,
+            });
+        }
+        expected = entry.end;
+    }
+    if expected != len {
+        return Err(SafetensorsError::TrailingBytes {
+            len,
+            unused: len - expected,
+        });
+    }
+    Ok((entries, data_start))
+}
+
+fn parse_entry(name: String, value: Value) -> Result<SafetensorsEntry, SafetensorsError> {
+    let invalid = |reason| SafetensorsError::InvalidEntry {
+        name: name.clone(),
+        reason,
+    };
+    let as_usize = |v: &Value| v.as_u64().and_then(|v| usize::try_from(v).ok());
+
+    let entry = value.as_object().ok_or_else(|| invalid("not an object"))?;
+    let dtype = entry
+        .get("dtype")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("missing string field `dtype`"))?;
+    let dt =
+        DType::from_safetensors_name(dtype).ok_or_else(|| SafetensorsError::UnsupportedDType {
+            name: name.clone(),
+            dtype: dtype.to_string(),
+        })?;
+    let shape = entry
+        .get("shape")
+        .and_then(Value::as_array)
+        .and_then(|dims| dims.iter().map(as_usize).collect::<Option<Vec<_>>>())
+        .ok_or_else(|| invalid("missing integer array field `shape`"))?;
+    let offsets = entry
+        .get("data_offsets")
+        .and_then(Value::as_array)
+        .and_then(|o| o.iter().map(as_usize).collect::<Option<Vec<_>>>())
+        .filter(|o| o.len() == 2)
+        .ok_or_else(|| invalid("missing field `data_offsets` of two integers"))?;
+    let (start, end) = (offsets[0], offsets[1]);
+    if end < start {
+        return Err(invalid("data offsets are decreasing"));
+    }
+
+    let len = shape
+        .iter()
+        .try_fold(dt.size_of(), |acc, &d| acc.checked_mul(d));
+    let shape = Shape::from(shape);
+    if len != Some(end - start) {
+        return Err(SafetensorsError::ShapeMismatch {
+            name,
+            shape,
+            dt,
+            len: end - start,
+        });
+    }
+    Ok(SafetensorsEntry {
+        name,
+        dt,
+        shape,
+        start,
+        end,
+    })
+}
+
+///Deserializes every tensor of an in-memory safetensors file.
+pub fn read_safetensors(bytes: &[u8]) -> Result<HashMap<String, Tensor<CPU>>, SafetensorsError> {
+    let (entries, data_start) = read_safetensors_header(bytes)?;
+    let data = &bytes[data_start..];
+    entries
+        .into_iter()
+        .map(|e| {
+            let tensor = Tensor::<CPU>::from_bytes(e.dt, e.shape, &data[e.start..e.end])?;
+            Ok((e.name, tensor))
+        })
+        .collect()
+}
+
+///Serializes tensors in the safetensors format.
+///As in the reference implementation, tensors are ordered by decreasing element size, then by name,
+///so that each is aligned to its dtype and can be memory-mapped in place.
+///Strided views are written in row-major order.
+pub fn write_safetensors(
+    tensors: &HashMap<String, Tensor<CPU>>,
+) -> Result<Vec<u8>, SafetensorsError> {
+    let mut names = tensors.keys().collect::<Vec<_>>();
+    names.sort_by_key(|name| (std::cmp::Reverse(tensors[*name].dt().size_of()), *name));
+
+    let mut header = Map::new();
+    let mut data = vec![];
+    for name in names {
+        let tensor = &tensors[name];
+        let start = data.len();
+        data.extend(tensor.to_bytes()?);
+        let mut entry = Map::new();
+        let dtype = tensor
+            .dt()
+            .safetensors_name()
+            .ok_or(TensorError::UnsupportedDType(tensor.dt()))?;
+        entry.insert("dtype".into(), dtype.into());
+        entry.insert("shape".into(), tensor.shape().as_slice().into());
+        entry.insert("data_offsets".into(), vec![start, data.len()].into());
+        header.insert(name.clone(), entry.into());
+    }
+
+    let mut header = serde_json::to_vec(&header)?;
+    //The data section is aligned to 8 bytes by padding the header with spaces.
+    header.resize(header.len().next_multiple_of(8), b' ');
+    let mut bytes = Vec::with_capacity(8 + header.len() + data.len());
+    bytes.extend((header.len() as u64).to_le_bytes());
+    bytes.extend(header);
+    bytes.extend(data);
+    Ok(bytes)
+}
+
+///Loads every tensor of a `.safetensors` file onto the CPU.
+pub fn load_safetensors(
+    path: impl AsRef<Path>,
+) -> Result<HashMap<String, Tensor<CPU>>, SafetensorsError> {
+    read_safetensors(&std::fs::read(path)?)
+}
+
+///Loads every tensor of a `.safetensors` file onto the CPU without copying, by memory-mapping it.
+///The tensors are read-only views of the file, which stays mapped until all of them are dropped.
+///Tensors whose offset is not aligned to their dtype are copied instead.
+///
+///# Safety
+///The file must not be modified while it is mapped, see [`memmap2::Mmap::map`].
+pub unsafe fn load_safetensors_mmap(
+    path: impl AsRef<Path>,
+) -> Result<HashMap<String, Tensor<CPU>>, SafetensorsError> {
+    let file = std::fs::File::open(path)?;
+    let mmap = Arc::new(unsafe { Mmap::map(&file)? });
+    let (entries, data_start) = read_safetensors_header(&mmap)?;
+    entries
+        .into_iter()
+        .map(|e| {
+            let range = data_start + e.start..data_start + e.end;
+            let aligned = mmap[range.clone()].as_ptr().align_offset(e.dt.alignment()) == 0;
+            let tensor = if aligned {
+                Tensor::<CPU>::from_mmap(mmap.clone(), range, e.dt, e.shape)?
+            } else {
+                Tensor::<CPU>::from_bytes(e.dt, e.shape, &mmap[range])?
+            };
+            Ok((e.name, tensor))
+        })
+        .collect()
+}
+
+///Saves tensors to a `.safetensors` file, see [`write_safetensors`].
+pub fn save_safetensors(
+    tensors: &HashMap<String, Tensor<CPU>>,
+    path: impl AsRef<Path>,
+) -> Result<(), SafetensorsError> {
+    Ok(std::fs::write(path, write_safetensors(tensors)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use half::{bf16, f16};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn safetensors_roundtrip() {
+        let matrix = Tensor::<CPU>::new(vec![2, 3].into(), vec![1f32, 2., 3., 4., 5., 6.]).unwrap();
+        let tensors = HashMap::from([
+            ("matrix".to_string(), matrix.clone()),
+            ("transposed".to_string(), matrix.permute(&[1, 0]).unwrap()),
+            (
+                "half".to_string(),
+                Tensor::<CPU>::new(vec![3].into(), [0.5, -2., 3.25].map(f16::from_f32).to_vec())
+                    .unwrap(),
+            ),
+            (
+                "brain".to_string(),
+                Tensor::<CPU>::new(
+                    vec![1, 2].into(),
+                    [1e10, -0.125].map(bf16::from_f32).to_vec(),
+                )
+                .unwrap(),
+            ),
+            (
+                "bytes".to_string(),
+                Tensor::<CPU>::new(vec![3].into(), vec![1u8, 2, 3]).unwrap(),
+            ),
+            (
+                "empty".to_string(),
+                Tensor::<CPU>::new(vec![0, 4].into(), Vec::<i64>::new()).unwrap(),
+            ),
+        ]);
+        let path = std::env::temp_dir().join(format!("{}.safetensors", nanoid::nanoid!()));
+        save_safetensors(&tensors, &path).unwrap();
+        let loaded = load_safetensors(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), tensors.len());
+        for (name, tensor) in &tensors {
+            assert_eq!(&loaded[name], tensor, "{}", name);
+            assert!(loaded[name].is_contiguous());
+        }
+        assert_eq!(loaded["half"].dt(), DType::F16);
+        assert_eq!(loaded["brain"].dt(), DType::BF16);
+        assert_eq!(
+            loaded["transposed"].to_vec::<f32>().unwrap(),
+            vec![1., 4., 2., 5., 3., 6.]
+        );
+    }
+
+    #[test]
+    fn malformed_safetensors_are_rejected() {
+        fn file(header: &str, data: &[u8]) -> Vec<u8> {
+            let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+            bytes.extend(header.as_bytes());
+            bytes.extend(data);
+            bytes
+        }
+        let read = |bytes: &[u8]| read_safetensors(bytes).unwrap_err();
+
+        assert!(matches!(read(&[1, 2]), SafetensorsError::TooSmall(2)));
+        assert!(matches!(
+            read(&u64::MAX.to_le_bytes()),
+            SafetensorsError::HeaderTooLarge { .. }
+        ));
+        assert!(matches!(
+            read(&file("{\"a\":", &[])),
+            SafetensorsError::InvalidHeader(_)
+        ));
+        assert!(matches!(
+            read(&file(r#"{"a":{"dtype":"F32","shape":[1]}}"#, &[0; 4])),
+            SafetensorsError::InvalidEntry { .. }
+        ));
+        assert!(matches!(
+            read(&file(r#"{"a":{"dtype":"BOOL","shape":[1],"data_offsets":[0,1]}}"#, &[0])),
+            SafetensorsError::UnsupportedDType { dtype, .. } if dtype == "BOOL"
+        ));
+        assert!(matches!(
+            read(&file(
+                r#"{"a":{"dtype":"F32","shape":[2],"data_offsets":[0,4]}}"#,
+                &[0; 4]
+            )),
+            SafetensorsError::ShapeMismatch { .. }
+        ));
+        assert!(matches!(
+            read(&file(
+                r#"{"a":{"dtype":"U8","shape":[2],"data_offsets":[2,4]}}"#,
+                &[0; 4]
+            )),
+            SafetensorsError::InvalidOffsets { expected: 0, .. }
+        ));
+        assert!(matches!(
+            read(&file(
+                r#"{"a":{"dtype":"U8","shape":[2],"data_offsets":[0,2]}}"#,
+                &[0; 3]
+            )),
+            SafetensorsError::TrailingBytes { unused: 1, .. }
+        ));
+
+        let valid = file(
+            r#"{"__metadata__":{"format":"pt"},"b":{"dtype":"I16","shape":[],"data_offsets":[2,4]},"a":{"dtype":"U8","shape":[2],"data_offsets":[0,2]}}"#,
+            &[7, 8, 1, 0],
+        );
+        let tensors = read_safetensors(&valid).unwrap();
+        assert_eq!(tensors["a"].to_vec::<u8>().unwrap(), vec![7, 8]);
+        assert_eq!(tensors["b"].item::<i16>().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn mmap_tensors_are_read_only_views() {
+        let data = (0..12).map(|i| i as f32 * 0.5).collect::<Vec<_>>();
+        let weight = Tensor::<CPU>::new(vec![3, 4].into(), data.clone()).unwrap();
+        let tensors = HashMap::from([
+            ("weight".to_string(), weight.clone()),
+            (
+                "bias".to_string(),
+                Tensor::<CPU>::new(vec![2].into(), vec![1u8, 2]).unwrap(),
+            ),
+        ]);
+        let path = std::env::temp_dir().join(format!("{}.safetensors", nanoid::nanoid!()));
+        save_safetensors(&tensors, &path).unwrap();
+        let mut loaded = unsafe { load_safetensors_mmap(&path).unwrap() };
+        std::fs::remove_file(&path).unwrap();
+
+        let mut mapped = loaded.remove("weight").unwrap();
+        assert!(mapped.storage().is_mapped());
+        assert_eq!(mapped, weight);
+        assert_eq!(loaded["bias"].to_vec::<u8>().unwrap(), vec![1, 2]);
+        assert!(matches!(
+            mapped.as_slice_mut::<f32>(),
+            Err(TensorError::StorageError(StorageError::ReadOnly))
+        ));
+        //Operations read the mapping and produce owned tensors.
+        let doubled = mapped.add(&mapped).unwrap();
+        assert!(!doubled.storage().is_mapped());
+        assert_eq!(doubled.to_vec::<f32>().unwrap()[3], 3.);
+
+        let device = Arc::new(WebGPU::new().await.unwrap());
+        let uploaded = mapped.to(&device).unwrap();
+        assert_eq!(uploaded.to(&Arc::new(CPU)).unwrap(), weight);
+    }
+
+    #[test]
+    fn mmap_regions_are_validated() {
+        let path = std::env::temp_dir().join(nanoid::nanoid!());
+        std::fs::write(&path, [0u8; 13]).unwrap();
+        let mmap =
+            Arc::new(unsafe { memmap2::Mmap::map(&std::fs::File::open(&path).unwrap()) }.unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let t = Tensor::<CPU>::from_mmap(mmap.clone(), 4..12, DType::U32, vec![2].into()).unwrap();
+        assert_eq!(t.to_vec::<u32>().unwrap(), vec![0, 0]);
+        assert!(matches!(
+            Storage::from_mmap(mmap.clone(), 8..16, DType::U8),
+            Err(StorageError::OutOfBounds { len: 13, .. })
+        ));
+        assert!(matches!(
+            Storage::from_mmap(mmap.clone(), 1..5, DType::F32),
+            Err(StorageError::Misaligned { .. })
+        ));
+        assert!(matches!(
+            Tensor::<CPU>::from_mmap(mmap.clone(), 0..8, DType::U16, vec![3].into()),
+            Err(TensorError::ShapeMismatch(_, 4))
+        ));
+        //The mapping outlives the handle it was created from.
+        drop(mmap);
+        assert_eq!(t.sum_all().unwrap().item::<u32>().unwrap(), 0);
+    }
+}
@@ -11,13 +11,47 @@ impl Shape {
         Self(d.into())
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &usize> {
+    pub fn iter(&self) -> std::slice::Iter<'_, usize> {
         self.0.iter()
     }
 
     pub fn numel(&self) -> usize {
         self.0.iter().product()
     }
+
+    pub fn rank(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn as_slice(&self) -> &[usize] {
+        &self.0
+    }
+
+    ///Computes the shape both operands broadcast to, following NumPy rules.
+    ///Dimensions are aligned from the right, and must be equal or 1.
+    pub fn broadcast(&self, other: &Shape) -> Option<Shape> {
+        let rank = self.rank().max(other.rank());
+        let mut shape: SmallVec<[usize; 4]> = smallvec::smallvec![0; rank];
+        for i in 0..rank {
+            let a = self.rank().checked_sub(i + 1).map_or(1, |d| self[d]);
+            let b = other.rank().checked_sub(i + 1).map_or(1, |d| other[d]);
+            shape[rank - i - 1] = match (a, b) {
+                (a, b) if a == b => a,
+                (1, b) => b,
+                (a, 1) => a,
+                _ => return None,
+            };
+        }
+        Some(Shape(shape))
+    }
+}
+
+impl std::ops::Index<usize> for Shape {
+    type Output = usize;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.0[index]
+    }
 }
 
 impl From<Vec<usize>> for Shape {
@@ -26,17 +60,129 @@ impl From<Vec<usize>> for Shape {
     }
 }
 
-#[derive(Debug, Clone)]
+///Strides describe how many elements to step over in storage to move one index along each dimension.
+///A stride of 0 repeats the same element, which is how broadcasting is represented.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Strides(SmallVec<[usize; 4]>);
 
+impl Strides {
+    pub fn new<D>(d: D) -> Self
+    where
+        D: Into<SmallVec<[usize; 4]>>,
+    {
+        Self(d.into())
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, usize> {
+        self.0.iter()
+    }
+
+    pub fn rank(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn as_slice(&self) -> &[usize] {
+        &self.0
+    }
+
+    ///Strides laying out `shape` densely in column-major (Fortran) order,
+    ///where the first dimension varies fastest.
+    pub fn column_major(shape: &Shape) -> Self {
+        let mut strides: SmallVec<[usize; 4]> = smallvec::smallvec![0; shape.rank()];
+        let mut stride = 1;
+        for (i, dim) in shape.iter().enumerate() {
+            strides[i] = stride;
+            stride *= dim;
+        }
+        Self(strides)
+    }
+
+    ///Whether these strides lay out `shape` densely in row-major order.
+    ///Dimensions of size 1 can have any stride.
+    pub fn is_contiguous(&self, shape: &Shape) -> bool {
+        let mut expected = 1;
+        for (&dim, &stride) in shape.iter().zip(self.iter()).rev() {
+            if dim != 1 && stride != expected {
+                return false;
+            }
+            expected *= dim;
+        }
+        true
+    }
+}
+
+impl std::ops::Index<usize> for Strides {
+    type Output = usize;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
 impl From<Shape> for Strides {
     fn from(shape: Shape) -> Self {
-        let mut strides = SmallVec::with_capacity(shape.0.len());
+        Self::from(&shape)
+    }
+}
+
+impl From<&Shape> for Strides {
+    fn from(shape: &Shape) -> Self {
+        let mut strides: SmallVec<[usize; 4]> = smallvec::smallvec![0; shape.rank()];
         let mut stride = 1;
-        for dim in shape.0.iter().rev() {
-            strides.push(stride);
+        for (i, dim) in shape.iter().enumerate().rev() {
+            strides[i] = stride;
             stride *= dim;
         }
         Self(strides)
     }
 }
+
+///Iterates over the storage offset (in elements) of every element of a strided view,
+///in row-major order.
+pub(crate) struct StridedIndex<'a> {
+    shape: &'a [usize],
+    strides: &'a [usize],
+    index: SmallVec<[usize; 4]>,
+    offset: usize,
+    remaining: usize,
+}
+
+impl<'a> StridedIndex<'a> {
+    pub(crate) fn new(shape: &'a Shape, strides: &'a Strides) -> Self {
+        Self {
+            shape: shape.as_slice(),
+            strides: strides.as_slice(),
+            index: smallvec::smallvec![0; shape.rank()],
+            offset: 0,
+            remaining: shape.numel(),
+        }
+    }
+}
+
+impl Iterator for StridedIndex<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let current = self.offset;
+        for d in (0..self.shape.len()).rev() {
+            self.index[d] += 1;
+            self.offset += self.strides[d];
+            if self.index[d] < self.shape[d] {
+                break;
+            }
+            self.offset -= self.strides[d] * self.index[d];
+            self.index[d] = 0;
+        }
+        Some(current)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for StridedIndex<'_> {}
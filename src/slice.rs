@@ -0,0 +1,338 @@
+use crate::{
+    as_std, wgsl, DType, Device, GPUHandle, GradOp, Kernel, KernelArg, Metadata, Shape,
+    StridedIndex, Strides, TData, Tensor, TensorError, WebGPU, Workgroups, CPU,
+};
+use std::ops::{Range, RangeFrom, RangeFull, RangeInclusive, RangeTo, RangeToInclusive};
+
+///Indexes a single dimension of a tensor, see [`s!`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceIndex {
+    ///Selects a single index, removing the dimension.
+    Select(usize),
+    ///Keeps the half open range `start..end`, `None` meaning the end of the dimension.
+    Range { start: usize, end: Option<usize> },
+}
+
+impl From<usize> for SliceIndex {
+    fn from(index: usize) -> Self {
+        SliceIndex::Select(index)
+    }
+}
+
+impl From<Range<usize>> for SliceIndex {
+    fn from(r: Range<usize>) -> Self {
+        SliceIndex::Range {
+            start: r.start,
+            end: Some(r.end),
+        }
+    }
+}
+
+impl From<RangeInclusive<usize>> for SliceIndex {
+    fn from(r: RangeInclusive<usize>) -> Self {
+        SliceIndex::Range {
+            start: *r.start(),
+            end: Some(r.end().saturating_add(1)),
+        }
+    }
+}
+
+impl From<RangeFrom<usize>> for SliceIndex {
+    fn from(r: RangeFrom<usize>) -> Self {
+        SliceIndex::Range {
+            start: r.start,
+            end: None,
+        }
+    }
+}
+
+impl From<RangeTo<usize>> for SliceIndex {
+    fn from(r: RangeTo<usize>) -> Self {
+        SliceIndex::Range {
+            start: 0,
+            end: Some(r.end),
+        }
+    }
+}
+
+impl From<RangeToInclusive<usize>> for SliceIndex {
+    fn from(r: RangeToInclusive<usize>) -> Self {
+        SliceIndex::Range {
+            start: 0,
+            end: Some(r.end.saturating_add(1)),
+        }
+    }
+}
+
+impl From<RangeFull> for SliceIndex {
+    fn from(_: RangeFull) -> Self {
+        SliceIndex::Range {
+            start: 0,
+            end: None,
+        }
+    }
+}
+
+///Builds the argument to [`Tensor::slice`], NumPy style.
+///```
+///# use wgpu_tensor::*;
+///let t = Tensor::<CPU>::new(vec![2, 4, 3].into(), (0..24u32).collect()).unwrap();
+///let v = t.slice(&s![.., 1..3, 0]).unwrap();
+///assert_eq!(v.shape(), &Shape::from(vec![2, 2]));
+///```
+#[macro_export]
+macro_rules! s {
+    ($($idx:expr),* $(,)?) => {
+        [$($crate::SliceIndex::from($idx)),*]
+    };
+}
+
+///Slicing produces views sharing storage with `self`, only the shape, strides and offset change.
+impl<D: Device> Tensor<D> {
+    ///Indexes the leading dimensions of the tensor, trailing dimensions are kept whole.
+    pub fn slice(&self, indices: &[SliceIndex]) -> Result<Tensor<D>, TensorError> {
+        let rank = self.shape().rank();
+        if indices.len() > rank {
+            return Err(TensorError::InvalidDims {
+                dims: (0..indices.len()).collect(),
+                rank,
+            });
+        }
+        let mut shape = vec![];
+        let mut strides = vec![];
+        let mut offset = self.offset();
+        for d in 0..rank {
+            let (dim, stride) = (self.shape()[d], self.strides()[d]);
+            match indices.get(d) {
+                Some(&SliceIndex::Select(i)) => {
+                    if i >= dim {
+                        return Err(TensorError::IndexOutOfBounds {
+                            index: i,
+                            dim: d,
+                            size: dim,
+                        });
+                    }
+                    offset += i * stride;
+                }
+                Some(&SliceIndex::Range { start, end }) => {
+                    let end = end.unwrap_or(dim);
+                    if start > end || end > dim {
+                        return Err(TensorError::IndexOutOfBounds {
+                            index: end.max(start),
+                            dim: d,
+                            size: dim,
+                        });
+                    }
+                    offset += start * stride;
+                    shape.push(end - start);
+                    strides.push(stride);
+                }
+                None => {
+                    shape.push(dim);
+                    strides.push(stride);
+                }
+            }
+        }
+        let view = self.view_at(shape.into(), Strides::new(strides), offset);
+        Ok(view.record(GradOp::Slice(indices.to_vec()), &[self]))
+    }
+
+    ///Keeps `len` elements of `dim`, starting at `start`.
+    pub fn narrow(&self, dim: usize, start: usize, len: usize) -> Result<Tensor<D>, TensorError> {
+        let end = start
+            .checked_add(len)
+            .ok_or_else(|| TensorError::IndexOutOfBounds {
+                index: start,
+                dim,
+                size: self.shape().as_slice().get(dim).copied().unwrap_or(0),
+            })?;
+        self.slice(&self.along(dim, (start..end).into())?)
+    }
+
+    ///Selects `index` along `dim`, removing the dimension.
+    pub fn select(&self, dim: usize, index: usize) -> Result<Tensor<D>, TensorError> {
+        self.slice(&self.along(dim, index.into())?)
+    }
+
+    ///Builds slice indices that only restrict `dim`.
+    fn along(&self, dim: usize, index: SliceIndex) -> Result<Vec<SliceIndex>, TensorError> {
+        if dim >= self.shape().rank() {
+            return Err(TensorError::InvalidDims {
+                dims: vec![dim],
+                rank: self.shape().rank(),
+            });
+        }
+        let mut indices = vec![SliceIndex::from(..); dim];
+        indices.push(index);
+        Ok(indices)
+    }
+}
+
+impl Tensor<CPU> {
+    ///Inverse of [`Tensor::slice`]: a contiguous tensor of `shape`,
+    ///holding `self` at `indices` and zeros elsewhere.
+    pub(crate) fn unslice(
+        &self,
+        shape: Shape,
+        indices: &[SliceIndex],
+    ) -> Result<Tensor<CPU>, TensorError> {
+        fn unslice_t<T: TData>(
+            src: &Tensor<CPU>,
+            shape: Shape,
+            indices: &[SliceIndex],
+        ) -> Result<Tensor<CPU>, TensorError> {
+            let mut dst = Tensor::new(shape.clone(), vec![T::zeroed(); shape.numel()])?;
+            let offsets = {
+                let view = dst.unslice_target(src, indices)?;
+                StridedIndex::new(view.shape(), view.strides())
+                    .map(|i| view.offset() + i)
+                    .collect::<Vec<_>>()
+            };
+            let data = dst.as_slice_mut::<T>()?;
+            for (o, value) in offsets.into_iter().zip(src.to_vec::<T>()?) {
+                data[o] = value;
+            }
+            Ok(dst)
+        }
+        as_std!(unslice_t(self.dt())(self, shape, indices))
+    }
+}
+
+impl Tensor<WebGPU> {
+    ///Inverse of [`Tensor::slice`]: a contiguous tensor of `shape`,
+    ///holding `self` at `indices` and zeros elsewhere.
+    ///Supports F32, I32 and U32.
+    pub(crate) fn unslice(
+        &self,
+        shape: Shape,
+        indices: &[SliceIndex],
+    ) -> Result<Tensor<WebGPU>, TensorError> {
+        let dt = self.dt();
+        let ty = wgsl::scalar_type(dt).ok_or(TensorError::UnsupportedDType(dt))?;
+        let dst = self.scalar_like(0)?.expand(shape)?.contiguous()?;
+        let view = dst.unslice_target(self, indices)?;
+        let numel = self.numel();
+        if numel > 0 {
+            let handle = self.device().handle();
+            let meta = Metadata::new()
+                .push(numel)
+                .push_layout(self)
+                .push_layout(&view)
+                .upload(handle)?;
+            Kernel::new("unslice", UnsliceKernel::source(ty), dt).dispatch(
+                handle,
+                &[
+                    KernelArg::tensor(self),
+                    KernelArg::output(&view),
+                    KernelArg::uniform(&meta),
+                ],
+                Workgroups::linear(numel),
+            );
+        }
+        Ok(dst)
+    }
+}
+
+impl<D: Device> Tensor<D> {
+    ///The view of `self` at `indices`, which `src` is written to by `unslice`.
+    fn unslice_target(
+        &self,
+        src: &Tensor<D>,
+        indices: &[SliceIndex],
+    ) -> Result<Tensor<D>, TensorError> {
+        let view = self.slice(indices)?;
+        if view.shape() != src.shape() {
+            return Err(TensorError::BroadcastMismatch(
+                src.shape().clone(),
+                view.shape().clone(),
+            ));
+        }
+        Ok(view)
+    }
+}
+
+///Scatters a strided input into a strided view of the output.
+///Metadata layout: `[numel, src layout, dst layout]`, see [`Metadata::push_layout`].
+struct UnsliceKernel;
+
+impl UnsliceKernel {
+    fn source(ty: &str) -> String {
+        format!(
+            r#"
+@group(0) @binding(0) var<storage, read> src: array<{ty}>;
+@group(0) @binding(1) var<storage, read_write> dst: array<{ty}>;
+{meta}{global_index}{strided_offset}
+@compute @workgroup_size({wg})
+fn main(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {{
+    let i = global_index(gid, groups);
+    if (i >= param(0u)) {{
+        return;
+    }}
+    let dst_base = 3u + 2u * param(1u);
+    dst[strided_offset(dst_base, i)] = src[strided_offset(1u, i)];
+}}
+"#,
+            meta = Metadata::declaration(2),
+            global_index = wgsl::GLOBAL_INDEX,
+            strided_offset = wgsl::STRIDED_OFFSET,
+            wg = GPUHandle::WORKGROUP_SIZE,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn slices_are_views() {
+        let t = Tensor::<CPU>::new(vec![2, 4, 3].into(), (0..24u32).collect()).unwrap();
+        let v = t.slice(&s![.., 1..3, 0]).unwrap();
+        assert!(std::sync::Arc::ptr_eq(t.storage(), v.storage()));
+        assert_eq!(v.offset(), 3);
+        assert_eq!(v.to_vec::<u32>().unwrap(), vec![3, 6, 15, 18]);
+
+        let row = t.select(0, 1).unwrap().select(0, 2).unwrap();
+        assert!(row.is_contiguous());
+        assert_eq!(row.as_slice::<u32>().unwrap(), &[18, 19, 20]);
+        assert_eq!(
+            t.narrow(2, 1, 2).unwrap().shape(),
+            &Shape::from(vec![2, 4, 2])
+        );
+        assert!(t.slice(&s![2]).is_err());
+        assert!(t.narrow(1, 3, 2).is_err());
+        assert!(matches!(
+            t.narrow(1, 1, usize::MAX),
+            Err(TensorError::IndexOutOfBounds {
+                index: 1,
+                dim: 1,
+                ..
+            })
+        ));
+        for index in [
+            SliceIndex::from(1..=usize::MAX),
+            SliceIndex::from(..=usize::MAX),
+        ] {
+            assert!(matches!(
+                t.slice(&[index]),
+                Err(TensorError::IndexOutOfBounds {
+                    index: usize::MAX,
+                    dim: 0,
+                    size: 2
+                })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn gpu_transfer_honors_offset() {
+        let t = Tensor::<CPU>::new(vec![4, 3].into(), (0..12u32).collect()).unwrap();
+        let v = t.slice(&s![1..3, 1..]).unwrap();
+        let expected = v.contiguous().unwrap();
+        let gpu = v.to(&Arc::new(WebGPU::new().await.unwrap())).unwrap();
+        let packed = gpu.contiguous().unwrap();
+        assert_eq!(packed.offset(), 0);
+        assert_eq!(packed.to(&Arc::new(CPU)).unwrap(), expected);
+    }
+}
@@ -1,9 +1,11 @@
-use crate::device::Device;
-use crate::{AllocMode, CPUPrim, TData, CPU};
+use crate::device::{Device, DevicePrimitive};
+use crate::{AllocMode, CPUPrim, DType, TData, CPU};
+use memmap2::Mmap;
 use std::alloc::Layout;
 use std::fmt::Debug;
 use std::mem::ManuallyDrop;
-use std::rc::Rc;
+use std::ops::Range;
+use std::sync::Arc;
 
 #[derive(thiserror::Error, Debug)]
 pub enum StorageError {
@@ -13,6 +15,18 @@ pub enum StorageError {
     InvalidLayout(#[from] std::alloc::LayoutError),
     #[error("Attempted to access empty storage")]
     EmptyStorage,
+    #[error("Attempted to access storage of type {actual:?} as {expected:?}")]
+    DTypeMismatch { expected: DType, actual: DType },
+    #[error("Region {start}..{end} is out of bounds of a mapping of {len} bytes")]
+    OutOfBounds {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    #[error("Region {start}..{end} is not aligned to, or a multiple of, the size of {dt:?}")]
+    Misaligned { start: usize, end: usize, dt: DType },
+    #[error("Cannot mutably access memory-mapped storage")]
+    ReadOnly,
 }
 
 ///Storage is an abstraction that allows us to decouple a Tensor from its data.
@@ -21,34 +35,91 @@ pub enum StorageError {
 ///* D: The device on which the data is stored.
 #[derive(Debug)]
 pub struct Storage<D: Device> {
-    data: D::Prim,
+    data: ManuallyDrop<D::Prim>,
+    dt: DType,
     layout: Layout,
-    device: Rc<D>,
+    device: Arc<D>,
 }
 
 impl<D: Device> Storage<D> {
+    ///Wraps memory already allocated on the device.
+    pub(crate) fn from_prim(data: D::Prim, dt: DType, layout: Layout, device: Arc<D>) -> Self {
+        Self {
+            data: ManuallyDrop::new(data),
+            dt,
+            layout,
+            device,
+        }
+    }
+
     ///Copy storage from the current device to an external device.
     ///Similar to Pytorch's [`to`](https://pytorch.org/docs/stable/generated/torch.Tensor.to.html) method.
-    pub fn to<Ext: Device>(&self, ext: Ext) -> Result<Storage<Ext>, StorageError> {
-        let mut dst = ext.allocate(self.layout, AllocMode::COPY_SRC | AllocMode::COPY_DST)?;
-        self.device.copy_to(&self.data, &mut dst, &ext)?;
+    ///The copy shares the handle `ext`, so it can be combined with other storage on that device.
+    pub fn to<Ext: Device>(&self, ext: &Arc<Ext>) -> Result<Storage<Ext>, StorageError> {
+        let mut dst = ext.allocate(
+            self.layout,
+            AllocMode::STORAGE | AllocMode::COPY_SRC | AllocMode::COPY_DST,
+        )?;
+        self.device.copy_to(&self.data, &mut dst, &**ext)?;
 
         Ok(Storage {
-            data: dst,
+            data: ManuallyDrop::new(dst),
+            dt: self.dt,
             layout: self.layout,
-            device: Rc::new(ext),
+            device: ext.clone(),
         })
     }
 
+    ///Takes the memory out of the storage without deallocating it.
+    pub(crate) fn into_prim(self) -> D::Prim {
+        let mut this = ManuallyDrop::new(self);
+        //SAFETY: `this` is never dropped, so each field is taken or dropped exactly once.
+        unsafe {
+            std::ptr::drop_in_place(&mut this.device);
+            ManuallyDrop::take(&mut this.data)
+        }
+    }
+
+    ///Like [`Storage::to`], awaiting the device instead of blocking while reading it back.
+    ///Dropping the future cancels the transfer, leaving this storage intact.
+    ///Host memory and copies within a device never wait on a read back, so they go through [`Storage::to`].
+    pub async fn to_async<Ext: Device>(
+        &self,
+        ext: &Arc<Ext>,
+    ) -> Result<Storage<Ext>, StorageError> {
+        if ext.id() == self.device.id() || self.data.as_wgpu_buffer().is_none() {
+            return self.to(ext);
+        }
+        let mut host = vec![0u8; self.layout.size()];
+        self.device
+            .copy_to_host_async(&self.data, &mut host)
+            .await?;
+        let mut dst = ext.allocate(
+            self.layout,
+            AllocMode::STORAGE | AllocMode::COPY_SRC | AllocMode::COPY_DST,
+        )?;
+        ext.copy_from_host(&host, &mut dst)?;
+        Ok(Storage::from_prim(dst, self.dt, self.layout, ext.clone()))
+    }
+
     pub fn data(&self) -> &D::Prim {
         &self.data
     }
 
+    pub fn dt(&self) -> DType {
+        self.dt
+    }
+
     pub fn layout(&self) -> &Layout {
         &self.layout
     }
 
-    pub fn device(&self) -> &Rc<D> {
+    ///Number of elements of [`Storage::dt`] that fit in the allocation.
+    pub fn numel(&self) -> usize {
+        self.layout.size() / self.dt.size_of() * self.dt.block_size()
+    }
+
+    pub fn device(&self) -> &Arc<D> {
         &self.device
     }
 }
@@ -62,13 +133,60 @@ impl Storage<CPU> {
         let ptr = content.as_mut_ptr() as *mut u8;
 
         Ok(Storage {
-            data: CPUPrim::new(ptr, layout.size()),
+            data: ManuallyDrop::new(CPUPrim::new(ptr, layout.size())),
+            dt,
             layout,
-            device: Rc::new(CPU),
+            device: Arc::new(CPU),
         })
     }
 
+    ///Wraps the bytes `range` of a memory-mapped file without copying them.
+    ///The mapping is kept alive for as long as the storage, and cannot be written through it.
+    ///The region must be aligned to `dt`, and a multiple of its size, see [`DType::size_of`].
+    pub fn from_mmap(
+        mmap: Arc<Mmap>,
+        range: Range<usize>,
+        dt: DType,
+    ) -> Result<Self, StorageError> {
+        let Range { start, end } = range;
+        if start > end || end > mmap.len() {
+            return Err(StorageError::OutOfBounds {
+                start,
+                end,
+                len: mmap.len(),
+            });
+        }
+        let ptr = mmap.as_ptr().wrapping_add(start);
+        if ptr.align_offset(dt.alignment()) != 0 || (end - start) % dt.size_of() != 0 {
+            return Err(StorageError::Misaligned { start, end, dt });
+        }
+        let layout = Layout::from_size_align(end - start, dt.alignment())?;
+        //As for allocations, empty regions use a dangling pointer.
+        let ptr = if layout.size() == 0 {
+            std::ptr::without_provenance(layout.align())
+        } else {
+            ptr
+        };
+        Ok(Storage {
+            data: ManuallyDrop::new(CPUPrim::mapped(mmap, ptr, layout.size())),
+            dt,
+            layout,
+            device: Arc::new(CPU),
+        })
+    }
+
+    ///Whether the storage borrows a memory-mapped file, see [`Storage::from_mmap`].
+    pub fn is_mapped(&self) -> bool {
+        self.data.is_mapped()
+    }
+
+    ///The bytes of the whole allocation.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data.as_ptr(), self.data.len()) }
+    }
+
     pub fn as_ptr<T: TData>(&self) -> Result<*const T, StorageError> {
+        self.check_dtype::<T>()?;
         let ptr: *const T = self.data.as_ptr();
         if ptr.is_null() {
             Err(StorageError::EmptyStorage)
@@ -76,10 +194,35 @@ impl Storage<CPU> {
             Ok(ptr)
         }
     }
+
+    pub fn as_mut_ptr<T: TData>(&mut self) -> Result<*mut T, StorageError> {
+        self.check_dtype::<T>()?;
+        if self.is_mapped() {
+            return Err(StorageError::ReadOnly);
+        }
+        let ptr: *mut T = self.data.as_mut_ptr();
+        if ptr.is_null() {
+            Err(StorageError::EmptyStorage)
+        } else {
+            Ok(ptr)
+        }
+    }
+
+    fn check_dtype<T: TData>(&self) -> Result<(), StorageError> {
+        if T::dtype() != self.dt {
+            return Err(StorageError::DTypeMismatch {
+                expected: T::dtype(),
+                actual: self.dt,
+            });
+        }
+        Ok(())
+    }
 }
 
 impl<D: Device> Drop for Storage<D> {
     fn drop(&mut self) {
-        self.device.deallocate(&mut self.data, self.layout).unwrap();
+        //SAFETY: data is never accessed again after being taken.
+        let data = unsafe { ManuallyDrop::take(&mut self.data) };
+        self.device.deallocate(data, self.layout).unwrap();
     }
 }
@@ -1,6 +1,12 @@
-use std::rc::Rc;
+use memmap2::Mmap;
+use std::alloc::Layout;
+use std::ops::Range;
+use std::sync::Arc;
 
-use crate::{as_std, DType, Device, Shape, Storage, StorageError, Strides, TData, CPU};
+use crate::{
+    as_std, quant::check_blocks, AllocMode, DType, Device, DeviceId, GradNode, Shape, Storage,
+    StorageError, StridedIndex, Strides, TData, CPU,
+};
 use itertools::Itertools;
 
 #[derive(thiserror::Error, Debug)]
@@ -9,6 +15,44 @@ pub enum TensorError {
     ShapeMismatch(Shape, usize),
     #[error("Storage error: {0}")]
     StorageError(#[from] StorageError),
+    #[error("Attempted to access tensor of type {actual:?} as {expected:?}")]
+    DTypeMismatch { expected: DType, actual: DType },
+    #[error("Expected a tensor with a single element, got shape: {0:?}")]
+    NotScalar(Shape),
+    #[error("Cannot mutably access storage shared with another tensor")]
+    SharedStorage,
+    #[error("DType {0:?} is not supported by this operation on this device")]
+    UnsupportedDType(DType),
+    #[error("Device error: {0}")]
+    DeviceError(#[from] crate::DeviceError),
+    #[error("Operation requires a contiguous tensor, got shape {0:?} with strides {1:?}")]
+    NotContiguous(Shape, Strides),
+    #[error("Invalid dimensions {dims:?} for tensor of rank {rank}")]
+    InvalidDims { dims: Vec<usize>, rank: usize },
+    #[error("Cannot broadcast shape {0:?} to {1:?}")]
+    BroadcastMismatch(Shape, Shape),
+    #[error("Index {index} is out of bounds for dimension {dim} with size {size}")]
+    IndexOutOfBounds {
+        index: usize,
+        dim: usize,
+        size: usize,
+    },
+    #[error("Cannot multiply matrices of shapes {0:?} and {1:?}")]
+    MatmulMismatch(Shape, Shape),
+    #[error("Multiplying matrices of shapes {0:?} and {1:?} exceeds the dispatch limits")]
+    DispatchLimit(Shape, Shape),
+    #[error("Kernel metadata of {0} values exceeds the capacity of {cap}", cap = crate::Metadata::CAPACITY)]
+    MetadataOverflow(usize),
+    #[error("Cannot reduce over an empty dimension of shape {0:?}")]
+    EmptyReduction(Shape),
+    #[error("Integer overflow reducing a tensor of type {0:?}")]
+    IntegerOverflow(DType),
+    #[error("Tensor does not require grad")]
+    NoGradient,
+    #[error("Shape {0:?} does not split into blocks of {1} elements along its last dimension")]
+    BlockMismatch(Shape, usize),
+    #[error("Expected a tensor on {expected}, got one on {actual}")]
+    DeviceMismatch { expected: String, actual: String },
 }
 
 ///Tensor is a generalization of vectors and matrices to potentially higher dimensions.
@@ -16,12 +60,30 @@ pub enum TensorError {
 ///This decouples the tensor from the underlying memory, so in essence, every tensor is a view into a storage.
 ///T: The type of the elements in the tensor.
 ///D: The device on which the tensor is stored.
+///Tensors are `Send + Sync`, views can be shared between threads and tasks.
 #[derive(Debug)]
 pub struct Tensor<D: Device> {
     dt: DType,
     shape: Shape,
     strides: Strides,
-    storage: Rc<Storage<D>>,
+    offset: usize,
+    storage: Arc<Storage<D>>,
+    autograd: Option<Arc<GradNode<D>>>, //Set if the tensor requires grad.
+}
+
+///Cloning a tensor is cheap, the clone is a view sharing the same storage.
+///Clones also share autograd state, gradients of a leaf accumulate across all of them.
+impl<D: Device> Clone for Tensor<D> {
+    fn clone(&self) -> Self {
+        Self {
+            dt: self.dt,
+            shape: self.shape.clone(),
+            strides: self.strides.clone(),
+            offset: self.offset,
+            storage: self.storage.clone(),
+            autograd: self.autograd.clone(),
+        }
+    }
 }
 
 impl PartialEq for Tensor<CPU> {
@@ -32,28 +94,162 @@ impl PartialEq for Tensor<CPU> {
         if self.dt != other.dt {
             return false;
         }
-        unsafe fn eq_t<T: TData>(a: &Tensor<CPU>, b: &Tensor<CPU>) -> bool {
-            a.as_slice::<T>().unwrap() == b.as_slice::<T>().unwrap()
+        if self.dt.is_quantized() {
+            return self.to_bytes().ok() == other.to_bytes().ok();
+        }
+        fn eq_t<T: TData>(a: &Tensor<CPU>, b: &Tensor<CPU>) -> Result<bool, TensorError> {
+            Ok(a.to_vec::<T>()? == b.to_vec::<T>()?)
         }
-        unsafe { as_std!(eq_t(self.dt)(self, other)) }
+        let eq = || -> Result<bool, TensorError> { as_std!(eq_t(self.dt)(self, other)) };
+        eq().unwrap_or(false)
     }
 }
 
 impl<D: Device> Tensor<D> {
-    ///Moves the tensor from D -> Other.
-    pub fn to<Ext: Device>(self, ext: Ext) -> Result<Tensor<Ext>, anyhow::Error> {
-        let storage = self.storage.to(ext)?;
+    pub(crate) fn from_storage(dt: DType, shape: Shape, storage: Storage<D>) -> Self {
+        Self {
+            dt,
+            strides: shape.clone().into(),
+            shape,
+            offset: 0,
+            storage: Arc::new(storage),
+            autograd: None,
+        }
+    }
+
+    ///Creates a new view of the same storage with a different shape and strides.
+    pub(crate) fn view_of(&self, shape: Shape, strides: Strides) -> Self {
+        self.view_at(shape, strides, self.offset)
+    }
+
+    ///Creates a new view of the same storage, starting `offset` elements into it.
+    pub(crate) fn view_at(&self, shape: Shape, strides: Strides, offset: usize) -> Self {
+        Self {
+            dt: self.dt,
+            shape,
+            strides,
+            offset,
+            storage: self.storage.clone(),
+            autograd: None,
+        }
+    }
+
+    ///Moves the tensor from D -> Other, onto the shared device handle `ext`.
+    ///The result does not require grad.
+    pub fn to<Ext: Device>(self, ext: &Arc<Ext>) -> Result<Tensor<Ext>, anyhow::Error> {
+        Ok(self.map_storage(|storage| storage.to(ext).map(Arc::new))?)
+    }
+
+    ///Moves the tensor from D -> Other without blocking the executor, see [`Storage::to_async`].
+    ///The result does not require grad.
+    pub async fn to_async<Ext: Device>(self, ext: &Arc<Ext>) -> Result<Tensor<Ext>, anyhow::Error> {
+        let storage = Arc::new(self.storage.to_async(ext).await?);
+        Ok(self.map_storage(|_| Ok::<_, StorageError>(storage))?)
+    }
+
+    ///Rebuilds the view around storage produced by `f`, which must hold as many elements.
+    ///The result does not require grad.
+    pub(crate) fn map_storage<Ext: Device, E>(
+        self,
+        f: impl FnOnce(Arc<Storage<D>>) -> Result<Arc<Storage<Ext>>, E>,
+    ) -> Result<Tensor<Ext>, E> {
         Ok(Tensor {
             dt: self.dt,
             shape: self.shape,
             strides: self.strides,
-            storage: Rc::new(storage),
+            offset: self.offset,
+            storage: f(self.storage)?,
+            autograd: None,
         })
     }
 
     pub fn device(&self) -> &D {
         self.storage.device()
     }
+
+    ///Identifies the device instance holding the tensor, see [`Tensor::check_device`].
+    pub fn device_id(&self) -> DeviceId {
+        self.device().id()
+    }
+
+    ///Fails with [`TensorError::DeviceMismatch`] unless `other` lives on the same device instance.
+    pub fn check_device(&self, other: &Tensor<D>) -> Result<(), TensorError> {
+        if self.device_id() != other.device_id() {
+            return Err(TensorError::DeviceMismatch {
+                expected: self.device_id().to_string(),
+                actual: other.device_id().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn dt(&self) -> DType {
+        self.dt
+    }
+
+    pub fn shape(&self) -> &Shape {
+        &self.shape
+    }
+
+    pub fn strides(&self) -> &Strides {
+        &self.strides
+    }
+
+    pub fn numel(&self) -> usize {
+        self.shape.numel()
+    }
+
+    pub fn storage(&self) -> &Arc<Storage<D>> {
+        &self.storage
+    }
+
+    pub(crate) fn autograd(&self) -> Option<&Arc<GradNode<D>>> {
+        self.autograd.as_ref()
+    }
+
+    pub(crate) fn set_autograd(&mut self, autograd: Option<Arc<GradNode<D>>>) {
+        self.autograd = autograd;
+    }
+
+    ///Offset, in elements, of the first element of the tensor within its storage.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn is_contiguous(&self) -> bool {
+        self.strides.is_contiguous(&self.shape)
+    }
+
+    ///Contiguous and starting at the beginning of the storage.
+    ///Kernels that index the storage directly require this.
+    pub(crate) fn is_packed(&self) -> bool {
+        self.is_contiguous() && self.offset == 0
+    }
+
+    ///Ensures that T matches the tensor's dtype, and that the storage
+    ///holds enough elements for the shape.
+    fn check_access<T: TData>(&self) -> Result<(), TensorError> {
+        if T::dtype() != self.dt {
+            return Err(TensorError::DTypeMismatch {
+                expected: T::dtype(),
+                actual: self.dt,
+            });
+        }
+        let extent = self
+            .shape
+            .iter()
+            .zip(self.strides.iter())
+            .map(|(&dim, &stride)| dim.saturating_sub(1) * stride)
+            .sum::<usize>()
+            + 1;
+        if self.shape.numel() > 0 && self.offset + extent > self.storage.numel() {
+            return Err(TensorError::ShapeMismatch(
+                self.shape.clone(),
+                self.storage.numel(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl Tensor<CPU> {
@@ -73,27 +269,374 @@ impl Tensor<CPU> {
             dt,
             shape,
             strides,
+            offset: 0,
             storage: storage.into(),
+            autograd: None,
         })
     }
 
-    pub fn as_slice<T: TData>(&self) -> anyhow::Result<&[T]> {
+    ///Instantiates a contiguous tensor from the native-endian bytes of its elements,
+    ///or of its blocks for block-quantized dtypes.
+    ///The bytes are copied, so they need not be aligned to `dt`.
+    pub fn from_bytes(dt: DType, shape: Shape, bytes: &[u8]) -> Result<Self, TensorError> {
+        if dt.is_quantized() {
+            check_blocks(dt, &shape)?;
+            if bytes.len() != dt.byte_len(shape.numel()) {
+                return Err(TensorError::ShapeMismatch(
+                    shape,
+                    bytes.len() / dt.size_of() * dt.block_size(),
+                ));
+            }
+            let layout =
+                Layout::from_size_align(bytes.len(), dt.alignment()).map_err(StorageError::from)?;
+            let mut data = CPU.allocate(layout, AllocMode::empty())?;
+            CPU.copy_from_host(bytes, &mut data)?;
+            let storage = Storage::from_prim(data, dt, layout, Arc::new(CPU));
+            return Ok(Self::from_storage(dt, shape, storage));
+        }
+        fn from_bytes_t<T: TData>(shape: Shape, bytes: &[u8]) -> Result<Tensor<CPU>, TensorError> {
+            if bytes.len() != shape.numel() * std::mem::size_of::<T>() {
+                return Err(TensorError::ShapeMismatch(
+                    shape,
+                    bytes.len() / std::mem::size_of::<T>(),
+                ));
+            }
+            let mut data = vec![T::zeroed(); shape.numel()];
+            bytemuck::cast_slice_mut::<T, u8>(&mut data).copy_from_slice(bytes);
+            Tensor::new(shape, data)
+        }
+        as_std!(from_bytes_t(dt)(shape, bytes))
+    }
+
+    ///Views the bytes `range` of a memory-mapped file as a contiguous tensor, without copying them.
+    ///The tensor is read-only, and keeps the mapping alive. See [`Storage::from_mmap`].
+    pub fn from_mmap(
+        mmap: Arc<Mmap>,
+        range: Range<usize>,
+        dt: DType,
+        shape: Shape,
+    ) -> Result<Self, TensorError> {
+        check_blocks(dt, &shape)?;
+        let storage = Storage::from_mmap(mmap, range, dt)?;
+        if storage.numel() != shape.numel() {
+            return Err(TensorError::ShapeMismatch(shape, storage.numel()));
+        }
+        Ok(Self::from_storage(dt, shape, storage))
+    }
+
+    ///Copies the native-endian bytes of the elements in row-major order, following the strides.
+    ///Block-quantized tensors must be packed, and their blocks are copied as is.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, TensorError> {
+        if self.dt.is_quantized() {
+            if !self.is_packed() {
+                return Err(TensorError::NotContiguous(
+                    self.shape.clone(),
+                    self.strides.clone(),
+                ));
+            }
+            return Ok(self.storage.as_bytes()[..self.dt.byte_len(self.numel())].to_vec());
+        }
+        fn to_bytes_t<T: TData>(t: &Tensor<CPU>) -> Result<Vec<u8>, TensorError> {
+            if t.is_contiguous() {
+                return Ok(bytemuck::cast_slice(t.as_slice::<T>()?).to_vec());
+            }
+            Ok(bytemuck::cast_slice(&t.to_vec::<T>()?).to_vec())
+        }
+        as_std!(to_bytes_t(self.dt)(self))
+    }
+
+    ///Borrows the elements of a contiguous tensor.
+    ///Use [`Tensor::to_vec`] or [`Tensor::contiguous`] for strided views.
+    pub fn as_slice<T: TData>(&self) -> Result<&[T], TensorError> {
+        self.check_access::<T>()?;
+        if !self.is_contiguous() {
+            return Err(TensorError::NotContiguous(
+                self.shape.clone(),
+                self.strides.clone(),
+            ));
+        }
+        if self.shape.numel() == 0 {
+            return Ok(&[]);
+        }
+        let ptr: *const T = self.storage.as_ptr()?;
+        unsafe {
+            Ok(std::slice::from_raw_parts::<T>(
+                ptr.add(self.offset),
+                self.shape.numel(),
+            ))
+        }
+    }
+
+    ///Mutable access requires that no other tensor shares the storage.
+    pub fn as_slice_mut<T: TData>(&mut self) -> Result<&mut [T], TensorError> {
+        self.check_access::<T>()?;
+        if !self.is_contiguous() {
+            return Err(TensorError::NotContiguous(
+                self.shape.clone(),
+                self.strides.clone(),
+            ));
+        }
+        let numel = self.shape.numel();
+        if numel == 0 {
+            return Ok(&mut []);
+        }
+        let storage = Arc::get_mut(&mut self.storage).ok_or(TensorError::SharedStorage)?;
+        let ptr: *mut T = storage.as_mut_ptr()?;
+        unsafe {
+            Ok(std::slice::from_raw_parts_mut::<T>(
+                ptr.add(self.offset),
+                numel,
+            ))
+        }
+    }
+
+    ///Copies the elements into a Vec in row-major order, following the strides.
+    pub fn to_vec<T: TData>(&self) -> Result<Vec<T>, TensorError> {
+        if self.is_contiguous() {
+            return Ok(self.as_slice::<T>()?.to_vec());
+        }
+        self.check_access::<T>()?;
+        if self.numel() == 0 {
+            return Ok(vec![]);
+        }
+        let data = self.storage_slice::<T>()?;
+        Ok(StridedIndex::new(&self.shape, &self.strides)
+            .map(|i| data[self.offset + i])
+            .collect())
+    }
+
+    ///Borrows the entire underlying storage, ignoring the shape, strides and offset of the view.
+    pub(crate) fn storage_slice<T: TData>(&self) -> Result<&[T], TensorError> {
+        self.check_access::<T>()?;
+        if self.storage.numel() == 0 {
+            return Ok(&[]);
+        }
         let ptr: *const T = self.storage.as_ptr()?;
-        if ptr.is_null() {
-            Ok(&[])
-        } else {
-            unsafe { Ok(std::slice::from_raw_parts::<T>(ptr, self.shape.numel())) }
+        unsafe { Ok(std::slice::from_raw_parts::<T>(ptr, self.storage.numel())) }
+    }
+
+    ///Returns the value of a single element tensor.
+    pub fn item<T: TData>(&self) -> Result<T, TensorError> {
+        if self.shape.numel() != 1 {
+            return Err(TensorError::NotScalar(self.shape.clone()));
         }
+        Ok(self.to_vec::<T>()?[0])
     }
 }
 
 impl std::fmt::Display for Tensor<CPU> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        unsafe fn dump_t<T: TData>(tensor: &Tensor<CPU>, n: usize) -> String {
-            tensor.as_slice::<T>().unwrap()[0..n].iter().join(", ")
+        fn dump_t<T: TData>(tensor: &Tensor<CPU>, n: usize) -> Result<String, TensorError> {
+            Ok(tensor.to_vec::<T>()?[0..n].iter().join(", "))
         }
-        write!(f, "[{}]", unsafe {
+        if self.dt.is_quantized() {
+            return match self.dequantize() {
+                Ok(dense) => dense.fmt(f),
+                Err(e) => write!(f, "<{}>", e),
+            };
+        }
+        let dump = || -> Result<String, TensorError> {
             as_std!(dump_t(self.dt)(self, self.shape.numel()))
+        };
+        match dump() {
+            Ok(elements) => write!(f, "[{}]", elements),
+            Err(e) => write!(f, "<{}>", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_mismatched_dtype() {
+        let tensor = Tensor::<CPU>::new(vec![2, 2].into(), vec![1f32, 2., 3., 4.]).unwrap();
+        assert!(matches!(
+            tensor.as_slice::<u64>(),
+            Err(TensorError::DTypeMismatch {
+                expected: DType::U64,
+                actual: DType::F32
+            })
+        ));
+        assert!(tensor.to_vec::<i32>().is_err());
+        assert_eq!(tensor.to_vec::<f32>().unwrap(), vec![1., 2., 3., 4.]);
+    }
+
+    #[test]
+    fn half_equality() {
+        let a = Tensor::<CPU>::new(vec![2].into(), vec![half::bf16::from_f32(0.5); 2]).unwrap();
+        let b = Tensor::<CPU>::new(vec![2].into(), vec![half::bf16::from_f32(0.5); 2]).unwrap();
+        let nan = Tensor::<CPU>::new(vec![2].into(), vec![half::bf16::NAN; 2]).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, nan);
+        assert_eq!(a.dt(), DType::BF16);
+        assert_eq!(a.to_string(), "[0.5, 0.5]");
+    }
+
+    #[test]
+    fn item_and_mutable_access() {
+        let mut tensor = Tensor::<CPU>::new(vec![1].into(), vec![7u32]).unwrap();
+        tensor.as_slice_mut::<u32>().unwrap()[0] = 9;
+        assert_eq!(tensor.item::<u32>().unwrap(), 9);
+
+        let matrix = Tensor::<CPU>::new(vec![2].into(), vec![1u32, 2]).unwrap();
+        assert!(matches!(
+            matrix.item::<u32>(),
+            Err(TensorError::NotScalar(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn transfers_share_device_handle() {
+        let device = Arc::new(crate::WebGPU::new().await.unwrap());
+        let a = Tensor::<CPU>::new(vec![3].into(), vec![1f32, 2., 3.]).unwrap();
+        let b = Tensor::<CPU>::new(vec![3].into(), vec![4f32, 5., 6.]).unwrap();
+        let (a, b) = (a.to(&device).unwrap(), b.to(&device).unwrap());
+        assert!(Arc::ptr_eq(a.storage().device(), b.storage().device()));
+        assert_eq!(a.device_id(), device.id());
+        assert_eq!(a.device_id().kind(), "WebGPU");
+        assert_ne!(a.device_id(), DeviceId::CPU);
+
+        let sum = a.add(&b).unwrap().to(&Arc::new(CPU)).unwrap();
+        assert_eq!(sum.device_id(), DeviceId::CPU);
+        assert_eq!(sum.to_vec::<f32>().unwrap(), vec![5., 7., 9.]);
+    }
+
+    #[tokio::test]
+    async fn operands_on_different_devices_are_rejected() {
+        let first = Arc::new(crate::WebGPU::new().await.unwrap());
+        let second = Arc::new(crate::WebGPU::new().await.unwrap());
+        let host = Tensor::<CPU>::new(vec![2, 2].into(), vec![1f32, 2., 3., 4.]).unwrap();
+        let a = host.clone().to(&first).unwrap();
+        let b = host.to(&second).unwrap();
+        assert_ne!(a.device_id(), b.device_id());
+        assert!(matches!(
+            a.add(&b),
+            Err(TensorError::DeviceMismatch { expected, actual })
+                if expected == a.device_id().to_string() && actual == b.device_id().to_string()
+        ));
+        assert!(matches!(
+            a.matmul(&b),
+            Err(TensorError::DeviceMismatch { .. })
+        ));
+        drop((a, b));
+        //Tearing down one of two live devices aborts on some backends, keep both alive.
+        std::mem::forget((first, second));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn cpu_tensors_cross_tasks() {
+        fn send_sync<T: Send + Sync>() {}
+        send_sync::<Tensor<CPU>>();
+        send_sync::<Tensor<crate::WebGPU>>();
+        send_sync::<Tensor<crate::AnyDevice>>();
+
+        let t = Tensor::<CPU>::new(vec![4].into(), vec![1f32, 2., 3., 4.]).unwrap();
+        let shared = t.clone();
+        let doubled = tokio::spawn(async move { shared.add(&shared).unwrap() })
+            .await
+            .unwrap();
+        //The spawned task dropped its view, so the storage is no longer shared.
+        assert_eq!(Arc::strong_count(t.storage()), 1);
+        let tasks = (0..4).map(|i| {
+            let doubled = doubled.clone();
+            tokio::task::spawn_blocking(move || {
+                doubled.sum_all().unwrap().item::<f32>().unwrap() * i as f32
+            })
+        });
+        let mut results = vec![];
+        for task in tasks.collect::<Vec<_>>() {
+            results.push(task.await.unwrap());
+        }
+        assert_eq!(results, vec![0., 20., 40., 60.]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn gpu_tensors_cross_tasks() {
+        let device = Arc::new(crate::WebGPU::new().await.unwrap());
+        let tasks = (0..3)
+            .map(|i| {
+                let device = device.clone();
+                tokio::spawn(async move {
+                    let t = Tensor::<CPU>::new(vec![2].into(), vec![i as f32, 1.])
+                        .unwrap()
+                        .to(&device)
+                        .unwrap();
+                    t.mul(&t).unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut squares = vec![];
+        for task in tasks {
+            squares.push(task.await.unwrap());
+        }
+        let total = tokio::spawn(async move {
+            let mut total = squares[0].clone();
+            for square in &squares[1..] {
+                total = total.add(square).unwrap();
+            }
+            total
         })
+        .await
+        .unwrap();
+        assert_eq!(total.device_id(), device.id());
+        let total = total.to(&Arc::new(CPU)).unwrap();
+        assert_eq!(total.to_vec::<f32>().unwrap(), vec![5., 3.]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn async_transfers_roundtrip() {
+        let device = Arc::new(crate::WebGPU::new().await.unwrap());
+        let cpu = Arc::new(CPU);
+        let data = (0..1027).map(|i| i as f32 / 3.).collect::<Vec<_>>();
+        let t = Tensor::<CPU>::new(vec![1027].into(), data.clone()).unwrap();
+        let gpu = t.to_async(&device).await.unwrap();
+        let sum = gpu.add(&gpu).unwrap();
+        //Within the device, the pending sum is copied without a read back.
+        let copy = sum.clone().to_async(&device).await.unwrap();
+        assert_eq!(copy.device_id(), device.id());
+        assert_eq!(copy.to(&cpu).unwrap(), sum.clone().to(&cpu).unwrap());
+
+        //Transfers run on spawned tasks, polled by the device in the background.
+        let back = tokio::spawn(async move { sum.to_async(&cpu).await.unwrap() })
+            .await
+            .unwrap();
+        let expected = data.iter().map(|x| x + x).collect::<Vec<_>>();
+        assert_eq!(back.to_vec::<f32>().unwrap(), expected);
+
+        let odd = Tensor::<CPU>::new(vec![3].into(), vec![half::f16::ONE; 3]).unwrap();
+        let odd = odd.to(&device).unwrap().to_async(&Arc::new(CPU)).await;
+        assert_eq!(
+            odd.unwrap().to_vec::<half::f16>().unwrap(),
+            vec![half::f16::ONE; 3]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn cancelled_transfers_leave_source_intact() {
+        use std::future::Future;
+        use std::task::Poll;
+
+        let device = Arc::new(crate::WebGPU::new().await.unwrap());
+        let cpu = Arc::new(CPU);
+        let t = Tensor::<CPU>::new(vec![4].into(), vec![1f32, 2., 3., 4.]).unwrap();
+        let gpu = t.clone().to(&device).unwrap();
+        for _ in 0..8 {
+            //Poll once, so the staging copy is submitted, then drop the pending future.
+            let mut transfer = Box::pin(gpu.clone().to_async(&cpu));
+            let _ = std::future::poll_fn(|cx| Poll::Ready(transfer.as_mut().poll(cx))).await;
+        }
+        let doubled = gpu.add(&gpu).unwrap();
+        assert_eq!(
+            doubled
+                .to_async(&cpu)
+                .await
+                .unwrap()
+                .to_vec::<f32>()
+                .unwrap(),
+            vec![2., 4., 6., 8.]
+        );
+        assert_eq!(gpu.to(&cpu).unwrap(), t);
     }
 }
@@ -0,0 +1,431 @@
+use crate::{
+    as_std, wgsl, Castable, DType, GPUHandle, GradOp, Kernel, KernelArg, Metadata, StridedIndex,
+    Tensor, TensorError, WebGPU, Workgroups, CPU,
+};
+use half::{bf16, f16};
+use num_traits::Float;
+
+///Elementwise unary operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Exp,
+    Log,
+    Sqrt,
+    Abs,
+    Neg,
+    Tanh,
+    Sigmoid,
+    Relu,
+    ///The tanh approximation, `0.5x(1 + tanh(sqrt(2/π)(x + 0.044715x³)))`.
+    Gelu,
+    Silu,
+    ///-1, 0 or 1. NaN is preserved.
+    Sign,
+}
+
+impl UnaryOp {
+    ///Whether the op is defined for integer DTypes.
+    pub fn is_integral(self) -> bool {
+        matches!(
+            self,
+            UnaryOp::Abs | UnaryOp::Neg | UnaryOp::Relu | UnaryOp::Sign
+        )
+    }
+}
+
+///Unary functions for every [`DType`].
+///* Integers only support [`UnaryOp::is_integral`] ops, which wrap on overflow.
+///* `relu` maps NaN to 0.
+pub trait Unary: Castable {
+    fn supports(op: UnaryOp) -> bool;
+    fn unary(self, op: UnaryOp) -> Self;
+}
+
+fn float_unary<F: Float>(op: UnaryOp, x: F) -> F {
+    let one = F::one();
+    match op {
+        UnaryOp::Exp => x.exp(),
+        UnaryOp::Log => x.ln(),
+        UnaryOp::Sqrt => x.sqrt(),
+        UnaryOp::Abs => x.abs(),
+        UnaryOp::Neg => -x,
+        UnaryOp::Tanh => x.tanh(),
+        UnaryOp::Sigmoid => one / (one + (-x).exp()),
+        UnaryOp::Relu => {
+            if x > F::zero() {
+                x
+            } else {
+                F::zero()
+            }
+        }
+        UnaryOp::Gelu => {
+            let c = F::from(std::f64::consts::FRAC_2_SQRT_PI * std::f64::consts::FRAC_1_SQRT_2)
+                .unwrap();
+            let inner = c * (x + F::from(0.044715).unwrap() * x * x * x);
+            F::from(0.5).unwrap() * x * (one + inner.tanh())
+        }
+        UnaryOp::Silu => x / (one + (-x).exp()),
+        UnaryOp::Sign => {
+            if x == F::zero() {
+                x
+            } else {
+                x.signum()
+            }
+        }
+    }
+}
+
+macro_rules! unary_float {
+    ($t:ty) => {
+        impl Unary for $t {
+            fn supports(_: UnaryOp) -> bool {
+                true
+            }
+            fn unary(self, op: UnaryOp) -> Self {
+                float_unary(op, self)
+            }
+        }
+    };
+}
+
+unary_float!(f32);
+unary_float!(f64);
+
+///Half precision functions are computed in f32 and rounded back.
+macro_rules! unary_half {
+    ($t:ty) => {
+        impl Unary for $t {
+            fn supports(_: UnaryOp) -> bool {
+                true
+            }
+            fn unary(self, op: UnaryOp) -> Self {
+                <$t>::from_f32(float_unary(op, self.to_f32()))
+            }
+        }
+    };
+}
+
+unary_half!(f16);
+unary_half!(bf16);
+
+macro_rules! unary_signed {
+    ($t:ty) => {
+        impl Unary for $t {
+            fn supports(op: UnaryOp) -> bool {
+                op.is_integral()
+            }
+            fn unary(self, op: UnaryOp) -> Self {
+                match op {
+                    UnaryOp::Abs => self.wrapping_abs(),
+                    UnaryOp::Neg => self.wrapping_neg(),
+                    UnaryOp::Relu => self.max(0),
+                    UnaryOp::Sign => self.signum(),
+                    _ => unreachable!("{:?} is not defined for {}", op, stringify!($t)),
+                }
+            }
+        }
+    };
+}
+
+unary_signed!(i8);
+unary_signed!(i16);
+unary_signed!(i32);
+unary_signed!(i64);
+
+macro_rules! unary_unsigned {
+    ($t:ty) => {
+        impl Unary for $t {
+            fn supports(op: UnaryOp) -> bool {
+                op.is_integral()
+            }
+            fn unary(self, op: UnaryOp) -> Self {
+                match op {
+                    UnaryOp::Abs | UnaryOp::Relu => self,
+                    UnaryOp::Neg => self.wrapping_neg(),
+                    UnaryOp::Sign => self.min(1),
+                    _ => unreachable!("{:?} is not defined for {}", op, stringify!($t)),
+                }
+            }
+        }
+    };
+}
+
+unary_unsigned!(u8);
+unary_unsigned!(u16);
+unary_unsigned!(u32);
+unary_unsigned!(u64);
+
+impl Tensor<CPU> {
+    ///Applies `op` to every element, returning a contiguous tensor.
+    pub fn unary(&self, op: UnaryOp) -> Result<Tensor<CPU>, TensorError> {
+        fn unary_t<T: Unary>(
+            tensor: &Tensor<CPU>,
+            op: UnaryOp,
+        ) -> Result<Tensor<CPU>, TensorError> {
+            if !T::supports(op) {
+                return Err(TensorError::UnsupportedDType(T::dtype()));
+            }
+            let src = tensor.storage_slice::<T>()?;
+            let result = StridedIndex::new(tensor.shape(), tensor.strides())
+                .map(|i| src[tensor.offset() + i].unary(op))
+                .collect();
+            Tensor::new(tensor.shape().clone(), result)
+        }
+        let dst = as_std!(unary_t(self.dt())(self, op))?;
+        Ok(dst.record(GradOp::Unary(op), &[self]))
+    }
+}
+
+///Unary ops on the GPU support F32, I32 and U32, other DTypes return [`TensorError::UnsupportedDType`].
+///Compared to [`Tensor::<CPU>::unary`]:
+///* `abs`, `neg`, `relu` and `sign` match exactly.
+///* The remaining ops are within an absolute error of 1e-6 plus a relative error of 1e-5.
+///  `log` and `sqrt` of negative numbers are implementation defined.
+impl Tensor<WebGPU> {
+    pub fn unary(&self, op: UnaryOp) -> Result<Tensor<WebGPU>, TensorError> {
+        let dt = self.dt();
+        let source = UnaryKernel::source(op, dt)?;
+        let numel = self.numel();
+        let dst = Tensor::uninit(self.storage().device().clone(), dt, self.shape().clone())?;
+        if numel > 0 {
+            let handle = self.device().handle();
+            let meta = Metadata::new()
+                .push(numel)
+                .push_layout(self)
+                .upload(handle)?;
+            Kernel::new("unary", source, dt).dispatch(
+                handle,
+                &[
+                    KernelArg::tensor(self),
+                    KernelArg::output(&dst),
+                    KernelArg::uniform(&meta),
+                ],
+                Workgroups::linear(numel),
+            );
+        }
+        Ok(dst.record(GradOp::Unary(op), &[self]))
+    }
+}
+
+macro_rules! unary_methods {
+    ($device:ty) => {
+        impl Tensor<$device> {
+            pub fn exp(&self) -> Result<Tensor<$device>, TensorError> {
+                self.unary(UnaryOp::Exp)
+            }
+
+            ///Natural logarithm.
+            pub fn log(&self) -> Result<Tensor<$device>, TensorError> {
+                self.unary(UnaryOp::Log)
+            }
+
+            pub fn sqrt(&self) -> Result<Tensor<$device>, TensorError> {
+                self.unary(UnaryOp::Sqrt)
+            }
+
+            pub fn abs(&self) -> Result<Tensor<$device>, TensorError> {
+                self.unary(UnaryOp::Abs)
+            }
+
+            pub fn neg(&self) -> Result<Tensor<$device>, TensorError> {
+                self.unary(UnaryOp::Neg)
+            }
+
+            pub fn tanh(&self) -> Result<Tensor<$device>, TensorError> {
+                self.unary(UnaryOp::Tanh)
+            }
+
+            pub fn sigmoid(&self) -> Result<Tensor<$device>, TensorError> {
+                self.unary(UnaryOp::Sigmoid)
+            }
+
+            pub fn relu(&self) -> Result<Tensor<$device>, TensorError> {
+                self.unary(UnaryOp::Relu)
+            }
+
+            ///See [`UnaryOp::Gelu`].
+            pub fn gelu(&self) -> Result<Tensor<$device>, TensorError> {
+                self.unary(UnaryOp::Gelu)
+            }
+
+            pub fn silu(&self) -> Result<Tensor<$device>, TensorError> {
+                self.unary(UnaryOp::Silu)
+            }
+
+            ///See [`UnaryOp::Sign`].
+            pub fn sign(&self) -> Result<Tensor<$device>, TensorError> {
+                self.unary(UnaryOp::Sign)
+            }
+        }
+
+        ///Panics if the DType does not support negation, use [`Tensor::neg`] to handle the error.
+        impl std::ops::Neg for &Tensor<$device> {
+            type Output = Tensor<$device>;
+
+            fn neg(self) -> Self::Output {
+                Tensor::<$device>::neg(self).unwrap()
+            }
+        }
+    };
+}
+
+unary_methods!(CPU);
+unary_methods!(WebGPU);
+
+///Maps a strided input elementwise into a packed output.
+///Metadata layout: `[numel, rank, offset, shape.., strides..]`.
+struct UnaryKernel;
+
+impl UnaryKernel {
+    ///WGSL expression computing `op` of `x`.
+    fn expression(op: UnaryOp, dt: DType) -> Option<&'static str> {
+        let expr = match (dt, op) {
+            (DType::F32, UnaryOp::Exp) => "exp(x)",
+            (DType::F32, UnaryOp::Log) => "log(x)",
+            (DType::F32, UnaryOp::Sqrt) => "sqrt(x)",
+            (DType::F32, UnaryOp::Abs) => "abs(x)",
+            (DType::F32, UnaryOp::Neg) => "-x",
+            (DType::F32, UnaryOp::Tanh) => "safe_tanh(x)",
+            (DType::F32, UnaryOp::Sigmoid) => "1.0 / (1.0 + exp(-x))",
+            (DType::F32, UnaryOp::Relu) => "select(0.0, x, x > 0.0)",
+            (DType::F32, UnaryOp::Gelu) => {
+                "0.5 * x * (1.0 + safe_tanh(0.7978846 * (x + 0.044715 * x * x * x)))"
+            }
+            (DType::F32, UnaryOp::Silu) => "x / (1.0 + exp(-x))",
+            (DType::F32, UnaryOp::Sign) => "select(sign(x), x, x != x)",
+            (DType::I32, UnaryOp::Abs) => "abs(x)",
+            (DType::I32, UnaryOp::Neg) => "0 - x",
+            (DType::I32, UnaryOp::Relu) => "max(x, 0)",
+            (DType::I32, UnaryOp::Sign) => "select(select(0, -1, x < 0), 1, x > 0)",
+            (DType::U32, UnaryOp::Abs | UnaryOp::Relu) => "x",
+            (DType::U32, UnaryOp::Neg) => "0u - x",
+            (DType::U32, UnaryOp::Sign) => "min(x, 1u)",
+            _ => return None,
+        };
+        Some(expr)
+    }
+
+    fn source(op: UnaryOp, dt: DType) -> Result<String, TensorError> {
+        let unsupported = || TensorError::UnsupportedDType(dt);
+        let ty = wgsl::scalar_type(dt).ok_or_else(unsupported)?;
+        let expr = Self::expression(op, dt).ok_or_else(unsupported)?;
+        Ok(format!(
+            r#"
+@group(0) @binding(0) var<storage, read> src: array<{ty}>;
+@group(0) @binding(1) var<storage, read_write> dst: array<{ty}>;
+{meta}{global_index}{strided_offset}{tanh}
+fn op(x: {ty}) -> {ty} {{
+    return {expr};
+}}
+
+@compute @workgroup_size({wg})
+fn main(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {{
+    let i = global_index(gid, groups);
+    if (i >= param(0u)) {{
+        return;
+    }}
+    dst[i] = op(src[strided_offset(1u, i)]);
+}}
+"#,
+            meta = Metadata::declaration(2),
+            global_index = wgsl::GLOBAL_INDEX,
+            strided_offset = wgsl::STRIDED_OFFSET,
+            tanh = wgsl::SAFE_TANH,
+            wg = GPUHandle::WORKGROUP_SIZE,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+
+    fn assert_close(actual: &[f32], expected: &[f32], op: UnaryOp) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                a == e || (a - e).abs() <= 1e-6 + 1e-5 * e.abs(),
+                "{:?}: {} != {}",
+                op,
+                a,
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn cpu_unary_semantics() {
+        let x = Tensor::<CPU>::new(vec![4].into(), vec![-2f32, 0., 1., f32::NAN]).unwrap();
+        assert_eq!(
+            x.relu().unwrap().to_vec::<f32>().unwrap(),
+            vec![0., 0., 1., 0.]
+        );
+        let silu = x.silu().unwrap().to_vec::<f32>().unwrap();
+        assert!((silu[2] - 0.7310586).abs() < 1e-6);
+        assert!((x.gelu().unwrap().to_vec::<f32>().unwrap()[2] - 0.841192).abs() < 1e-5);
+
+        let ints = Tensor::<CPU>::new(vec![3].into(), vec![i8::MIN, -3, 4]).unwrap();
+        assert_eq!((-&ints).to_vec::<i8>().unwrap(), vec![i8::MIN, 3, -4]);
+        assert_eq!(ints.relu().unwrap().to_vec::<i8>().unwrap(), vec![0, 0, 4]);
+        assert_eq!(
+            ints.sign().unwrap().to_vec::<i8>().unwrap(),
+            vec![-1, -1, 1]
+        );
+        let sign = x.sign().unwrap().to_vec::<f32>().unwrap();
+        assert_eq!(sign[..3], [-1., 0., 1.]);
+        assert!(sign[3].is_nan());
+        assert!(matches!(
+            ints.exp(),
+            Err(TensorError::UnsupportedDType(DType::I8))
+        ));
+    }
+
+    #[tokio::test]
+    async fn gpu_unary_matches_cpu() {
+        let data = (0..48).map(|i| (i as f32 - 24.) * 0.37).collect::<Vec<_>>();
+        let cpu = Tensor::<CPU>::new(vec![6, 8].into(), data).unwrap();
+        let gpu = cpu
+            .clone()
+            .to(&Arc::new(WebGPU::new().await.unwrap()))
+            .unwrap();
+        let ops = [
+            UnaryOp::Exp,
+            UnaryOp::Abs,
+            UnaryOp::Neg,
+            UnaryOp::Tanh,
+            UnaryOp::Sigmoid,
+            UnaryOp::Relu,
+            UnaryOp::Gelu,
+            UnaryOp::Silu,
+            UnaryOp::Sign,
+        ];
+        let (cpu_t, gpu_t) = (cpu.transpose(0, 1).unwrap(), gpu.transpose(0, 1).unwrap());
+        for op in ops {
+            let expected = cpu_t.unary(op).unwrap();
+            let result = gpu_t.unary(op).unwrap().to(&Arc::new(CPU)).unwrap();
+            assert_close(
+                &result.to_vec::<f32>().unwrap(),
+                &expected.to_vec::<f32>().unwrap(),
+                op,
+            );
+        }
+        let positive = cpu.abs().unwrap();
+        let gpu_positive = gpu.abs().unwrap();
+        for op in [UnaryOp::Log, UnaryOp::Sqrt] {
+            let expected = positive.unary(op).unwrap().to_vec::<f32>().unwrap();
+            let result = gpu_positive.unary(op).unwrap().to(&Arc::new(CPU)).unwrap();
+            assert_close(&result.to_vec::<f32>().unwrap(), &expected, op);
+        }
+
+        let ints = Tensor::<CPU>::new(vec![3].into(), vec![i32::MIN, -3, 4]).unwrap();
+        let gpu_ints = Tensor::<WebGPU>::from_bytes(
+            gpu.storage().device().clone(),
+            DType::I32,
+            ints.shape().clone(),
+            bytemuck::cast_slice(ints.as_slice::<i32>().unwrap()),
+        )
+        .unwrap();
+        assert_eq!((-&gpu_ints).to(&Arc::new(CPU)).unwrap(), -&ints);
+        assert!(gpu_ints.tanh().is_err());
+    }
+}
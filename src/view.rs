@@ -0,0 +1,316 @@
+use crate::{
+    as_std, wgsl, DType, Device, GPUHandle, GradOp, Kernel, KernelArg, Metadata, Shape, Strides,
+    TData, Tensor, TensorError, WebGPU, Workgroups, CPU,
+};
+use smallvec::SmallVec;
+
+///View operations only rewrite the shape and strides, the returned tensor shares storage with `self`.
+impl<D: Device> Tensor<D> {
+    ///Reinterprets a contiguous tensor with a new shape of the same number of elements.
+    pub fn view(&self, shape: Shape) -> Result<Tensor<D>, TensorError> {
+        if shape.numel() != self.numel() {
+            return Err(TensorError::ShapeMismatch(shape, self.numel()));
+        }
+        if !self.is_contiguous() {
+            return Err(TensorError::NotContiguous(
+                self.shape().clone(),
+                self.strides().clone(),
+            ));
+        }
+        let strides = Strides::from(&shape);
+        Ok(self
+            .view_of(shape, strides)
+            .record(GradOp::Reshape, &[self]))
+    }
+
+    ///Reorders the dimensions, `dims` must be a permutation of `0..rank`.
+    pub fn permute(&self, dims: &[usize]) -> Result<Tensor<D>, TensorError> {
+        let rank = self.shape().rank();
+        let mut seen = vec![false; rank];
+        for &d in dims {
+            if d >= rank || std::mem::replace(&mut seen[d], true) {
+                return Err(self.invalid_dims(dims));
+            }
+        }
+        if dims.len() != rank {
+            return Err(self.invalid_dims(dims));
+        }
+        let shape = dims
+            .iter()
+            .map(|&d| self.shape()[d])
+            .collect::<SmallVec<_>>();
+        let strides = dims
+            .iter()
+            .map(|&d| self.strides()[d])
+            .collect::<SmallVec<_>>();
+        let view = self.view_of(Shape::new(shape), Strides::new(strides));
+        Ok(view.record(GradOp::Permute(dims.to_vec()), &[self]))
+    }
+
+    ///Swaps two dimensions.
+    pub fn transpose(&self, a: usize, b: usize) -> Result<Tensor<D>, TensorError> {
+        let rank = self.shape().rank();
+        if a >= rank || b >= rank {
+            return Err(self.invalid_dims(&[a, b]));
+        }
+        let mut dims = (0..rank).collect::<Vec<_>>();
+        dims.swap(a, b);
+        self.permute(&dims)
+    }
+
+    ///Removes `dim` if it has size 1, otherwise returns an identical view.
+    pub fn squeeze(&self, dim: usize) -> Result<Tensor<D>, TensorError> {
+        if dim >= self.shape().rank() {
+            return Err(self.invalid_dims(&[dim]));
+        }
+        let mut shape = self.shape().as_slice().to_vec();
+        let mut strides = self.strides().as_slice().to_vec();
+        if shape[dim] == 1 {
+            shape.remove(dim);
+            strides.remove(dim);
+        }
+        let view = self.view_of(shape.into(), Strides::new(strides));
+        Ok(view.record(GradOp::Reshape, &[self]))
+    }
+
+    ///Inserts a dimension of size 1 at `dim`.
+    pub fn unsqueeze(&self, dim: usize) -> Result<Tensor<D>, TensorError> {
+        let rank = self.shape().rank();
+        if dim > rank {
+            return Err(self.invalid_dims(&[dim]));
+        }
+        let stride = if dim < rank {
+            self.shape()[dim] * self.strides()[dim]
+        } else {
+            1
+        };
+        let mut shape = self.shape().as_slice().to_vec();
+        let mut strides = self.strides().as_slice().to_vec();
+        shape.insert(dim, 1);
+        strides.insert(dim, stride);
+        let view = self.view_of(shape.into(), Strides::new(strides));
+        Ok(view.record(GradOp::Reshape, &[self]))
+    }
+
+    ///Broadcasts the tensor to `shape`, following NumPy rules.
+    ///New leading dimensions and expanded dimensions of size 1 get a stride of 0.
+    pub fn expand(&self, shape: Shape) -> Result<Tensor<D>, TensorError> {
+        let rank = self.shape().rank();
+        if shape.rank() < rank {
+            return Err(TensorError::BroadcastMismatch(self.shape().clone(), shape));
+        }
+        let lead = shape.rank() - rank;
+        let mut strides = SmallVec::<[usize; 4]>::from_elem(0, shape.rank());
+        for d in 0..rank {
+            let (from, to) = (self.shape()[d], shape[lead + d]);
+            if from == to {
+                strides[lead + d] = self.strides()[d];
+            } else if from != 1 {
+                return Err(TensorError::BroadcastMismatch(self.shape().clone(), shape));
+            }
+        }
+        Ok(self
+            .view_of(shape, Strides::new(strides))
+            .record(GradOp::Expand, &[self]))
+    }
+
+    fn invalid_dims(&self, dims: &[usize]) -> TensorError {
+        TensorError::InvalidDims {
+            dims: dims.to_vec(),
+            rank: self.shape().rank(),
+        }
+    }
+}
+
+impl Tensor<CPU> {
+    ///Returns a tensor with the same elements laid out densely in row-major order.
+    ///If `self` is already contiguous, the storage is shared rather than copied.
+    pub fn contiguous(&self) -> Result<Tensor<CPU>, TensorError> {
+        if self.is_contiguous() {
+            return Ok(self.clone());
+        }
+        fn contiguous_t<T: TData>(tensor: &Tensor<CPU>) -> Result<Tensor<CPU>, TensorError> {
+            Tensor::new(tensor.shape().clone(), tensor.to_vec::<T>()?)
+        }
+        let dst = as_std!(contiguous_t(self.dt())(self))?;
+        Ok(dst.record(GradOp::Identity, &[self]))
+    }
+
+    ///Like [`Tensor::view`], but copies into a contiguous tensor first if needed.
+    pub fn reshape(&self, shape: Shape) -> Result<Tensor<CPU>, TensorError> {
+        self.contiguous()?.view(shape)
+    }
+}
+
+impl Tensor<WebGPU> {
+    ///Returns a tensor with the same elements laid out densely in row-major order,
+    ///starting at the beginning of its storage.
+    ///If `self` is already laid out this way, the storage is shared rather than copied.
+    pub fn contiguous(&self) -> Result<Tensor<WebGPU>, TensorError> {
+        if self.is_packed() {
+            return Ok(self.clone());
+        }
+        let dt = self.dt();
+        if dt.is_quantized() {
+            return Err(TensorError::UnsupportedDType(dt));
+        }
+        let numel = self.numel();
+        let dst = Tensor::uninit(self.storage().device().clone(), dt, self.shape().clone())?;
+        if numel > 0 {
+            let handle = self.device().handle();
+            let meta = Metadata::new()
+                .push(numel)
+                .push_layout(self)
+                .upload(handle)?;
+            let bits = dt.size_of() as u32 * 8;
+            let (source, invocations) = if bits > 32 {
+                (ContiguousKernel::wide(bits / 32), numel)
+            } else {
+                (
+                    ContiguousKernel::packed(bits),
+                    (numel * dt.size_of()).div_ceil(4),
+                )
+            };
+            Kernel::new("contiguous", source, dt).dispatch(
+                handle,
+                &[
+                    KernelArg::tensor(self),
+                    KernelArg::output(&dst),
+                    KernelArg::uniform(&meta),
+                ],
+                Workgroups::linear(invocations),
+            );
+        }
+        Ok(dst.record(GradOp::Identity, &[self]))
+    }
+
+    ///Like [`Tensor::view`], but copies into a contiguous tensor first if needed.
+    pub fn reshape(&self, shape: Shape) -> Result<Tensor<WebGPU>, TensorError> {
+        self.contiguous()?.view(shape)
+    }
+}
+
+///Gathers a strided view into a packed buffer.
+///Metadata layout: `[numel, rank, offset, shape.., strides..]`.
+struct ContiguousKernel;
+
+impl ContiguousKernel {
+    fn header() -> String {
+        format!(
+            r#"
+@group(0) @binding(0) var<storage, read> src: array<u32>;
+@group(0) @binding(1) var<storage, read_write> dst: array<u32>;
+{}{}{}"#,
+            Metadata::declaration(2),
+            wgsl::GLOBAL_INDEX,
+            wgsl::STRIDED_OFFSET
+        )
+    }
+
+    ///Elements of 32 bits or less, each invocation writes one u32 word of the output.
+    fn packed(bits: u32) -> String {
+        let per_word = 32 / bits;
+        let mask = u32::MAX >> (32 - bits);
+        format!(
+            r#"{header}
+@compute @workgroup_size({wg})
+fn main(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {{
+    let numel = param(0u);
+    let word = global_index(gid, groups);
+    if (word * {per_word}u >= numel) {{
+        return;
+    }}
+    var result = 0u;
+    for (var k = 0u; k < {per_word}u; k++) {{
+        let i = word * {per_word}u + k;
+        if (i < numel) {{
+            let o = strided_offset(1u, i);
+            let raw = (src[o / {per_word}u] >> ((o % {per_word}u) * {bits}u)) & {mask}u;
+            result |= raw << (k * {bits}u);
+        }}
+    }}
+    dst[word] = result;
+}}
+"#,
+            header = Self::header(),
+            wg = GPUHandle::WORKGROUP_SIZE,
+        )
+    }
+
+    ///Elements wider than 32 bits, each invocation copies `words` u32 words of one element.
+    fn wide(words: u32) -> String {
+        format!(
+            r#"{header}
+@compute @workgroup_size({wg})
+fn main(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {{
+    let i = global_index(gid, groups);
+    if (i >= param(0u)) {{
+        return;
+    }}
+    let o = strided_offset(1u, i);
+    for (var w = 0u; w < {words}u; w++) {{
+        dst[i * {words}u + w] = src[o * {words}u + w];
+    }}
+}}
+"#,
+            header = Self::header(),
+            wg = GPUHandle::WORKGROUP_SIZE,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+
+    fn arange(shape: Vec<usize>) -> Tensor<CPU> {
+        let n = shape.iter().product::<usize>();
+        Tensor::<CPU>::new(shape.into(), (0..n as u32).collect()).unwrap()
+    }
+
+    #[test]
+    fn views_share_storage() {
+        let t = arange(vec![2, 3]);
+        let tt = t.transpose(0, 1).unwrap();
+        assert!(std::sync::Arc::ptr_eq(t.storage(), tt.storage()));
+        assert!(!tt.is_contiguous());
+        assert_eq!(tt.to_vec::<u32>().unwrap(), vec![0, 3, 1, 4, 2, 5]);
+        assert!(tt.view(vec![6].into()).is_err());
+        let flat = tt.reshape(vec![6].into()).unwrap();
+        assert_eq!(flat.as_slice::<u32>().unwrap(), &[0, 3, 1, 4, 2, 5]);
+
+        let u = t.unsqueeze(1).unwrap();
+        assert_eq!(u.shape(), &Shape::from(vec![2, 1, 3]));
+        assert_eq!(u.squeeze(1).unwrap().shape(), t.shape());
+        assert!(u.is_contiguous());
+    }
+
+    #[test]
+    fn expand_broadcasts() {
+        let row = arange(vec![3]);
+        let e = row.expand(vec![2, 3].into()).unwrap();
+        assert_eq!(e.strides().as_slice(), &[0, 1]);
+        assert_eq!(e.to_vec::<u32>().unwrap(), vec![0, 1, 2, 0, 1, 2]);
+        assert!(row.expand(vec![2, 4].into()).is_err());
+
+        let p = arange(vec![2, 3, 4]).permute(&[2, 0, 1]).unwrap();
+        assert_eq!(p.shape(), &Shape::from(vec![4, 2, 3]));
+        assert_eq!(
+            p.contiguous().unwrap().to_vec::<u32>().unwrap()[0..3],
+            [0, 4, 8]
+        );
+        assert!(arange(vec![2, 3]).permute(&[0, 0]).is_err());
+    }
+
+    #[tokio::test]
+    async fn gpu_contiguous_matches_cpu() {
+        for dt in [DType::U8, DType::F16, DType::F32, DType::F64] {
+            let cpu = arange(vec![3, 5]).cast(dt).unwrap();
+            let expected = cpu.transpose(0, 1).unwrap().contiguous().unwrap();
+            let gpu = cpu.to(&Arc::new(WebGPU::new().await.unwrap())).unwrap();
+            let result = gpu.transpose(0, 1).unwrap().contiguous().unwrap();
+            assert_eq!(result.to(&Arc::new(CPU)).unwrap(), expected);
+        }
+    }
+}
@@ -1,5 +1,10 @@
-use crate::{BufferID, Device, DeviceAllocator};
-use crate::{DeviceError, DevicePrimitive};
+use crate::{copy_through_host, DeviceError, DeviceId, DevicePrimitive};
+use crate::{
+    AllocMode, BufferID, BufferPool, Device, DeviceAllocator, Graph, PipelineCache, PoolConfig,
+};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, OnceLock};
+use std::thread::JoinHandle;
 use wgpu::util::DeviceExt;
 use wgpu::InstanceDescriptor;
 use wgpu::Limits;
@@ -7,12 +12,24 @@ use wgpu::Limits;
 ///Encapsulates everything needed to interact with the GPU.
 #[derive(Debug)]
 pub struct GPUHandle {
-    device: wgpu::Device, //Responsible for the creation of compute resources.
-    queue: wgpu::Queue,   //Executes recorded CommandBuffers.
+    device: Arc<wgpu::Device>, //Responsible for the creation of compute resources.
+    queue: wgpu::Queue,        //Executes recorded CommandBuffers.
+    pool: BufferPool,          //Recycles freed buffers.
+    pipelines: PipelineCache,  //Compiled compute pipelines.
+    graph: Graph,              //Kernels recorded in lazy mode.
+    poller: OnceLock<Poller>,  //Started by the first asynchronous transfer.
+    id: DeviceId,
 }
 
 impl GPUHandle {
+    pub const WORKGROUP_SIZE: u32 = 64;
+    pub const MAX_WORKGROUPS: u32 = 65535;
+
     pub async fn new() -> Result<Self, DeviceError> {
+        Self::with_pool_config(PoolConfig::default()).await
+    }
+
+    pub async fn with_pool_config(config: PoolConfig) -> Result<Self, DeviceError> {
         let backends = wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::PRIMARY);
         let instance = wgpu::Instance::new(InstanceDescriptor {
             backends,
@@ -36,7 +53,19 @@ impl GPUHandle {
             .await
             .map_err(|e| DeviceError::ResourceError(anyhow::anyhow!(e)))?;
 
-        Ok(Self { device, queue })
+        Ok(Self {
+            device: Arc::new(device),
+            queue,
+            pool: BufferPool::new(config),
+            pipelines: PipelineCache::default(),
+            graph: Graph::default(),
+            poller: OnceLock::new(),
+            id: DeviceId::new("WebGPU"),
+        })
+    }
+
+    pub fn id(&self) -> DeviceId {
+        self.id
     }
 
     pub fn device(&self) -> &wgpu::Device {
@@ -46,17 +75,114 @@ impl GPUHandle {
     pub fn queue(&self) -> &wgpu::Queue {
         &self.queue
     }
+
+    pub fn pool(&self) -> &BufferPool {
+        &self.pool
+    }
+
+    pub fn pipelines(&self) -> &PipelineCache {
+        &self.pipelines
+    }
+
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    ///Wakes the background poller, so that callbacks registered on the device are eventually called.
+    pub(crate) fn poll_in_background(&self) {
+        self.poller
+            .get_or_init(|| Poller::spawn(self.device.clone()))
+            .wake();
+    }
+
+    ///Buffer sizes must be a multiple of [`wgpu::COPY_BUFFER_ALIGNMENT`] to be written to,
+    ///so e.g a tensor of 3 f16s occupies 8 bytes on the GPU.
+    pub fn padded_size(size: usize) -> u64 {
+        let align = wgpu::COPY_BUFFER_ALIGNMENT;
+        (size as u64).div_ceil(align) * align
+    }
+
+    ///Writes `src` at the start of `dst` through the queue.
+    ///The unaligned tail is written as a whole word, padded with zeroes.
+    pub(crate) fn write_padded(&self, dst: &wgpu::Buffer, src: &[u8]) {
+        let align = wgpu::COPY_BUFFER_ALIGNMENT as usize;
+        let (head, tail) = src.split_at(src.len() / align * align);
+        if !head.is_empty() {
+            self.queue.write_buffer(dst, 0, head);
+        }
+        if !tail.is_empty() {
+            let mut word = [0u8; wgpu::COPY_BUFFER_ALIGNMENT as usize];
+            word[..tail.len()].copy_from_slice(tail);
+            self.queue.write_buffer(dst, head.len() as u64, &word);
+        }
+    }
+}
+
+///The poller thread must be gone before the device is dropped.
+impl Drop for GPUHandle {
+    fn drop(&mut self) {
+        drop(self.poller.take());
+    }
+}
+
+///Polls a [`wgpu::Device`] on a background thread, so that futures waiting on its callbacks
+///make progress without blocking the executor.
+///Wakes are coalesced, the thread sleeps while no transfer is pending.
+#[derive(Debug)]
+struct Poller {
+    wake: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Poller {
+    fn spawn(device: Arc<wgpu::Device>) -> Self {
+        let (wake, rx) = std::sync::mpsc::channel::<()>();
+        let thread = std::thread::Builder::new()
+            .name("wgpu-poller".to_string())
+            .spawn(move || {
+                while rx.recv().is_ok() {
+                    while rx.try_recv().is_ok() {}
+                    device.poll(wgpu::Maintain::Wait);
+                }
+            })
+            .expect("failed to spawn the WebGPU poller thread");
+        Self {
+            wake: Some(wake),
+            thread: Some(thread),
+        }
+    }
+
+    fn wake(&self) {
+        if let Some(wake) = &self.wake {
+            let _ = wake.send(());
+        }
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        //Closing the channel stops the thread once it is done polling.
+        self.wake.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
 }
 
-///Allocator could be really smart here, and maintain a pool of buffers.
-///For now, we just create a new buffer for every allocation.
+///Freed buffers are returned to a [`BufferPool`], keyed by size and [`AllocMode`].
+///Allocations are served from the pool when possible, falling back to a new buffer.
+///Buffers still bound by a pending [`Graph`] node are only returned once it has been submitted.
 impl DeviceAllocator for GPUHandle {
     type Prim = wgpu::Buffer;
 
-    unsafe fn alloc(&self, layout: std::alloc::Layout, mode: crate::AllocMode) -> Self::Prim {
+    unsafe fn alloc(&self, layout: std::alloc::Layout, mode: AllocMode) -> Self::Prim {
+        let size = Self::padded_size(layout.size());
+        if let Some(buffer) = self.pool.acquire(size, mode) {
+            return buffer;
+        }
         self.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some(BufferID::new().inner()),
-            size: layout.size() as u64,
+            size,
             usage: mode.into(),
             mapped_at_creation: false,
         })
@@ -64,10 +190,17 @@ impl DeviceAllocator for GPUHandle {
 
     unsafe fn alloc_init(
         &self,
-        _layout: std::alloc::Layout,
+        layout: std::alloc::Layout,
         init: &[u8],
-        mode: crate::AllocMode,
+        mode: AllocMode,
     ) -> Self::Prim {
+        //Pooled buffers can only be reinitialized through the queue.
+        if mode.contains(AllocMode::COPY_DST) {
+            if let Some(buffer) = self.pool.acquire(Self::padded_size(layout.size()), mode) {
+                self.write_padded(&buffer, init);
+                return buffer;
+            }
+        }
         self.device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(BufferID::new().inner()),
@@ -76,8 +209,10 @@ impl DeviceAllocator for GPUHandle {
             })
     }
 
-    unsafe fn dealloc(&self, item: &mut Self::Prim, _layout: std::alloc::Layout) {
-        item.destroy()
+    unsafe fn dealloc(&self, item: Self::Prim, _layout: std::alloc::Layout) {
+        if let Some(item) = self.graph.defer_release(item) {
+            self.pool.release(item)
+        }
     }
 }
 
@@ -85,6 +220,10 @@ impl DevicePrimitive for wgpu::Buffer {
     fn len(&self) -> usize {
         self.size() as _
     }
+
+    fn as_wgpu_buffer(&self) -> Option<&wgpu::Buffer> {
+        Some(self)
+    }
 }
 
 #[derive(Debug)]
@@ -99,6 +238,12 @@ impl WebGPU {
         })
     }
 
+    pub async fn with_pool_config(config: PoolConfig) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            handle: GPUHandle::with_pool_config(config).await?,
+        })
+    }
+
     pub fn handle(&self) -> &GPUHandle {
         &self.handle
     }
@@ -108,7 +253,15 @@ impl Device for WebGPU {
     type Prim = wgpu::Buffer;
     type Allocator = GPUHandle;
 
+    fn id(&self) -> DeviceId {
+        self.handle.id()
+    }
+
     fn copy_to_host(&self, src: &Self::Prim, dst: &mut [u8]) -> Result<(), DeviceError> {
+        if dst.len() > src.len() {
+            return Err(DeviceError::CopyMismatch(src.len(), dst.len()));
+        }
+        self.handle.realize_target(Some(src.global_id()));
         let buffer_slice = src.slice(..);
         let (tx, rx) = std::sync::mpsc::sync_channel(1);
         let len = dst.len();
@@ -119,7 +272,7 @@ impl Device for WebGPU {
             &buffer_slice,
             move |buffer| {
                 tx.send(if let Ok(b) = buffer {
-                    Ok(unsafe { std::slice::from_raw_parts(b.as_ptr() as *const u8, len) })
+                    Ok(unsafe { std::slice::from_raw_parts(b.as_ptr(), len) })
                 } else {
                     Err(DeviceError::TransferError("WebGPU".to_string()))
                 })
@@ -134,25 +287,143 @@ impl Device for WebGPU {
         Ok(())
     }
 
+    ///Copies `src` to a staging buffer and awaits its mapping, which the background poller drives.
+    ///Dropping the future abandons the staging buffer, `src` itself is never mapped.
+    async fn copy_to_host_async(
+        &self,
+        src: &Self::Prim,
+        dst: &mut [u8],
+    ) -> Result<(), DeviceError> {
+        if dst.len() > src.len() {
+            return Err(DeviceError::CopyMismatch(src.len(), dst.len()));
+        }
+        let size = GPUHandle::padded_size(dst.len());
+        if size == 0 {
+            return Ok(());
+        }
+        self.handle.realize_target(Some(src.global_id()));
+        let staging = self.handle.device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder =
+            self.handle
+                .device()
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("copy_to_host"),
+                });
+        encoder.copy_buffer_to_buffer(src, 0, &staging, 0, size);
+        self.handle.queue().submit(Some(encoder.finish()));
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = tx.send(result);
+            });
+        self.handle.poll_in_background();
+        rx.await
+            .map_err(|_| DeviceError::TransferError("WebGPU".to_string()))?
+            .map_err(|_| DeviceError::TransferError("WebGPU".to_string()))?;
+        dst.copy_from_slice(&staging.slice(..).get_mapped_range()[..dst.len()]);
+        Ok(())
+    }
+
+    ///Writes `src` in place, only the trailing partial word is padded separately.
     fn copy_from_host(&self, src: &[u8], dst: &mut Self::Prim) -> Result<(), DeviceError> {
-        self.handle.queue().write_buffer(dst, 0, src);
+        let padded = GPUHandle::padded_size(src.len());
+        if padded > dst.size() {
+            return Err(DeviceError::CopyMismatch(src.len(), dst.len()));
+        }
+        self.handle.write_padded(dst, src);
+        Ok(())
+    }
+
+    ///Buffers of the same device are copied with a single `copy_buffer_to_buffer`,
+    ///once the pending writes to `src` are realized.
+    fn copy_to<Ext: Device>(
+        &self,
+        src: &Self::Prim,
+        dst: &mut Ext::Prim,
+        ext: &Ext,
+    ) -> Result<(), DeviceError> {
+        let Some(buffer) = dst.as_wgpu_buffer().filter(|_| ext.id() == self.id()) else {
+            return copy_through_host(self, src, dst, ext);
+        };
+        let size = src.size().min(buffer.size());
+        if size == 0 {
+            return Ok(());
+        }
+        self.handle.realize_target(Some(src.global_id()));
+        let mut encoder =
+            self.handle
+                .device()
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("copy_to"),
+                });
+        encoder.copy_buffer_to_buffer(src, 0, buffer, 0, size);
+        self.handle.queue().submit(Some(encoder.finish()));
         Ok(())
     }
 
     fn allocate(
         &self,
         layout: std::alloc::Layout,
-        mode: crate::AllocMode,
+        mode: AllocMode,
     ) -> Result<Self::Prim, DeviceError> {
         unsafe { Ok(self.handle.alloc(layout, mode)) }
     }
 
-    fn deallocate(
-        &self,
-        item: &mut Self::Prim,
-        layout: std::alloc::Layout,
-    ) -> Result<(), DeviceError> {
+    fn deallocate(&self, item: Self::Prim, layout: std::alloc::Layout) -> Result<(), DeviceError> {
         unsafe { self.handle.dealloc(item, layout) }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use half::f16;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn copies_between_buffers_of_a_device() {
+        let device = Arc::new(WebGPU::new().await.unwrap());
+        let cpu = Arc::new(CPU);
+        let data = vec![1f32, -2., 0.5, 4., 8.];
+        let host = Tensor::<CPU>::new(vec![5].into(), data.clone()).unwrap();
+        let gpu = host.clone().to(&device).unwrap();
+
+        //Pending writes to the source are realized before the copy.
+        device.handle().graph().set_lazy(true);
+        let neg = gpu.neg().unwrap();
+        let copy = neg.clone().to(&device).unwrap();
+        device.handle().graph().set_lazy(false);
+        assert!(!Arc::ptr_eq(copy.storage(), neg.storage()));
+        assert_eq!(copy.device_id(), gpu.device_id());
+        assert_eq!(copy.to(&cpu).unwrap(), host.neg().unwrap());
+
+        //The type-erased handle to the same device copies directly too.
+        let any = Arc::new(AnyDevice::from(device.clone()));
+        let erased = gpu.to(&any).unwrap().into_webgpu().unwrap();
+        assert_eq!(erased.to(&cpu).unwrap().to_vec::<f32>().unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn unaligned_uploads_are_padded() {
+        let device = Arc::new(WebGPU::new().await.unwrap());
+        let cpu = Arc::new(CPU);
+        for len in [1, 2, 3, 5] {
+            let data = (0..len)
+                .map(|i| f16::from_f32(i as f32 - 1.5))
+                .collect::<Vec<_>>();
+            let host = Tensor::<CPU>::new(vec![len].into(), data.clone()).unwrap();
+            let gpu = host.to(&device).unwrap();
+            assert_eq!(gpu.storage().data().size(), GPUHandle::padded_size(len * 2));
+            let copy = gpu.to(&device).unwrap();
+            assert_eq!(copy.to(&cpu).unwrap().to_vec::<f16>().unwrap(), data);
+        }
+    }
+}